@@ -17,8 +17,9 @@ function database-url {
 	echo ${CHROMA_DATABASE_URL}
 }
 
+# Each chat gets its own collection, see CHROMA_COLLECTION_NAME in the README
 function collection-name {
-	echo "blossom-embeddings"
+	echo '{chat_name}'
 }
 
 function run {
@@ -0,0 +1,16 @@
+CREATE TABLE messages (
+  id BLOB NOT NULL PRIMARY KEY DEFAULT (randomblob(16)),
+
+  chat_id BLOB NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+  position INTEGER NOT NULL,
+
+  role TEXT NOT NULL,
+  content TEXT NOT NULL,
+  tool_name TEXT,
+
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+  UNIQUE (chat_id, position)
+);
+
+CREATE INDEX idx_messages_chat_id ON messages(chat_id);
@@ -0,0 +1,11 @@
+CREATE TABLE embeddings (
+  collection TEXT NOT NULL,
+  id TEXT NOT NULL,
+
+  document TEXT NOT NULL,
+  embedding BLOB NOT NULL,
+
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+  PRIMARY KEY (collection, id)
+);
@@ -0,0 +1,19 @@
+CREATE TABLE attachments (
+  id BLOB NOT NULL PRIMARY KEY DEFAULT (randomblob(16)),
+
+  chat_id BLOB NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+  path TEXT NOT NULL,
+  content_hash TEXT NOT NULL,
+
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+  UNIQUE (chat_id, path)
+);
+
+CREATE TABLE attachment_chunks (
+  attachment_id BLOB NOT NULL REFERENCES attachments(id) ON DELETE CASCADE,
+  content_hash TEXT NOT NULL,
+  vector_id TEXT NOT NULL,
+
+  PRIMARY KEY (attachment_id, content_hash)
+);
@@ -0,0 +1 @@
+ALTER TABLE embeddings ADD COLUMN metadata TEXT;
@@ -0,0 +1,8 @@
+-- Keyword index over attached chunks, searched alongside their embeddings
+CREATE VIRTUAL TABLE chunks_fts USING fts5 (
+  collection UNINDEXED,
+  id UNINDEXED,
+  document,
+  metadata UNINDEXED,
+  tokenize = 'unicode61'
+);
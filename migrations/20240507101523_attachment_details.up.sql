@@ -0,0 +1,4 @@
+ALTER TABLE attachments ADD COLUMN size INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE attachments ADD COLUMN chunk_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE attachments ADD COLUMN embedding_model TEXT;
+ALTER TABLE attachments ADD COLUMN indexed_at TIMESTAMP;
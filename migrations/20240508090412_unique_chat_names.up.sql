@@ -0,0 +1,6 @@
+-- Tell apart any chats which already share a name, keeping the oldest as is
+UPDATE chats
+SET name = name || '-' || LOWER(HEX(SUBSTR(id, 1, 4)))
+WHERE rowid NOT IN (SELECT MIN(rowid) FROM chats GROUP BY name);
+
+CREATE UNIQUE INDEX idx_chats_name ON chats(name);
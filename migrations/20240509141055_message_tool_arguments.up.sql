@@ -0,0 +1,2 @@
+-- The arguments a tool was called with, as a JSON object of argument names to values
+ALTER TABLE messages ADD COLUMN tool_arguments TEXT;
@@ -0,0 +1,21 @@
+-- Messages form a tree so a chat can branch from any earlier message.
+-- Each chat points at the last message of the branch currently being followed.
+ALTER TABLE messages ADD COLUMN parent_id BLOB REFERENCES messages(id) ON DELETE CASCADE;
+ALTER TABLE chats ADD COLUMN head_id BLOB REFERENCES messages(id) ON DELETE SET NULL;
+
+-- Existing chats are a single branch in the order messages were written
+UPDATE messages
+SET parent_id = (
+  SELECT previous.id FROM messages previous
+  WHERE previous.chat_id = messages.chat_id AND previous.position = messages.position - 1
+);
+
+UPDATE chats
+SET head_id = (
+  SELECT id FROM messages
+  WHERE messages.chat_id = chats.id
+  ORDER BY position DESC
+  LIMIT 1
+);
+
+CREATE INDEX idx_messages_parent_id ON messages(parent_id);
@@ -0,0 +1,4 @@
+-- A rolling summary of the turns that no longer fit in the conversational model's context,
+-- along with the last message it covers so other branches can tell it doesn't apply to them
+ALTER TABLE chats ADD COLUMN summary TEXT;
+ALTER TABLE chats ADD COLUMN summary_through BLOB REFERENCES messages(id) ON DELETE SET NULL;
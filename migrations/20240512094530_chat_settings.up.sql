@@ -0,0 +1,13 @@
+-- Per-chat overrides for the models, persona and sampling options set in the environment
+CREATE TABLE chat_settings (
+  chat_id BLOB NOT NULL PRIMARY KEY REFERENCES chats(id) ON DELETE CASCADE,
+  system_prompt TEXT,
+  supervisor_model TEXT,
+  conversational_model TEXT,
+  image_model TEXT,
+  embedding_model TEXT,
+  temperature REAL,
+  top_p REAL,
+  num_ctx INTEGER,
+  seed INTEGER
+);
@@ -0,0 +1,16 @@
+-- How many dimensions the embeddings in each collection have, fixed by the first one stored
+CREATE TABLE embedding_collections (
+  collection TEXT NOT NULL PRIMARY KEY,
+  dimension INTEGER NOT NULL
+);
+
+-- Collections from before take the dimension of their most recent embedding
+INSERT INTO embedding_collections (collection, dimension)
+SELECT collection, length(embedding) / 4
+FROM embeddings AS latest
+WHERE rowid = (
+  SELECT rowid FROM embeddings
+  WHERE collection = latest.collection
+  ORDER BY created_at DESC, rowid DESC
+  LIMIT 1
+);
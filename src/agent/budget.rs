@@ -0,0 +1,152 @@
+use super::language_model::Message;
+use crate::chunking::count_tokens;
+
+/// The context length assumed for the conversational model unless configured otherwise,
+/// matching the default Ollama loads models with
+pub const DEFAULT_CONTEXT_LENGTH: usize = 2048;
+/// How many tokens are kept free for the model's response unless configured otherwise
+pub const DEFAULT_RESPONSE_TOKENS: usize = 512;
+/// How many tokens are set aside for retrieved documents unless configured otherwise,
+/// enough for a search's worth of chunks of the default size
+pub const DEFAULT_DOCUMENT_TOKENS: usize = 768;
+
+/// What each message costs on top of its content, for the role and separators
+/// the model's chat template wraps it in
+const MESSAGE_OVERHEAD: usize = 4;
+
+/// Estimate how many tokens a message takes up in a prompt
+pub fn message_tokens(message: &Message) -> usize {
+    MESSAGE_OVERHEAD + count_tokens(message.content())
+}
+
+/// Splits a model's context window between the parts of a conversational prompt.
+/// The system prompt, retrieved documents and the response are accounted for first,
+/// and the earlier turns of the conversation get whatever is left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenBudget {
+    context_length: usize,
+    response_tokens: usize,
+    document_tokens: usize,
+}
+
+impl Default for TokenBudget {
+    fn default() -> Self {
+        Self::new(DEFAULT_CONTEXT_LENGTH)
+    }
+}
+
+impl TokenBudget {
+    pub fn new(context_length: usize) -> Self {
+        Self {
+            context_length,
+            response_tokens: DEFAULT_RESPONSE_TOKENS,
+            document_tokens: DEFAULT_DOCUMENT_TOKENS,
+        }
+    }
+
+    pub fn with_context_length(mut self, context_length: usize) -> Self {
+        self.context_length = context_length;
+        self
+    }
+
+    pub fn with_response_tokens(mut self, response_tokens: usize) -> Self {
+        self.response_tokens = response_tokens;
+        self
+    }
+
+    pub fn with_document_tokens(mut self, document_tokens: usize) -> Self {
+        self.document_tokens = document_tokens;
+        self
+    }
+
+    pub fn context_length(&self) -> usize {
+        self.context_length
+    }
+
+    pub fn response_tokens(&self) -> usize {
+        self.response_tokens
+    }
+
+    pub fn document_tokens(&self) -> usize {
+        self.document_tokens
+    }
+
+    /// Tokens left for earlier turns once the system prompt, documents and response are accounted for
+    pub fn history_tokens(&self, system_prompt: &str) -> usize {
+        self.context_length
+            .saturating_sub(self.response_tokens)
+            .saturating_sub(self.document_tokens)
+            .saturating_sub(message_tokens(&Message::system(system_prompt)))
+    }
+
+    /// How many of the oldest messages have to go for the rest to fit in `available` tokens
+    pub fn overflow(messages: &[Message], available: usize) -> usize {
+        let mut total: usize = messages.iter().map(message_tokens).sum();
+        let mut count = 0;
+        while total > available && count < messages.len() {
+            total -= message_tokens(&messages[count]);
+            count += 1;
+        }
+        count
+    }
+}
+
+/// How a conversational prompt uses up the model's context window, in tokens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextUsage {
+    pub context_length: usize,
+    /// The system prompt, including any summary of older turns
+    pub system: usize,
+    /// The part of the system prompt taken up by the summary
+    pub summary: usize,
+    /// The earlier turns sent along in full
+    pub history: usize,
+    /// How many earlier messages are sent along in full
+    pub messages: usize,
+    /// Set aside for retrieved documents
+    pub documents: usize,
+    /// Kept free for the response
+    pub response: usize,
+}
+
+impl ContextUsage {
+    /// Tokens spoken for, including those set aside
+    pub fn used(&self) -> usize {
+        self.system + self.history + self.documents + self.response
+    }
+
+    /// Tokens left for the next input
+    pub fn free(&self) -> usize {
+        self.context_length.saturating_sub(self.used())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_history_tokens() {
+        let budget = TokenBudget::new(1000)
+            .with_response_tokens(100)
+            .with_document_tokens(200);
+        let system = message_tokens(&Message::system("Be helpful"));
+        assert_eq!(budget.history_tokens("Be helpful"), 700 - system);
+        assert_eq!(TokenBudget::new(10).history_tokens("Be helpful"), 0);
+    }
+
+    #[test]
+    fn test_overflow() {
+        let messages = vec![
+            Message::user("one two three"),
+            Message::assistant("four five"),
+            Message::user("six"),
+        ];
+        let tokens = messages.iter().map(message_tokens).collect::<Vec<_>>();
+        let total: usize = tokens.iter().sum();
+        assert_eq!(TokenBudget::overflow(&messages, total), 0);
+        assert_eq!(TokenBudget::overflow(&messages, total - 1), 1);
+        assert_eq!(TokenBudget::overflow(&messages, tokens[2]), 2);
+        assert_eq!(TokenBudget::overflow(&messages, 0), 3);
+    }
+}
@@ -1,8 +1,54 @@
 use std::path::PathBuf;
 
 pub enum Command {
-    Chat { message: String },
-    Attach { paths: Vec<PathBuf> },
+    Chat {
+        message: String,
+    },
+    Attach {
+        paths: Vec<PathBuf>,
+    },
+    /// List the files attached to the chat
+    Attachments,
+    /// Remove an attached file and everything indexed from it
+    Detach {
+        path: PathBuf,
+    },
+    /// Show what was indexed from an attached file
+    Inspect {
+        path: PathBuf,
+    },
+    /// Show the full text of a source cited by the last answer, numbered from 1
+    Source {
+        index: Option<usize>,
+    },
+    /// Generate a new answer to a user turn on the current branch, numbered from 1
+    /// and defaulting to the last, keeping the old answer on its own branch
+    Retry {
+        turn: Option<usize>,
+    },
+    /// Replace a user turn on the current branch with a new message, on a new branch
+    Edit {
+        turn: Option<usize>,
+        message: String,
+    },
+    /// Copy the current branch into a new chat
+    Fork {
+        name: Option<String>,
+    },
+    /// List the branches of the chat by their last message
+    Branches,
+    /// Show how the conversational model's context window is being used
+    Context,
+    /// Override a model, the system prompt or a sampling option for this chat,
+    /// or show the current settings without a name
+    Set {
+        setting: Option<String>,
+        value: String,
+    },
+    /// Follow another branch of the chat, numbered as in `/branches`
+    Switch {
+        branch: Option<usize>,
+    },
     Exit,
 }
 
@@ -19,6 +65,60 @@ impl From<&str> for Command {
                     .map(PathBuf::from)
                     .collect(),
             },
+            "/attachments" => Command::Attachments,
+            "/detach" => Command::Detach {
+                path: PathBuf::from(rest(value)),
+            },
+            "/inspect" => Command::Inspect {
+                path: PathBuf::from(rest(value)),
+            },
+            "/source" => Command::Source {
+                index: value
+                    .split_whitespace()
+                    .nth(1)
+                    .and_then(|index| index.parse().ok()),
+            },
+            "/retry" => Command::Retry {
+                turn: rest(value).parse().ok(),
+            },
+            "/edit" => {
+                let rest = rest(value);
+                match rest.split_once(char::is_whitespace) {
+                    Some((turn, message)) if turn.parse::<usize>().is_ok() => Command::Edit {
+                        turn: turn.parse().ok(),
+                        message: message.trim().to_string(),
+                    },
+                    // A turn without a message, which is left empty to get the usage
+                    None if rest.parse::<usize>().is_ok() => Command::Edit {
+                        turn: rest.parse().ok(),
+                        message: String::new(),
+                    },
+                    _ => Command::Edit {
+                        turn: None,
+                        message: rest.to_string(),
+                    },
+                }
+            }
+            "/fork" => Command::Fork {
+                name: Some(rest(value))
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string),
+            },
+            "/branches" => Command::Branches,
+            "/context" => Command::Context,
+            "/switch" => Command::Switch {
+                branch: rest(value).parse().ok(),
+            },
+            "/set" => {
+                let rest = rest(value);
+                let (setting, value) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                Command::Set {
+                    setting: Some(setting)
+                        .filter(|setting| !setting.is_empty())
+                        .map(str::to_lowercase),
+                    value: value.trim().to_string(),
+                }
+            }
             "/exit" => Command::Exit,
             "exit" => Command::Exit,
             "bye" => Command::Exit,
@@ -28,3 +128,38 @@ impl From<&str> for Command {
         }
     }
 }
+
+/// Everything after the command, so paths may contain spaces
+fn rest(value: &str) -> &str {
+    value
+        .trim_start()
+        .split_once(char::is_whitespace)
+        .map(|(_, rest)| rest.trim())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn edit(input: &str) -> (Option<usize>, String) {
+        match Command::from(input) {
+            Command::Edit { turn, message } => (turn, message),
+            _ => panic!("not an edit: {}", input),
+        }
+    }
+
+    #[test]
+    fn test_edit() {
+        assert_eq!(
+            edit("/edit 2 pink blossoms"),
+            (Some(2), "pink blossoms".to_string())
+        );
+        assert_eq!(
+            edit("/edit pink blossoms"),
+            (None, "pink blossoms".to_string())
+        );
+        assert_eq!(edit("/edit 3"), (Some(3), String::new()));
+        assert_eq!(edit("/edit"), (None, String::new()));
+    }
+}
@@ -0,0 +1,148 @@
+mod ollama;
+mod openai;
+
+use std::pin::Pin;
+
+use async_trait::async_trait;
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+
+pub use ollama::OllamaBackend;
+pub use openai::OpenAiBackend;
+
+/// Who authored a message within a chat
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+/// A single message within a chat with a language model
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    role: Role,
+    content: String,
+}
+
+impl Message {
+    pub fn new(role: Role, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+        }
+    }
+
+    pub fn system(content: impl Into<String>) -> Self {
+        Self::new(Role::System, content)
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self::new(Role::User, content)
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self::new(Role::Assistant, content)
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+}
+
+/// A backend specific encoding of a conversation returned after a completion,
+/// which can be sent along with the next completion to keep a conversational memory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Context(Vec<i32>);
+
+impl From<Vec<i32>> for Context {
+    fn from(tokens: Vec<i32>) -> Self {
+        Self(tokens)
+    }
+}
+
+/// How a model samples its response, left to the model's own defaults where unset
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GenerationOptions {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    /// The size of the context window, in tokens
+    pub num_ctx: Option<u32>,
+    pub seed: Option<i32>,
+}
+
+/// A piece of a streamed completion
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Completion {
+    /// The text generated since the last piece
+    pub text: String,
+    /// The context of the conversation, only present on the final piece
+    pub context: Option<Context>,
+}
+
+/// A stream of completion pieces, in the order they were generated
+pub type CompletionStream =
+    Pin<Box<dyn Stream<Item = Result<Completion, LanguageModelError>> + Send>>;
+
+/// The operations blossom needs from an inference server.
+/// Every call names the model it should be run against.
+#[async_trait]
+pub trait LanguageModel: Send + Sync {
+    /// Respond to a conversation with a single message
+    async fn chat(
+        &self,
+        model: &str,
+        messages: &[Message],
+        options: &GenerationOptions,
+    ) -> Result<String, LanguageModelError>;
+
+    /// Stream a response to a conversation
+    async fn chat_stream(
+        &self,
+        model: &str,
+        messages: &[Message],
+        options: &GenerationOptions,
+    ) -> Result<CompletionStream, LanguageModelError>;
+
+    /// Stream a completion of the given prompt, continuing from `context` if given
+    async fn complete(
+        &self,
+        model: &str,
+        prompt: &str,
+        context: Option<Context>,
+        options: &GenerationOptions,
+    ) -> Result<CompletionStream, LanguageModelError>;
+
+    /// Embed the input into a vector
+    async fn embed(&self, model: &str, input: &str) -> Result<Vec<f64>, LanguageModelError>;
+
+    /// Stream a completion of the given prompt about a set of base64 encoded images
+    async fn vision(
+        &self,
+        model: &str,
+        prompt: &str,
+        images: &[String],
+    ) -> Result<CompletionStream, LanguageModelError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LanguageModelError {
+    #[error("ollama error: {0}")]
+    Ollama(#[from] ollama_rs::error::OllamaError),
+    #[error("http error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("server responded with status {0}: {1}")]
+    Status(u16, String),
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("no message in response")]
+    NoMessage,
+    #[error("stream error: {0}")]
+    Stream(String),
+    #[error("invalid url: {0}")]
+    InvalidUrl(#[from] url::ParseError),
+}
@@ -0,0 +1,319 @@
+use async_trait::async_trait;
+use futures::{stream, StreamExt};
+use ollama_rs::{
+    generation::{
+        chat::{request::ChatMessageRequest, ChatMessage, ChatMessageResponse, MessageRole},
+        completion::{
+            request::GenerationRequest, GenerationContext, GenerationResponse,
+            GenerationResponseStream,
+        },
+        images::Image,
+        options::GenerationOptions as OllamaGenerationOptions,
+    },
+    Ollama,
+};
+use url::Url;
+
+use super::{
+    Completion, CompletionStream, Context, GenerationOptions, LanguageModel, LanguageModelError,
+    Message, Role,
+};
+
+/// Talks to an Ollama server over its native API
+#[derive(Debug, Clone)]
+pub struct OllamaBackend {
+    ollama: Ollama,
+}
+
+impl OllamaBackend {
+    pub fn new(url: &Url) -> Self {
+        let scheme = url.scheme();
+        let host = url.host_str().unwrap_or("localhost");
+        let port = url.port().unwrap_or(11434);
+        let host = format!("{}://{}", scheme, host);
+        Self {
+            ollama: Ollama::new(host, port),
+        }
+    }
+}
+
+#[async_trait]
+impl LanguageModel for OllamaBackend {
+    async fn chat(
+        &self,
+        model: &str,
+        messages: &[Message],
+        options: &GenerationOptions,
+    ) -> Result<String, LanguageModelError> {
+        let request = ChatMessageRequest::new(model.to_string(), chat_messages(messages))
+            .options(ollama_options(options));
+
+        let chat_message_response = self.ollama.send_chat_messages(request).await?;
+        match chat_message_response.message {
+            None => Err(LanguageModelError::NoMessage),
+            Some(response) => Ok(response.content),
+        }
+    }
+
+    async fn chat_stream(
+        &self,
+        model: &str,
+        messages: &[Message],
+        options: &GenerationOptions,
+    ) -> Result<CompletionStream, LanguageModelError> {
+        let request = ChatMessageRequest::new(model.to_string(), chat_messages(messages))
+            .options(ollama_options(options));
+        let stream = self.ollama.send_chat_messages_stream(request).await?;
+        Ok(Box::pin(stream.map(|response| match response {
+            Ok(response) => Ok(chat_completion(response)),
+            Err(()) => Err(LanguageModelError::Stream(
+                "failed to read a chat response".to_string(),
+            )),
+        })))
+    }
+
+    async fn complete(
+        &self,
+        model: &str,
+        prompt: &str,
+        context: Option<Context>,
+        options: &GenerationOptions,
+    ) -> Result<CompletionStream, LanguageModelError> {
+        let mut request = GenerationRequest::new(model.to_string(), prompt.to_string())
+            .options(ollama_options(options));
+        if let Some(context) = context {
+            request = request.context(into_generation_context(context));
+        }
+        let stream = self.ollama.generate_stream(request).await?;
+        Ok(completion_stream(stream))
+    }
+
+    async fn embed(&self, model: &str, input: &str) -> Result<Vec<f64>, LanguageModelError> {
+        let response = self
+            .ollama
+            .generate_embeddings(model.to_string(), input.to_string(), None)
+            .await?;
+        Ok(response.embeddings)
+    }
+
+    async fn vision(
+        &self,
+        model: &str,
+        prompt: &str,
+        images: &[String],
+    ) -> Result<CompletionStream, LanguageModelError> {
+        let images = images
+            .iter()
+            .map(|image| Image::from_base64(image))
+            .collect();
+        let request = GenerationRequest::new(model.to_string(), prompt.to_string()).images(images);
+        let stream = self.ollama.generate_stream(request).await?;
+        Ok(completion_stream(stream))
+    }
+}
+
+fn ollama_options(options: &GenerationOptions) -> OllamaGenerationOptions {
+    let mut ollama_options = OllamaGenerationOptions::default();
+    if let Some(temperature) = options.temperature {
+        ollama_options = ollama_options.temperature(temperature);
+    }
+    if let Some(top_p) = options.top_p {
+        ollama_options = ollama_options.top_p(top_p);
+    }
+    if let Some(num_ctx) = options.num_ctx {
+        ollama_options = ollama_options.num_ctx(num_ctx);
+    }
+    if let Some(seed) = options.seed {
+        ollama_options = ollama_options.seed(seed);
+    }
+    ollama_options
+}
+
+fn chat_messages(messages: &[Message]) -> Vec<ChatMessage> {
+    messages
+        .iter()
+        .map(|message| {
+            let role = match message.role() {
+                Role::System => MessageRole::System,
+                Role::User => MessageRole::User,
+                Role::Assistant => MessageRole::Assistant,
+            };
+            ChatMessage::new(role, message.content().to_string())
+        })
+        .collect()
+}
+
+/// The chat API carries the conversation in its messages, so never hands back a context
+fn chat_completion(response: ChatMessageResponse) -> Completion {
+    Completion {
+        text: response
+            .message
+            .map(|message| message.content)
+            .unwrap_or_default(),
+        context: None,
+    }
+}
+
+/// Flatten Ollama's batched generation stream into a stream of completion pieces
+fn completion_stream(stream: GenerationResponseStream) -> CompletionStream {
+    let stream = stream.flat_map(|batch| {
+        let pieces = match batch {
+            Ok(responses) => responses.into_iter().map(into_completion).collect(),
+            Err(e) => vec![Err(LanguageModelError::Stream(e.to_string()))],
+        };
+        stream::iter(pieces)
+    });
+    Box::pin(stream)
+}
+
+fn into_completion(response: GenerationResponse) -> Result<Completion, LanguageModelError> {
+    let context = match response.final_data {
+        Some(final_data) => Some(from_generation_context(final_data.context)?),
+        None => None,
+    };
+    Ok(Completion {
+        text: response.response,
+        context,
+    })
+}
+
+// Ollama keeps the tokens of its context private, so convert through their serialized form
+
+fn from_generation_context(context: GenerationContext) -> Result<Context, LanguageModelError> {
+    serde_json::to_value(context)
+        .and_then(serde_json::from_value)
+        .map_err(|e| LanguageModelError::Stream(e.to_string()))
+}
+
+fn into_generation_context(context: Context) -> GenerationContext {
+    serde_json::to_value(context)
+        .and_then(serde_json::from_value)
+        .expect("contexts share a serialized form")
+}
+
+#[cfg(test)]
+mod test {
+    use futures::TryStreamExt;
+
+    use crate::tests::prelude::*;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_chat() {
+        let server = FakeOllama::start().await;
+        server.chat_response("<tool-call name=\"converse\"/>");
+        let backend = OllamaBackend::new(server.url());
+
+        let messages = vec![Message::system("be helpful"), Message::user("hello")];
+        let options = GenerationOptions {
+            temperature: Some(0.5),
+            seed: Some(7),
+            ..Default::default()
+        };
+        let response = backend
+            .chat("supervisor", &messages, &options)
+            .await
+            .unwrap();
+        assert_eq!(response, "<tool-call name=\"converse\"/>");
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        let (path, body) = &requests[0];
+        assert_eq!(path, "/api/chat");
+        assert_eq!(body["model"], "supervisor");
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["messages"][1]["role"], "user");
+        assert_eq!(body["messages"][1]["content"], "hello");
+        assert_eq!(body["options"]["temperature"], 0.5);
+        assert_eq!(body["options"]["seed"], 7);
+        assert!(body["options"]["top_p"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream() {
+        let server = FakeOllama::start().await;
+        server.chat_response("Hello there");
+        let backend = OllamaBackend::new(server.url());
+
+        let messages = vec![Message::user("hi"), Message::assistant("hello")];
+        let stream = backend
+            .chat_stream("conversational", &messages, &GenerationOptions::default())
+            .await
+            .unwrap();
+        let completions = stream.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(
+            completions,
+            vec![Completion {
+                text: "Hello there".to_string(),
+                context: None,
+            }]
+        );
+
+        let (path, body) = &server.requests()[0];
+        assert_eq!(path, "/api/chat");
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["messages"][1]["role"], "assistant");
+        assert_eq!(body["messages"][1]["content"], "hello");
+    }
+
+    #[tokio::test]
+    async fn test_complete() {
+        let server = FakeOllama::start().await;
+        server.generate_response("Hello there friend");
+        let backend = OllamaBackend::new(server.url());
+
+        let stream = backend
+            .complete("conversational", "hi", None, &GenerationOptions::default())
+            .await
+            .unwrap();
+        let completions = stream.try_collect::<Vec<_>>().await.unwrap();
+        let text = completions
+            .iter()
+            .map(|completion| completion.text.as_str())
+            .collect::<String>();
+        assert_eq!(text, "Hello there friend");
+        assert_eq!(
+            completions.last().unwrap().context,
+            Some(Context::from(vec![1]))
+        );
+
+        let (path, body) = &server.requests()[0];
+        assert_eq!(path, "/api/generate");
+        assert_eq!(body["model"], "conversational");
+        assert_eq!(body["prompt"], "hi");
+        assert!(body["context"].is_null());
+
+        // The context of the last completion is sent along with the next
+        let stream = backend
+            .complete(
+                "conversational",
+                "and then?",
+                Some(Context::from(vec![1])),
+                &GenerationOptions::default(),
+            )
+            .await
+            .unwrap();
+        stream.try_collect::<Vec<_>>().await.unwrap();
+        let (_, body) = &server.requests()[1];
+        assert_eq!(body["context"], serde_json::json!([1]));
+    }
+
+    #[tokio::test]
+    async fn test_embed() {
+        let server = FakeOllama::start().await;
+        let backend = OllamaBackend::new(server.url());
+
+        let embedding = backend.embed("embedding", "some text").await.unwrap();
+        assert_eq!(embedding.len(), MOCK_EMBEDDING_DIMENSIONS);
+        assert_eq!(
+            embedding,
+            backend.embed("embedding", "some text").await.unwrap()
+        );
+
+        let (path, body) = &server.requests()[0];
+        assert_eq!(path, "/api/embeddings");
+        assert_eq!(body["model"], "embedding");
+        assert_eq!(body["prompt"], "some text");
+    }
+}
@@ -0,0 +1,359 @@
+use async_trait::async_trait;
+use futures::{future, stream, StreamExt};
+use reqwest::{Client, RequestBuilder, Response};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use url::Url;
+
+use super::{
+    Completion, CompletionStream, Context, GenerationOptions, LanguageModel, LanguageModelError,
+    Message, Role,
+};
+
+/// Talks to any server implementing the OpenAI `/v1/chat/completions`
+/// and `/v1/embeddings` protocol, such as llama.cpp, vLLM or LM Studio
+#[derive(Debug, Clone)]
+pub struct OpenAiBackend {
+    client: Client,
+    base_url: Url,
+    api_key: Option<String>,
+}
+
+impl OpenAiBackend {
+    pub fn new(base_url: &Url, api_key: Option<String>) -> Self {
+        // Make sure joined paths land under the base path rather than replacing it
+        let mut base_url = base_url.clone();
+        if !base_url.path().ends_with('/') {
+            base_url.set_path(&format!("{}/", base_url.path()));
+        }
+        Self {
+            client: Client::new(),
+            base_url,
+            api_key,
+        }
+    }
+
+    fn post(&self, path: &str) -> Result<RequestBuilder, LanguageModelError> {
+        let url = self.base_url.join(path)?;
+        let request = self.client.post(url);
+        Ok(match &self.api_key {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
+        })
+    }
+
+    async fn send(&self, path: &str, body: Value) -> Result<Response, LanguageModelError> {
+        let response = self.post(path)?.json(&body).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(LanguageModelError::Status(status.as_u16(), message));
+        }
+        Ok(response)
+    }
+
+    async fn send_stream(
+        &self,
+        model: &str,
+        messages: Vec<Value>,
+        options: &GenerationOptions,
+    ) -> Result<CompletionStream, LanguageModelError> {
+        let mut body = json!({
+            "model": model,
+            "messages": messages,
+            "stream": true,
+        });
+        add_options(&mut body, options);
+        let response = self.send("chat/completions", body).await?;
+        Ok(completion_stream(response))
+    }
+}
+
+#[async_trait]
+impl LanguageModel for OpenAiBackend {
+    async fn chat(
+        &self,
+        model: &str,
+        messages: &[Message],
+        options: &GenerationOptions,
+    ) -> Result<String, LanguageModelError> {
+        let messages = chat_messages(messages);
+        let mut body = json!({
+            "model": model,
+            "messages": messages,
+            "stream": false,
+        });
+        add_options(&mut body, options);
+        let response = self
+            .send("chat/completions", body)
+            .await?
+            .json::<ChatCompletion>()
+            .await?;
+        response
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .ok_or(LanguageModelError::NoMessage)
+    }
+
+    async fn chat_stream(
+        &self,
+        model: &str,
+        messages: &[Message],
+        options: &GenerationOptions,
+    ) -> Result<CompletionStream, LanguageModelError> {
+        self.send_stream(model, chat_messages(messages), options)
+            .await
+    }
+
+    async fn complete(
+        &self,
+        model: &str,
+        prompt: &str,
+        // OpenAI compatible servers don't hand back a conversation context
+        _context: Option<Context>,
+        options: &GenerationOptions,
+    ) -> Result<CompletionStream, LanguageModelError> {
+        let messages = vec![json!({ "role": "user", "content": prompt })];
+        self.send_stream(model, messages, options).await
+    }
+
+    async fn embed(&self, model: &str, input: &str) -> Result<Vec<f64>, LanguageModelError> {
+        let body = json!({
+            "model": model,
+            "input": input,
+        });
+        let response = self
+            .send("embeddings", body)
+            .await?
+            .json::<Embeddings>()
+            .await?;
+        response
+            .data
+            .into_iter()
+            .next()
+            .map(|embedding| embedding.embedding)
+            .ok_or(LanguageModelError::NoMessage)
+    }
+
+    async fn vision(
+        &self,
+        model: &str,
+        prompt: &str,
+        images: &[String],
+    ) -> Result<CompletionStream, LanguageModelError> {
+        let mut content = vec![json!({ "type": "text", "text": prompt })];
+        for image in images {
+            content.push(json!({
+                "type": "image_url",
+                "image_url": { "url": format!("data:image/png;base64,{}", image) },
+            }));
+        }
+        let messages = vec![json!({ "role": "user", "content": content })];
+        self.send_stream(model, messages, &GenerationOptions::default())
+            .await
+    }
+}
+
+/// Set whichever sampling options were given on a request body.
+/// There's no standard way to size the context window, so `num_ctx` is left to the server.
+fn add_options(body: &mut Value, options: &GenerationOptions) {
+    if let Some(temperature) = options.temperature {
+        body["temperature"] = json!(temperature);
+    }
+    if let Some(top_p) = options.top_p {
+        body["top_p"] = json!(top_p);
+    }
+    if let Some(seed) = options.seed {
+        body["seed"] = json!(seed);
+    }
+}
+
+fn chat_messages(messages: &[Message]) -> Vec<Value> {
+    messages
+        .iter()
+        .map(|message| json!({ "role": role(message.role()), "content": message.content() }))
+        .collect()
+}
+
+fn role(role: Role) -> &'static str {
+    match role {
+        Role::System => "system",
+        Role::User => "user",
+        Role::Assistant => "assistant",
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletion {
+    choices: Vec<ChatCompletionChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionChoice {
+    message: ChatCompletionMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionMessage {
+    content: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionChunk {
+    choices: Vec<ChatCompletionChunkChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionChunkChoice {
+    delta: ChatCompletionMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct Embeddings {
+    data: Vec<Embedding>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Embedding {
+    embedding: Vec<f64>,
+}
+
+/// Incrementally splits a server-sent event stream into the payloads of its `data:` fields
+#[derive(Debug, Default)]
+struct SseDecoder {
+    buffer: Vec<u8>,
+}
+
+impl SseDecoder {
+    /// Feed the decoder more bytes, returning the payloads of any events they completed.
+    /// Bytes are only decoded once their event is complete, so characters split
+    /// across reads come through whole.
+    fn decode(&mut self, bytes: &[u8]) -> Vec<String> {
+        // Carriage returns only ever end lines, and never appear within a character
+        self.buffer
+            .extend(bytes.iter().copied().filter(|byte| *byte != b'\r'));
+        let mut payloads = Vec::new();
+        while let Some(end) = self.buffer.windows(2).position(|pair| pair == b"\n\n") {
+            let event = String::from_utf8_lossy(&self.buffer[..end]).into_owned();
+            self.buffer.drain(..end + 2);
+            let data = event
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                .collect::<Vec<_>>()
+                .join("\n");
+            if !data.is_empty() {
+                payloads.push(data);
+            }
+        }
+        payloads
+    }
+}
+
+/// Turn a streamed chat completion response into a stream of completion pieces
+fn completion_stream(response: Response) -> CompletionStream {
+    let stream = response
+        .bytes_stream()
+        .scan(SseDecoder::default(), |decoder, bytes| {
+            let pieces = match bytes {
+                Ok(bytes) => decoder
+                    .decode(&bytes)
+                    .into_iter()
+                    .filter(|payload| payload != "[DONE]")
+                    .map(|payload| into_completion(&payload))
+                    .collect(),
+                Err(e) => vec![Err(LanguageModelError::Http(e))],
+            };
+            future::ready(Some(pieces))
+        })
+        .flat_map(stream::iter);
+    Box::pin(stream)
+}
+
+fn into_completion(payload: &str) -> Result<Completion, LanguageModelError> {
+    let chunk = serde_json::from_str::<ChatCompletionChunk>(payload)?;
+    let text = chunk
+        .choices
+        .into_iter()
+        .filter_map(|choice| choice.delta.content)
+        .collect::<String>();
+    Ok(Completion {
+        text,
+        context: None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sse_decoder() {
+        let mut decoder = SseDecoder::default();
+        assert!(decoder.decode(b"data: {\"a\":").is_empty());
+        assert_eq!(
+            decoder.decode(b" 1}\n\ndata: [DONE]\r\n\r\n"),
+            vec!["{\"a\": 1}".to_string(), "[DONE]".to_string()]
+        );
+        assert_eq!(
+            decoder.decode(b": keep-alive\n\ndata:x\ndata:y\n\n"),
+            vec!["x\ny".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_sse_decoder_split_character() {
+        let mut decoder = SseDecoder::default();
+        let event = "data: 🌸\n\n".as_bytes();
+        // Split the four bytes of the blossom down the middle
+        assert!(decoder.decode(&event[..8]).is_empty());
+        assert_eq!(decoder.decode(&event[8..]), vec!["🌸".to_string()]);
+    }
+
+    #[test]
+    fn test_into_completion() {
+        let payload = r#"{"id":"1","choices":[{"index":0,"delta":{"content":"Hello"}}]}"#;
+        let completion = into_completion(payload).unwrap();
+        assert_eq!(completion.text, "Hello");
+        assert_eq!(completion.context, None);
+
+        let payload = r#"{"id":"1","choices":[{"index":0,"delta":{},"finish_reason":"stop"}]}"#;
+        assert_eq!(into_completion(payload).unwrap().text, "");
+    }
+
+    #[test]
+    fn test_add_options() {
+        let mut body = json!({ "model": "a" });
+        add_options(&mut body, &GenerationOptions::default());
+        assert_eq!(body, json!({ "model": "a" }));
+
+        let options = GenerationOptions {
+            temperature: Some(0.5),
+            num_ctx: Some(4096),
+            seed: Some(7),
+            ..Default::default()
+        };
+        add_options(&mut body, &options);
+        assert_eq!(body, json!({ "model": "a", "temperature": 0.5, "seed": 7 }));
+    }
+
+    #[test]
+    fn test_base_url() {
+        let backend = OpenAiBackend::new(&Url::parse("http://localhost:8080/v1").unwrap(), None);
+        assert_eq!(
+            backend.base_url.join("chat/completions").unwrap().as_str(),
+            "http://localhost:8080/v1/chat/completions"
+        );
+    }
+
+    #[test]
+    fn test_post_invalid_url() {
+        let backend = OpenAiBackend::new(&Url::parse("http://localhost:8080/v1").unwrap(), None);
+        assert!(matches!(
+            backend.post("http://[::1"),
+            Err(LanguageModelError::InvalidUrl(_))
+        ));
+    }
+}
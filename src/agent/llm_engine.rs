@@ -1,161 +1,794 @@
 use std::io::Cursor;
-use std::ops::Deref;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use base64::prelude::*;
-use futures::StreamExt;
 use image::{io::Reader as ImageReader, ImageFormat};
-use ollama_rs::{
-    generation::{
-        chat::{request::ChatMessageRequest, ChatMessage, MessageRole},
-        completion::{request::GenerationRequest, GenerationContext, GenerationResponseStream},
-        images::Image,
-        options::GenerationOptions,
-    },
-    Ollama,
+
+use super::budget::{message_tokens, ContextUsage, TokenBudget};
+use super::language_model::{
+    CompletionStream, Context, GenerationOptions, LanguageModel, LanguageModelError, Message, Role,
 };
-use url::Url;
+use super::prompts::{Prompt, Prompts, Template, Variables};
+use super::supervisor::DEFAULT_TOOL_CALL_REPAIRS;
+use crate::chunking::count_tokens;
+use crate::vector_store::Passage;
 
-use super::tool_call::ToolCall;
+/// How the conversational model is reminded of the earlier turns of a conversation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConversationMemory {
+    /// Send the earlier turns along as messages to the chat API
+    #[default]
+    History,
+    /// Send back the token context the backend handed out with its last completion.
+    /// Only Ollama's generate API supports this, and it can't survive a restart.
+    Context,
+}
 
-lazy_static::lazy_static! {
-    static ref SUPERVISOR_SYSTEM_PROMPT: String = include_str!("../../supervisor.txt").to_string();
-    static ref CONVERSATIONAL_SYSTEM_PROMPT: String = include_str!("../../conversational.txt").to_string();
+/// The turns of a conversation with the conversational model so far,
+/// with the oldest turns folded into a summary once they no longer fit
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Conversation {
+    summary: Option<String>,
+    messages: Vec<Message>,
+    context: Option<Context>,
 }
 
-#[derive(Debug, Clone)]
+impl Conversation {
+    /// Pick up a conversation from its earlier turns
+    pub fn new(messages: Vec<Message>) -> Self {
+        Self {
+            summary: None,
+            messages,
+            context: None,
+        }
+    }
+
+    /// A summary of the turns before `messages`
+    pub fn with_summary(mut self, summary: Option<String>) -> Self {
+        self.summary = summary;
+        self
+    }
+
+    /// Carry on from the context handed back by an earlier completion
+    pub fn with_context(mut self, context: Option<Context>) -> Self {
+        self.context = context;
+        self
+    }
+
+    pub fn summary(&self) -> Option<&str> {
+        self.summary.as_deref()
+    }
+
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+
+    pub fn context(&self) -> Option<&Context> {
+        self.context.as_ref()
+    }
+
+    /// Replace the summary with one which also covers the oldest `count` messages, dropping them
+    pub fn fold(&mut self, count: usize, summary: String) {
+        self.messages.drain(..count.min(self.messages.len()));
+        self.summary = Some(summary);
+    }
+
+    /// Record a finished turn, along with the context handed back for it if any
+    pub fn push(&mut self, input: &str, response: &str, context: Option<Context>) {
+        self.messages.push(Message::user(input.trim()));
+        self.messages.push(Message::assistant(response.trim()));
+        if context.is_some() {
+            self.context = context;
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct LlmEngine {
     // model_map: HashMap<String, String>,
-    ollama: Ollama,
+    language_model: Arc<dyn LanguageModel>,
 
     supervisor_model: String,
     conversational_model: String,
     image_model: String,
     embedding_model: String,
+
+    conversation_memory: ConversationMemory,
+    token_budget: TokenBudget,
+    prompts: Prompts,
+    chat_name: Option<String>,
+    tool_call_repairs: usize,
+
+    // Overrides for the conversational model
+    system_prompt: Option<Template>,
+    generation_options: GenerationOptions,
 }
 
 /// Mulitpuropse engine with access to various models
 impl LlmEngine {
     pub fn new(
-        url: &Url,
+        language_model: Arc<dyn LanguageModel>,
         supervisor_model: String,
         conversational_model: String,
         image_model: String,
         embedding_model: String,
     ) -> Self {
-        let scheme = url.scheme();
-        let host = url.host_str().unwrap_or("localhost");
-        let port = url.port().unwrap_or(11434);
-        let host = format!("{}://{}", scheme, host);
         Self {
-            ollama: Ollama::new(host, port),
+            language_model,
 
             supervisor_model,
             conversational_model,
             image_model,
             embedding_model,
+
+            conversation_memory: ConversationMemory::default(),
+            token_budget: TokenBudget::default(),
+            prompts: Prompts::default(),
+            chat_name: None,
+            tool_call_repairs: DEFAULT_TOOL_CALL_REPAIRS,
+
+            system_prompt: None,
+            generation_options: GenerationOptions::default(),
+        }
+    }
+
+    pub fn with_supervisor_model(mut self, supervisor_model: String) -> Self {
+        self.supervisor_model = supervisor_model;
+        self
+    }
+
+    pub fn with_conversational_model(mut self, conversational_model: String) -> Self {
+        self.conversational_model = conversational_model;
+        self
+    }
+
+    pub fn with_image_model(mut self, image_model: String) -> Self {
+        self.image_model = image_model;
+        self
+    }
+
+    pub fn with_embedding_model(mut self, embedding_model: String) -> Self {
+        self.embedding_model = embedding_model;
+        self
+    }
+
+    /// Give the conversational model a persona of its own in place of the default system prompt
+    pub fn with_system_prompt(mut self, system_prompt: Template) -> Self {
+        self.system_prompt = Some(system_prompt);
+        self
+    }
+
+    /// Render the system prompts from the given templates rather than the built in ones
+    pub fn with_prompts(mut self, prompts: Prompts) -> Self {
+        self.prompts = prompts;
+        self
+    }
+
+    /// How many times the supervisor may be asked to fix a tool call which can't be parsed
+    pub fn with_tool_call_repairs(mut self, tool_call_repairs: usize) -> Self {
+        self.tool_call_repairs = tool_call_repairs;
+        self
+    }
+
+    pub fn tool_call_repairs(&self) -> usize {
+        self.tool_call_repairs
+    }
+
+    /// Name the chat the engine is working in, for the `{chat_name}` of prompts
+    pub fn with_chat_name(mut self, chat_name: String) -> Self {
+        self.chat_name = Some(chat_name);
+        self
+    }
+
+    /// Sample from the conversational model with the given options.
+    /// Setting `num_ctx` also budgets prompts for a context window of that size.
+    pub fn with_generation_options(mut self, generation_options: GenerationOptions) -> Self {
+        if let Some(num_ctx) = generation_options.num_ctx {
+            self.token_budget = self.token_budget.with_context_length(num_ctx as usize);
         }
+        self.generation_options = generation_options;
+        self
+    }
+
+    pub fn supervisor_model(&self) -> &str {
+        &self.supervisor_model
+    }
+
+    pub fn conversational_model(&self) -> &str {
+        &self.conversational_model
+    }
+
+    pub fn image_model(&self) -> &str {
+        &self.image_model
+    }
+
+    pub fn generation_options(&self) -> &GenerationOptions {
+        &self.generation_options
+    }
+
+    /// Budget prompts for the conversational model with the given context window
+    pub fn with_token_budget(mut self, token_budget: TokenBudget) -> Self {
+        self.token_budget = token_budget;
+        self
+    }
+
+    pub fn token_budget(&self) -> &TokenBudget {
+        &self.token_budget
+    }
+
+    pub fn with_conversation_memory(mut self, conversation_memory: ConversationMemory) -> Self {
+        self.conversation_memory = conversation_memory;
+        self
+    }
+
+    pub fn conversation_memory(&self) -> ConversationMemory {
+        self.conversation_memory
+    }
+
+    /// The name of the model used for embeddings
+    pub fn embedding_model(&self) -> &str {
+        &self.embedding_model
     }
 
     pub async fn embed(&self, input: &str) -> Result<Vec<f64>, LlmEngineError> {
-        let response = self
-            .generate_embeddings(self.embedding_model.clone(), input.to_string(), None)
+        let embeddings = self
+            .language_model
+            .embed(&self.embedding_model, input)
             .await?;
-        Ok(response.embeddings)
+        Ok(embeddings)
     }
 
-    pub async fn handle(&self, input: &str) -> Result<ToolCall, LlmEngineError> {
-        // Build a new chat message request
-        let system_prompt_message =
-            ChatMessage::new(MessageRole::System, SUPERVISOR_SYSTEM_PROMPT.to_string());
-        let chat_message = ChatMessage::new(MessageRole::User, input.to_string());
-        let request = ChatMessageRequest::new(
-            self.supervisor_model.clone(),
-            vec![system_prompt_message, chat_message],
-        );
+    /// Run the supervisor over a conversation and return its raw response.
+    /// The supervisor system prompt, describing the given `<tools>` block,
+    /// is prepended to the given messages.
+    pub async fn supervise(
+        &self,
+        tools: &str,
+        messages: &[Message],
+    ) -> Result<String, LlmEngineError> {
+        let system_prompt = self
+            .prompts
+            .get(Prompt::Supervisor)
+            .render(&self.variables().with("tools", tools));
+        let mut request_messages = vec![Message::system(system_prompt)];
+        request_messages.extend_from_slice(messages);
 
-        let chat_message_response = self.send_chat_messages(request).await?;
-        let response = match chat_message_response.message {
-            None => return Err(LlmEngineError::NoMessageError),
-            Some(response) => response,
-        };
-        let tool_call = match ToolCall::try_from(response.content.as_str()) {
-            Ok(tool_call) => tool_call,
-            Err(e) => {
-                tracing::error!("Received unparsable tool call: {}", response.content);
-                tracing::error!("Failed to parse tool call: {}", e);
-                return Err(LlmEngineError::ToolCallError(e));
-            }
-        };
-        Ok(tool_call)
+        let response = self
+            .language_model
+            .chat(
+                &self.supervisor_model,
+                &request_messages,
+                &GenerationOptions::default(),
+            )
+            .await?;
+        Ok(response)
     }
 
-    // TODO: Streaming
+    /// Stream an analysis of the image at `image_path` from the image model,
+    /// guided by the given `prompt`
     pub async fn image(
         &self,
         // Eventually just make this a URL
-        image_path: &PathBuf,
-    ) -> Result<String, LlmEngineError> {
-        let image = ImageReader::open(image_path).unwrap().decode().unwrap();
+        image_path: &Path,
+        prompt: &str,
+    ) -> Result<CompletionStream, LlmEngineError> {
+        if !image_path.is_file() {
+            return Err(LlmEngineError::ImageNotFound(image_path.to_path_buf()));
+        }
+        let image = ImageReader::open(image_path)?
+            .with_guessed_format()?
+            .decode()?;
         let mut buf = Vec::new();
-        image
-            .write_to(&mut Cursor::new(&mut buf), ImageFormat::Png)
-            .map_err(|e| LlmEngineError::DefaultError(e.into()))?;
+        image.write_to(&mut Cursor::new(&mut buf), ImageFormat::Png)?;
         let base64_image = BASE64_STANDARD.encode(&buf);
 
-        let image = Image::from_base64(&base64_image);
-        let request = GenerationRequest::new(
-            self.image_model.clone(),
-            "Please analyze this image".to_string(),
-        )
-        .add_image(image);
-
-        let mut stream: GenerationResponseStream =
-            self.generate_stream(request.clone()).await.unwrap();
-        let mut response_buffer = Vec::new();
-        while let Some(Ok(response)) = stream.next().await {
-            for ele in response.clone() {
-                response_buffer.extend(ele.response.as_bytes());
-            }
-        }
-        let response = String::from_utf8(response_buffer).unwrap();
-        Ok(response)
+        let stream = self
+            .language_model
+            .vision(&self.image_model, prompt.trim(), &[base64_image])
+            .await?;
+        Ok(stream)
     }
 
+    /// Stream a response from the conversational model, following on from the
+    /// `conversation` so far. Any retrieved `documents` are injected into the prompt
+    /// ahead of the input, numbered so the model can cite them.
     pub async fn converse(
         &self,
         input: &str,
-        context: Option<GenerationContext>,
-    ) -> Result<GenerationResponseStream, LlmEngineError> {
-        let input = input.trim();
-        let options = GenerationOptions::default();
-        let request = GenerationRequest::new(self.conversational_model.clone(), input.to_string())
-            .options(options);
-        if let Some(context) = context.clone() {
-            request.clone().context(context);
-        }
-        let stream: GenerationResponseStream = self.generate_stream(request.clone()).await?;
+        documents: &[Passage],
+        conversation: &Conversation,
+    ) -> Result<CompletionStream, LlmEngineError> {
+        let stream = match self.conversation_memory {
+            ConversationMemory::History => {
+                // The documents go wherever the system prompt places them, if it does
+                let (system, prompt) = if self.conversational_template().uses("context") {
+                    let system =
+                        self.conversational_system_prompt(conversation.summary(), documents);
+                    (system, input.trim().to_string())
+                } else {
+                    let system = self.conversational_system_prompt(conversation.summary(), &[]);
+                    (system, converse_prompt(input.trim(), documents))
+                };
+                let system = Message::system(system);
+                let prompt = Message::user(prompt);
+                // Leave out whatever older turns still don't fit alongside the documents
+                let available = self
+                    .token_budget
+                    .context_length()
+                    .saturating_sub(self.token_budget.response_tokens())
+                    .saturating_sub(message_tokens(&system))
+                    .saturating_sub(message_tokens(&prompt));
+                let history = conversation.messages();
+                let skip = TokenBudget::overflow(history, available);
+                if skip > 0 {
+                    tracing::warn!("Leaving {} older messages out of the prompt", skip);
+                }
+                let mut messages = vec![system];
+                messages.extend_from_slice(&history[skip..]);
+                messages.push(prompt);
+                self.language_model
+                    .chat_stream(
+                        &self.conversational_model,
+                        &messages,
+                        &self.generation_options,
+                    )
+                    .await?
+            }
+            ConversationMemory::Context => {
+                self.language_model
+                    .complete(
+                        &self.conversational_model,
+                        &converse_prompt(input.trim(), documents),
+                        conversation.context().cloned(),
+                        &self.generation_options,
+                    )
+                    .await?
+            }
+        };
         Ok(stream)
     }
+
+    /// What prompts may refer to whatever they are for
+    fn variables(&self) -> Variables {
+        Variables::new()
+            .with("date", time::OffsetDateTime::now_utc().date().to_string())
+            .with("chat_name", self.chat_name.clone().unwrap_or_default())
+    }
+
+    fn conversational_template(&self) -> &Template {
+        self.system_prompt
+            .as_ref()
+            .unwrap_or_else(|| self.prompts.get(Prompt::Conversational))
+    }
+
+    /// The conversational system prompt, with any retrieved `documents` as its `{context}`.
+    /// A summary of older turns goes in its `{summary}`, or after it if it has none.
+    fn conversational_system_prompt(&self, summary: Option<&str>, documents: &[Passage]) -> String {
+        let template = self.conversational_template();
+        let variables = self
+            .variables()
+            .with("summary", summary.unwrap_or_default())
+            .with("context", documents_prompt(documents));
+        let system_prompt = template.render(&variables);
+        match summary {
+            Some(summary) if !template.uses("summary") => format!(
+                "{}\n\nA summary of the conversation so far:\n{}",
+                system_prompt.trim_end(),
+                summary
+            ),
+            _ => system_prompt,
+        }
+    }
+
+    /// How a prompt continuing the `conversation` would use up the conversational
+    /// model's context window
+    pub fn context_usage(&self, conversation: &Conversation) -> ContextUsage {
+        let system_prompt = self.conversational_system_prompt(conversation.summary(), &[]);
+        let available = self.token_budget.history_tokens(&system_prompt);
+        let history = conversation.messages();
+        let skip = TokenBudget::overflow(history, available);
+        ContextUsage {
+            context_length: self.token_budget.context_length(),
+            system: message_tokens(&Message::system(system_prompt)),
+            summary: conversation.summary().map(count_tokens).unwrap_or_default(),
+            history: history[skip..].iter().map(message_tokens).sum(),
+            messages: history.len() - skip,
+            documents: self.token_budget.document_tokens(),
+            response: self.token_budget.response_tokens(),
+        }
+    }
+
+    /// Make sure the earlier turns of the `conversation` fit in the conversational model's
+    /// context, folding the oldest of them into its summary if they don't.
+    /// Returns how many messages were folded away.
+    pub async fn fit(&self, conversation: &mut Conversation) -> Result<usize, LlmEngineError> {
+        // The server keeps track of the conversation itself when carrying over its context
+        if self.conversation_memory == ConversationMemory::Context {
+            return Ok(0);
+        }
+        let system_prompt = self.conversational_system_prompt(conversation.summary(), &[]);
+        let available = self.token_budget.history_tokens(&system_prompt);
+        let messages = conversation.messages();
+        if TokenBudget::overflow(messages, available) == 0 {
+            return Ok(0);
+        }
+
+        // Make room for a few more turns, rather than summarizing again on the next one,
+        // and start what's left on a user message so it reads as whole turns
+        let mut count = TokenBudget::overflow(messages, available / 2);
+        while count < messages.len() && messages[count].role() != Role::User {
+            count += 1;
+        }
+        // Words run a little over a token each, so this keeps the summary to about a quarter
+        let words = (available / 6).max(16);
+        let summary = self
+            .summarize(conversation.summary(), &messages[..count], words)
+            .await?;
+        conversation.fold(count, summary);
+        Ok(count)
+    }
+
+    /// Summarize the given turns, carrying on from an earlier summary
+    async fn summarize(
+        &self,
+        summary: Option<&str>,
+        messages: &[Message],
+        words: usize,
+    ) -> Result<String, LlmEngineError> {
+        let mut transcript = String::new();
+        if let Some(summary) = summary {
+            transcript.push_str(&format!("<summary>\n{}\n</summary>\n\n", summary));
+        }
+        for message in messages {
+            let speaker = match message.role() {
+                Role::User => "User",
+                _ => "Assistant",
+            };
+            transcript.push_str(&format!("{}: {}\n\n", speaker, message.content()));
+        }
+        let request = vec![
+            Message::system(
+                self.prompts
+                    .get(Prompt::Summarizer)
+                    .render(&self.variables().with("words", words.to_string())),
+            ),
+            Message::user(transcript.trim_end()),
+        ];
+        let summary = self
+            .language_model
+            .chat(
+                &self.conversational_model,
+                &request,
+                &self.generation_options,
+            )
+            .await?;
+        Ok(summary.trim().to_string())
+    }
+}
+
+fn converse_prompt(input: &str, documents: &[Passage]) -> String {
+    if documents.is_empty() {
+        return input.to_string();
+    }
+    format!("{}\n\n{}", documents_prompt(documents), input)
 }
 
-impl Deref for LlmEngine {
-    type Target = Ollama;
-    fn deref(&self) -> &Self::Target {
-        &self.ollama
+/// The retrieved documents, numbered so the model can cite them
+fn documents_prompt(documents: &[Passage]) -> String {
+    if documents.is_empty() {
+        return String::new();
+    }
+    let mut prompt = String::from(
+        "Use the following documents to help answer the user, \
+         citing them by index like [1] where relevant:\n<documents>\n",
+    );
+    for (i, document) in documents.iter().enumerate() {
+        let source = match &document.source {
+            Some(source) => format!(" source=\"{}\"", source.path),
+            None => String::new(),
+        };
+        prompt.push_str(&format!(
+            "<document index=\"{}\"{}>\n{}\n</document>\n",
+            i + 1,
+            source,
+            document.text.trim()
+        ));
     }
+    prompt.push_str("</documents>");
+    prompt
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum LlmEngineError {
     #[error("default error: {0}")]
     DefaultError(anyhow::Error),
-    #[error("tool call error: {0}")]
-    ToolCallError(#[from] super::tool_call::ToolCallError),
-    #[error("ollama error: {0}")]
-    Ollam(#[from] ollama_rs::error::OllamaError),
+    #[error("language model error: {0}")]
+    LanguageModel(#[from] LanguageModelError),
     #[error("image error: {0}")]
     ImageError(#[from] image::ImageError),
-    #[error("no message error")]
-    NoMessageError,
+    #[error("image not found: {0}")]
+    ImageNotFound(PathBuf),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use futures::TryStreamExt;
+
+    use crate::agent::language_model::OllamaBackend;
+    use crate::tests::prelude::*;
+    use crate::vector_store::Source;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_embed() {
+        let mock = MockLanguageModel::new();
+        let engine = mock.engine();
+        let embedding = engine.embed("hello").await.unwrap();
+        assert_eq!(embedding.len(), MOCK_EMBEDDING_DIMENSIONS);
+        assert_eq!(
+            mock.requests(),
+            vec![MockRequest::Embed {
+                model: "embedding".to_string(),
+                input: "hello".to_string()
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_image() {
+        let mock = MockLanguageModel::new().completion_response("A pink flower");
+        let engine = mock.engine();
+
+        let missing = engine.image(Path::new("data/missing.jpg"), "what?").await;
+        assert!(matches!(missing, Err(LlmEngineError::ImageNotFound(_))));
+
+        let not_an_image = engine.image(Path::new("data/blossom.txt"), "what?").await;
+        assert!(matches!(not_an_image, Err(LlmEngineError::ImageError(_))));
+
+        assert!(engine
+            .image(Path::new("data/blossom.jpg"), " what is this? ")
+            .await
+            .is_ok());
+        assert_eq!(
+            mock.requests(),
+            vec![MockRequest::Vision {
+                model: "image".to_string(),
+                prompt: "what is this?".to_string(),
+                images: 1
+            }]
+        );
+    }
+
+    fn ollama_engine(server: &FakeOllama, memory: ConversationMemory) -> LlmEngine {
+        LlmEngine::new(
+            Arc::new(OllamaBackend::new(server.url())),
+            "supervisor".to_string(),
+            "conversational".to_string(),
+            "image".to_string(),
+            "embedding".to_string(),
+        )
+        .with_conversation_memory(memory)
+    }
+
+    async fn converse(engine: &LlmEngine, conversation: &mut Conversation, input: &str) {
+        let stream = engine.converse(input, &[], conversation).await.unwrap();
+        let completions = stream.try_collect::<Vec<_>>().await.unwrap();
+        let response = completions
+            .iter()
+            .map(|completion| completion.text.as_str())
+            .collect::<String>();
+        let context = completions
+            .into_iter()
+            .find_map(|completion| completion.context);
+        conversation.push(input, &response, context);
+    }
+
+    #[tokio::test]
+    async fn test_converse_sends_history() {
+        let server = FakeOllama::start().await;
+        server
+            .chat_response("Blossoms are pink")
+            .chat_response("In spring");
+        let engine = ollama_engine(&server, ConversationMemory::History);
+
+        let mut conversation = Conversation::default();
+        converse(&engine, &mut conversation, "What color are blossoms?").await;
+        converse(&engine, &mut conversation, "When do they bloom?").await;
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        let (path, body) = &requests[1];
+        assert_eq!(path, "/api/chat");
+        assert_eq!(body["model"], "conversational");
+        let messages = body["messages"].as_array().unwrap();
+        let turns = messages
+            .iter()
+            .map(|message| {
+                format!(
+                    "{}: {}",
+                    message["role"].as_str().unwrap(),
+                    message["content"].as_str().unwrap()
+                )
+            })
+            .collect::<Vec<_>>();
+        assert!(turns[0].starts_with("system: "));
+        assert_eq!(
+            turns[1..],
+            [
+                "user: What color are blossoms?",
+                "assistant: Blossoms are pink",
+                "user: When do they bloom?",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_converse_sends_context() {
+        let server = FakeOllama::start().await;
+        server
+            .generate_response("Blossoms are pink")
+            .generate_response("In spring");
+        let engine = ollama_engine(&server, ConversationMemory::Context);
+
+        let mut conversation = Conversation::default();
+        converse(&engine, &mut conversation, "What color are blossoms?").await;
+        converse(&engine, &mut conversation, "When do they bloom?").await;
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].1["context"].is_null());
+        let (path, body) = &requests[1];
+        assert_eq!(path, "/api/generate");
+        assert_eq!(body["prompt"], "When do they bloom?");
+        assert_eq!(body["context"], serde_json::json!([1]));
+    }
+
+    #[tokio::test]
+    async fn test_fit_summarizes_older_turns() {
+        let mock = MockLanguageModel::new()
+            .chat_response(" They talked about blossoms ")
+            .completion_response("Pink");
+        let system_tokens = message_tokens(&Message::system(
+            MockLanguageModel::new()
+                .engine()
+                .conversational_system_prompt(None, &[]),
+        ));
+        let budget = TokenBudget::new(system_tokens + 40)
+            .with_response_tokens(0)
+            .with_document_tokens(0);
+        let engine = mock.engine().with_token_budget(budget);
+
+        let turn = "one two three four five six";
+        let messages = (0..3)
+            .flat_map(|_| [Message::user(turn), Message::assistant(turn)])
+            .collect::<Vec<_>>();
+        let mut conversation = Conversation::new(messages.clone());
+        assert_eq!(engine.context_usage(&conversation).messages, 4);
+
+        // Enough is folded away to leave room for another turn
+        let folded = engine.fit(&mut conversation).await.unwrap();
+        assert_eq!(folded, 4);
+        assert_eq!(conversation.summary(), Some("They talked about blossoms"));
+        assert_eq!(conversation.messages(), &messages[4..]);
+        assert_eq!(engine.fit(&mut conversation).await.unwrap(), 0);
+
+        let usage = engine.context_usage(&conversation);
+        assert_eq!(usage.messages, 2);
+        assert!(usage.summary > 0);
+
+        // The summary rides along in the system prompt from then on
+        let _ = engine.converse("and?", &[], &conversation).await.unwrap();
+        let requests = mock.requests();
+        let MockRequest::Chat { messages, .. } = &requests[0] else {
+            panic!("expected a summary request, got {:?}", requests[0]);
+        };
+        assert!(messages[0].content().contains("at most"));
+        assert!(messages[1].content().starts_with("User: one two"));
+        let MockRequest::ChatStream { messages, .. } = &requests[1] else {
+            panic!("expected a streamed chat, got {:?}", requests[1]);
+        };
+        assert!(messages[0]
+            .content()
+            .ends_with("They talked about blossoms"));
+        assert_eq!(messages.last(), Some(&Message::user("and?")));
+    }
+
+    #[tokio::test]
+    async fn test_overrides() {
+        let mock = MockLanguageModel::new()
+            .chat_response("Hello")
+            .completion_response("Pink");
+        let options = GenerationOptions {
+            temperature: Some(0.2),
+            num_ctx: Some(4096),
+            ..Default::default()
+        };
+        let engine = mock
+            .engine()
+            .with_supervisor_model("boss".to_string())
+            .with_conversational_model("chatty".to_string())
+            .with_system_prompt(Template::literal("You are a botanist"))
+            .with_generation_options(options);
+        assert_eq!(engine.token_budget().context_length(), 4096);
+
+        engine.supervise("", &[Message::user("hi")]).await.unwrap();
+        let _ = engine
+            .converse("hi", &[], &Conversation::default())
+            .await
+            .unwrap();
+        let requests = mock.requests();
+        assert!(matches!(&requests[0], MockRequest::Chat { model, .. } if model == "boss"));
+        let MockRequest::ChatStream { model, messages } = &requests[1] else {
+            panic!("expected a streamed chat, got {:?}", requests[1]);
+        };
+        assert_eq!(model, "chatty");
+        assert_eq!(messages[0], Message::system("You are a botanist"));
+        // Only the conversational model samples with the chat's options
+        assert_eq!(mock.options(), vec![GenerationOptions::default(), options]);
+    }
+
+    #[tokio::test]
+    async fn test_prompts_place_documents() {
+        let mock = MockLanguageModel::new().completion_response("Pink");
+        let template = Prompt::Conversational
+            .parse("Chatting in {chat_name}.{#if context}\n{context}{/if}")
+            .unwrap();
+        let engine = mock
+            .engine()
+            .with_prompts(Prompts::default().with_prompt(Prompt::Conversational, template))
+            .with_chat_name("pink-cat".to_string());
+        let documents = vec![Passage {
+            id: "a".to_string(),
+            text: "Blossoms are pink".to_string(),
+            source: None,
+        }];
+
+        let _ = engine
+            .converse("What color?", &documents, &Conversation::default())
+            .await
+            .unwrap();
+        let requests = mock.requests();
+        let MockRequest::ChatStream { messages, .. } = &requests[0] else {
+            panic!("expected a streamed chat, got {:?}", requests[0]);
+        };
+        assert!(messages[0]
+            .content()
+            .starts_with("Chatting in pink-cat.\nUse the following documents"));
+        assert!(messages[0].content().ends_with("</documents>"));
+        assert_eq!(messages[1], Message::user("What color?"));
+        assert_eq!(
+            engine.conversational_system_prompt(None, &[]),
+            "Chatting in pink-cat."
+        );
+    }
+
+    #[test]
+    fn test_converse_prompt() {
+        assert_eq!(converse_prompt("hello", &[]), "hello");
+
+        let documents = vec![
+            Passage {
+                id: "a".to_string(),
+                text: "first".to_string(),
+                source: Some(Source {
+                    path: "notes.txt".to_string(),
+                    start: 0,
+                    end: 5,
+                }),
+            },
+            Passage {
+                id: "b".to_string(),
+                text: " second\n".to_string(),
+                source: None,
+            },
+        ];
+        let prompt = converse_prompt("hello", &documents);
+        assert!(prompt.contains("<document index=\"1\" source=\"notes.txt\">\nfirst\n</document>"));
+        assert!(prompt.contains("<document index=\"2\">\nsecond\n</document>"));
+        assert!(prompt.ends_with("</documents>\n\nhello"));
+    }
 }
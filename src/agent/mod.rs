@@ -1,7 +1,28 @@
+mod budget;
 mod command;
+mod language_model;
 mod llm_engine;
+mod prompts;
+mod supervisor;
 mod tool_call;
+mod tools;
 
+pub use budget::{
+    ContextUsage, TokenBudget, DEFAULT_CONTEXT_LENGTH, DEFAULT_DOCUMENT_TOKENS,
+    DEFAULT_RESPONSE_TOKENS,
+};
 pub use command::Command as ChatCommand;
-pub use llm_engine::{LlmEngine, LlmEngineError};
-pub use tool_call::{ToolCall, ToolCallError};
+pub use language_model::{
+    Completion, CompletionStream, Context, GenerationOptions, LanguageModel, LanguageModelError,
+    Message, OllamaBackend, OpenAiBackend, Role,
+};
+pub use llm_engine::{Conversation, ConversationMemory, LlmEngine, LlmEngineError};
+pub use prompts::{Prompt, PromptError, Prompts, Template, TemplateError, Variables};
+pub use supervisor::{
+    Event, Outcome, Step, Supervisor, SupervisorError, DEFAULT_TOOL_CALL_REPAIRS, MAX_DEPTH,
+};
+pub use tool_call::{Argument, ToolCall, ToolCallError};
+pub use tools::{
+    is_image, ArgumentSpec, ArgumentType, Arguments, AttachedImages, ConverseTool, ImageTool,
+    Retrieved, SearchDocumentsTool, SharedConversation, Tool, ToolError, ToolOutput, ToolRegistry,
+};
@@ -0,0 +1,166 @@
+use std::path::{Path, PathBuf};
+
+mod template;
+
+pub use template::{Template, TemplateError, Variables};
+
+/// The system prompts the engine sends to its models
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prompt {
+    /// Decides which tools to call, given the `{tools}` it has
+    Supervisor,
+    /// Talks with the user, who may want `{context}` or `{summary}` placed somewhere in particular
+    Conversational,
+    /// Folds older turns into a summary of at most `{words}` words
+    Summarizer,
+}
+
+impl Prompt {
+    pub const ALL: [Prompt; 3] = [
+        Prompt::Supervisor,
+        Prompt::Conversational,
+        Prompt::Summarizer,
+    ];
+
+    /// The name of the file the prompt is read from
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Prompt::Supervisor => "supervisor.txt",
+            Prompt::Conversational => "conversational.txt",
+            Prompt::Summarizer => "summarizer.txt",
+        }
+    }
+
+    /// The variables the prompt may refer to
+    pub fn variables(&self) -> &'static [&'static str] {
+        match self {
+            Prompt::Supervisor => &["tools", "date", "chat_name"],
+            Prompt::Conversational => &["date", "chat_name", "summary", "context"],
+            Prompt::Summarizer => &["words", "date", "chat_name"],
+        }
+    }
+
+    /// The prompt compiled into the binary
+    pub fn builtin(&self) -> &'static str {
+        match self {
+            Prompt::Supervisor => include_str!("../../../supervisor.txt"),
+            Prompt::Conversational => include_str!("../../../conversational.txt"),
+            Prompt::Summarizer => include_str!("../../../summarizer.txt"),
+        }
+    }
+
+    pub fn parse(&self, text: &str) -> Result<Template, TemplateError> {
+        Template::parse(text, self.variables())
+    }
+}
+
+/// A template for each prompt, read from a directory or built in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prompts {
+    supervisor: Template,
+    conversational: Template,
+    summarizer: Template,
+}
+
+impl Default for Prompts {
+    fn default() -> Self {
+        let builtin = |prompt: Prompt| {
+            prompt
+                .parse(prompt.builtin())
+                .expect("Built in prompts are valid templates")
+        };
+        Self {
+            supervisor: builtin(Prompt::Supervisor),
+            conversational: builtin(Prompt::Conversational),
+            summarizer: builtin(Prompt::Summarizer),
+        }
+    }
+}
+
+impl Prompts {
+    /// Read the prompts from `directory`, falling back to the built in prompt
+    /// for any file that isn't there
+    pub fn load(directory: &Path) -> Result<Self, PromptError> {
+        let mut prompts = Self::default();
+        for prompt in Prompt::ALL {
+            let path = directory.join(prompt.file_name());
+            let text = match std::fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(PromptError::Io(path, e)),
+            };
+            let template = prompt
+                .parse(&text)
+                .map_err(|e| PromptError::Template(path.clone(), e))?;
+            tracing::info!(
+                "Using the {} prompt from {}",
+                prompt.file_name(),
+                path.display()
+            );
+            prompts = prompts.with_prompt(prompt, template);
+        }
+        Ok(prompts)
+    }
+
+    pub fn with_prompt(mut self, prompt: Prompt, template: Template) -> Self {
+        match prompt {
+            Prompt::Supervisor => self.supervisor = template,
+            Prompt::Conversational => self.conversational = template,
+            Prompt::Summarizer => self.summarizer = template,
+        }
+        self
+    }
+
+    pub fn get(&self, prompt: Prompt) -> &Template {
+        match prompt {
+            Prompt::Supervisor => &self.supervisor,
+            Prompt::Conversational => &self.conversational,
+            Prompt::Summarizer => &self.summarizer,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PromptError {
+    #[error("failed to read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("invalid template in {0}: {1}")]
+    Template(PathBuf, TemplateError),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let directory =
+            std::env::temp_dir().join(format!("blossom-prompts-{}", rand::random::<u64>()));
+        std::fs::create_dir(&directory).unwrap();
+        std::fs::write(
+            directory.join("conversational.txt"),
+            "You are talking in {chat_name}.",
+        )
+        .unwrap();
+
+        let prompts = Prompts::load(&directory).unwrap();
+        let variables = Variables::new().with("chat_name", "pink-cat");
+        assert_eq!(
+            prompts.get(Prompt::Conversational).render(&variables),
+            "You are talking in pink-cat."
+        );
+        // Anything missing from the directory is built in
+        assert_eq!(
+            prompts.get(Prompt::Supervisor),
+            Prompts::default().get(Prompt::Supervisor)
+        );
+
+        std::fs::write(directory.join("summarizer.txt"), "At most {tools} words").unwrap();
+        assert!(matches!(
+            Prompts::load(&directory),
+            Err(PromptError::Template(path, TemplateError::UnknownVariable(name)))
+                if path.ends_with("summarizer.txt") && name == "tools"
+        ));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+
+/// A prompt with `{name}` placeholders and `{#if name}...{/if}` sections,
+/// the latter only kept when the named variable isn't empty.
+/// Braces around anything other than a tag are left alone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template(Vec<Node>);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Variable(String),
+    If(String, Vec<Node>),
+}
+
+enum Tag<'a> {
+    Variable(&'a str),
+    If(&'a str),
+    EndIf,
+}
+
+/// The values interpolated into a template, by name
+#[derive(Debug, Clone, Default)]
+pub struct Variables(HashMap<&'static str, String>);
+
+impl Variables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.0.insert(name, value.into());
+        self
+    }
+
+    fn get(&self, name: &str) -> &str {
+        self.0.get(name).map(String::as_str).unwrap_or_default()
+    }
+}
+
+impl Template {
+    /// Parse a template which may only refer to the `known` variables
+    pub fn parse(text: &str, known: &[&str]) -> Result<Self, TemplateError> {
+        let mut stack: Vec<(String, Vec<Node>)> = Vec::new();
+        let mut nodes = Vec::new();
+        let mut rest = text;
+        while let Some((before, tag, after)) = next_tag(rest) {
+            if !before.is_empty() {
+                nodes.push(Node::Text(before.to_string()));
+            }
+            match tag {
+                Tag::Variable(name) | Tag::If(name) if !known.contains(&name) => {
+                    return Err(TemplateError::UnknownVariable(name.to_string()));
+                }
+                Tag::Variable(name) => nodes.push(Node::Variable(name.to_string())),
+                Tag::If(name) => stack.push((name.to_string(), std::mem::take(&mut nodes))),
+                Tag::EndIf => {
+                    let (name, outer) = stack.pop().ok_or(TemplateError::UnexpectedEndIf)?;
+                    let body = std::mem::replace(&mut nodes, outer);
+                    nodes.push(Node::If(name, body));
+                }
+            }
+            rest = after;
+        }
+        if let Some((name, _)) = stack.pop() {
+            return Err(TemplateError::UnclosedIf(name));
+        }
+        if !rest.is_empty() {
+            nodes.push(Node::Text(rest.to_string()));
+        }
+        Ok(Self(nodes))
+    }
+
+    /// A template which is just the given text
+    pub fn literal(text: &str) -> Self {
+        Self(vec![Node::Text(text.to_string())])
+    }
+
+    pub fn render(&self, variables: &Variables) -> String {
+        let mut output = String::new();
+        render(&self.0, variables, &mut output);
+        output
+    }
+
+    /// Whether the template places the named variable itself
+    pub fn uses(&self, name: &str) -> bool {
+        uses(&self.0, name)
+    }
+}
+
+fn render(nodes: &[Node], variables: &Variables, output: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => output.push_str(text),
+            Node::Variable(name) => output.push_str(variables.get(name)),
+            Node::If(name, body) => {
+                if !variables.get(name).is_empty() {
+                    render(body, variables, output);
+                }
+            }
+        }
+    }
+}
+
+fn uses(nodes: &[Node], name: &str) -> bool {
+    nodes.iter().any(|node| match node {
+        Node::Text(_) => false,
+        Node::Variable(variable) => variable == name,
+        Node::If(variable, body) => variable == name || uses(body, name),
+    })
+}
+
+/// Split off the text before the next tag, the tag itself and the text after it
+fn next_tag(text: &str) -> Option<(&str, Tag<'_>, &str)> {
+    let mut offset = 0;
+    while let Some(start) = text[offset..].find('{').map(|i| offset + i) {
+        let end = text[start..].find('}').map(|i| start + i)?;
+        let inner = &text[start + 1..end];
+        let tag = match inner.strip_prefix("#if ") {
+            Some(name) if is_identifier(name.trim()) => Some(Tag::If(name.trim())),
+            _ if inner == "/if" => Some(Tag::EndIf),
+            _ if is_identifier(inner) => Some(Tag::Variable(inner)),
+            _ => None,
+        };
+        match tag {
+            Some(tag) => return Some((&text[..start], tag, &text[end + 1..])),
+            None => offset = start + 1,
+        }
+    }
+    None
+}
+
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TemplateError {
+    #[error("unknown variable: {0}")]
+    UnknownVariable(String),
+    #[error("{{#if {0}}} is never closed with {{/if}}")]
+    UnclosedIf(String),
+    #[error("{{/if}} without a matching {{#if}}")]
+    UnexpectedEndIf,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KNOWN: &[&str] = &["name", "summary"];
+
+    #[test]
+    fn test_render() {
+        let template = Template::parse(
+            "Hello {name}!{#if summary}\nSo far: {summary}{/if} {\"json\": 1}",
+            KNOWN,
+        )
+        .unwrap();
+        let variables = Variables::new().with("name", "blossom");
+        assert_eq!(template.render(&variables), "Hello blossom! {\"json\": 1}");
+        assert_eq!(
+            template.render(&variables.with("summary", "pink")),
+            "Hello blossom!\nSo far: pink {\"json\": 1}"
+        );
+        assert!(template.uses("summary"));
+        assert!(!Template::literal("{summary}").uses("summary"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            Template::parse("{nmae}", KNOWN),
+            Err(TemplateError::UnknownVariable(name)) if name == "nmae"
+        ));
+        assert!(matches!(
+            Template::parse("{#if name}a", KNOWN),
+            Err(TemplateError::UnclosedIf(name)) if name == "name"
+        ));
+        assert!(matches!(
+            Template::parse("a{/if}", KNOWN),
+            Err(TemplateError::UnexpectedEndIf)
+        ));
+    }
+}
@@ -0,0 +1,537 @@
+use std::path::PathBuf;
+
+use super::language_model::Message;
+use super::llm_engine::{LlmEngine, LlmEngineError};
+use super::tool_call::{Argument, ToolCall, ToolCallError};
+use super::tools::{ToolOutput, ToolRegistry};
+
+/// How many times the supervisor may call back into itself for a single user input
+pub const MAX_DEPTH: usize = 5;
+/// How many times the supervisor is shown why its tool call couldn't be parsed
+/// and asked for another, unless configured otherwise
+pub const DEFAULT_TOOL_CALL_REPAIRS: usize = 2;
+
+/// The outcome of a single supervisor iteration
+#[derive(Debug)]
+pub enum Step {
+    /// The supervisor wants a tool executed before continuing
+    ToolCall(ToolCall),
+    /// The supervisor answered the user directly
+    Answer(String),
+    /// The supervisor's tool call couldn't be parsed, so it was asked to try again
+    Repair(ToolCallError),
+}
+
+/// What came of a single step of the agent loop
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// A tool ran and its response was fed back to the supervisor
+    Response {
+        tool_call: ToolCall,
+        response: String,
+    },
+    /// A tool call failed and the error was fed back to the supervisor
+    Failed { tool_call: ToolCall, error: String },
+    /// The loop finished with an answer, either from the supervisor or a tool
+    Answer {
+        tool_call: Option<ToolCall>,
+        answer: String,
+    },
+}
+
+/// Progress reported while the agent loop runs
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// A tool is about to be called at the given depth
+    Calling {
+        depth: usize,
+        tool_call: &'a ToolCall,
+    },
+    /// The supervisor is being asked to fix a tool call, with attempts numbered from 1
+    Repairing {
+        attempt: usize,
+        error: &'a ToolCallError,
+    },
+    /// A step of the loop finished
+    Finished(&'a Outcome),
+}
+
+/// Drives the supervisor through a multi-step agent loop for one user input.
+/// Tool results are appended to the conversation within `<tool_response>` tags
+/// so the supervisor can analyze them on its next iteration.
+pub struct Supervisor {
+    input: String,
+    messages: Vec<Message>,
+    depth: usize,
+    repairs: usize,
+    outcomes: Vec<Outcome>,
+}
+
+impl Supervisor {
+    /// Start a new loop for the given user input, letting the supervisor
+    /// know about any images attached to the chat
+    pub fn new(input: &str, images: &[PathBuf]) -> Self {
+        let mut content = input.to_string();
+        if !images.is_empty() {
+            content.push_str("\n<images>\n");
+            for image in images {
+                content.push_str(&format!("  <image path=\"{}\"/>\n", image.display()));
+            }
+            content.push_str("</images>");
+        }
+        Self {
+            input: input.to_string(),
+            messages: vec![Message::user(content)],
+            depth: 0,
+            repairs: 0,
+            outcomes: Vec::new(),
+        }
+    }
+
+    /// How many steps the supervisor has taken so far
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The conversation between the user, the supervisor and its tools so far
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+
+    /// Everything that came of the loop so far, in order
+    pub fn outcomes(&self) -> &[Outcome] {
+        &self.outcomes
+    }
+
+    /// Run the agent loop until the supervisor or a tool produces a final answer.
+    /// Outcomes are recorded as they happen, so they remain available through
+    /// `outcomes` even if the loop fails part way through.
+    pub async fn run(
+        &mut self,
+        engine: &LlmEngine,
+        tools: &ToolRegistry,
+        mut on_event: impl FnMut(Event),
+    ) -> Result<(), SupervisorError> {
+        loop {
+            let tool_call = match self.step(engine, tools).await? {
+                Step::Answer(answer) => {
+                    self.finish(
+                        Outcome::Answer {
+                            tool_call: None,
+                            answer,
+                        },
+                        &mut on_event,
+                    );
+                    return Ok(());
+                }
+                Step::ToolCall(tool_call) => tool_call,
+                Step::Repair(error) => {
+                    on_event(Event::Repairing {
+                        attempt: self.repairs,
+                        error: &error,
+                    });
+                    continue;
+                }
+            };
+            on_event(Event::Calling {
+                depth: self.depth,
+                tool_call: &tool_call,
+            });
+            match tools.dispatch(&tool_call).await {
+                Ok(ToolOutput::Final(answer)) => {
+                    self.finish(
+                        Outcome::Answer {
+                            tool_call: Some(tool_call),
+                            answer,
+                        },
+                        &mut on_event,
+                    );
+                    return Ok(());
+                }
+                Ok(ToolOutput::Response(response)) => {
+                    self.tool_response(&tool_call, &response);
+                    self.finish(
+                        Outcome::Response {
+                            tool_call,
+                            response,
+                        },
+                        &mut on_event,
+                    );
+                }
+                Err(e) => {
+                    let error = format!("Failed to call `{}`: {}", tool_call.name(), e);
+                    self.tool_response(&tool_call, &error);
+                    self.finish(Outcome::Failed { tool_call, error }, &mut on_event);
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self, outcome: Outcome, on_event: &mut impl FnMut(Event)) {
+        on_event(Event::Finished(&outcome));
+        self.outcomes.push(outcome);
+    }
+
+    /// Invoke the supervisor on the conversation so far and decide what to do next.
+    /// A tool call that can't be parsed is sent back with the error, up to the engine's
+    /// limit of repairs, after which the user's input goes straight to `converse`.
+    /// Repairs count towards the depth, so they stop early enough to leave
+    /// the fallback a chance once the depth runs out.
+    pub async fn step(
+        &mut self,
+        engine: &LlmEngine,
+        tools: &ToolRegistry,
+    ) -> Result<Step, SupervisorError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(SupervisorError::MaxDepth(MAX_DEPTH));
+        }
+        self.depth += 1;
+
+        let content = engine.supervise(&tools.prompt(), &self.messages).await?;
+        self.messages.push(Message::assistant(content.clone()));
+
+        if !content.contains("<tool-call") {
+            return Ok(Step::Answer(content.trim().to_string()));
+        }
+        let error = match ToolCall::try_from(content.as_str()) {
+            Ok(tool_call) => return Ok(Step::ToolCall(tool_call)),
+            Err(e) => e,
+        };
+        tracing::warn!("Received unparsable tool call: {}", content);
+        tracing::warn!("Failed to parse tool call: {}", error);
+        if self.repairs < engine.tool_call_repairs() && self.depth < MAX_DEPTH {
+            self.repairs += 1;
+            self.messages.push(Message::user(format!(
+                "Your tool call could not be parsed: {}\n\
+                 Reply with a single, valid <tool-call></tool-call> element, \
+                 or answer the user without one.",
+                error
+            )));
+            return Ok(Step::Repair(error));
+        }
+        if tools.get("converse").is_none() {
+            return Err(SupervisorError::ToolCall(error));
+        }
+        tracing::warn!("Giving up on the tool call and conversing instead");
+        Ok(Step::ToolCall(ToolCall::new(
+            "converse",
+            vec![Argument::new("input", "String", &self.input)],
+        )))
+    }
+
+    /// Feed the result of a tool call back to the supervisor
+    pub fn tool_response(&mut self, tool_call: &ToolCall, result: &str) {
+        let content = format!(
+            "<tool_response name=\"{}\">\n{}\n</tool_response>",
+            tool_call.name(),
+            result
+        );
+        self.messages.push(Message::user(content));
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SupervisorError {
+    #[error("engine error: {0}")]
+    Engine(#[from] LlmEngineError),
+    #[error("tool call error: {0}")]
+    ToolCall(#[from] ToolCallError),
+    #[error("reached the maximum depth of {0} steps without a final answer")]
+    MaxDepth(usize),
+}
+
+#[cfg(test)]
+mod test {
+    use async_trait::async_trait;
+
+    use crate::agent::language_model::Role;
+    use crate::agent::tools::{
+        ArgumentSpec, ArgumentType, Arguments, ConverseTool, Retrieved, Tool, ToolError,
+    };
+    use crate::tests::prelude::*;
+
+    use super::*;
+
+    struct Lookup;
+
+    #[async_trait]
+    impl Tool for Lookup {
+        fn name(&self) -> &'static str {
+            "lookup"
+        }
+
+        fn description(&self) -> &'static str {
+            "Look up a fact"
+        }
+
+        fn arguments(&self) -> Vec<ArgumentSpec> {
+            vec![ArgumentSpec::required(
+                "topic",
+                ArgumentType::String,
+                "What to look up",
+            )]
+        }
+
+        async fn execute(&self, args: Arguments) -> Result<ToolOutput, ToolError> {
+            Ok(ToolOutput::Response(format!(
+                "{} are pink",
+                args.require("topic")?
+            )))
+        }
+    }
+
+    const LOOKUP: &str = r#"<tool-call name="lookup">
+    <argument name="topic" type="String" value="blossoms"/>
+</tool-call>"#;
+
+    const CONVERSE: &str = r#"<tool-call name="converse">
+    <argument name="input" type="String" value="Tell the user blossoms are pink"/>
+</tool-call>"#;
+
+    fn tools(engine: &LlmEngine) -> ToolRegistry {
+        let mut tools = ToolRegistry::new();
+        tools.register(Lookup);
+        tools.register(ConverseTool::new(engine.clone(), Retrieved::default()));
+        tools
+    }
+
+    fn chats(mock: &MockLanguageModel) -> Vec<Vec<Message>> {
+        mock.requests()
+            .into_iter()
+            .filter_map(|request| match request {
+                MockRequest::Chat { messages, .. } => Some(messages),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_run_answer() {
+        let mock = MockLanguageModel::new().chat_response("  Hello!  ");
+        let engine = mock.engine();
+
+        let mut supervisor = Supervisor::new("hi", &[]);
+        supervisor
+            .run(&engine, &tools(&engine), |_| {})
+            .await
+            .unwrap();
+        assert_eq!(
+            supervisor.outcomes(),
+            &[Outcome::Answer {
+                tool_call: None,
+                answer: "Hello!".to_string()
+            }]
+        );
+
+        let chats = chats(&mock);
+        assert_eq!(chats.len(), 1);
+        assert_eq!(chats[0][0].role(), Role::System);
+        assert!(chats[0][0].content().contains("<tool name=\"lookup\">"));
+        assert_eq!(chats[0][1], Message::user("hi"));
+    }
+
+    #[tokio::test]
+    async fn test_run_feeds_tool_responses_back() {
+        let mock = MockLanguageModel::new()
+            .chat_response(LOOKUP)
+            .chat_response(CONVERSE)
+            .completion_response("Blossoms are pink!");
+        let engine = mock.engine();
+
+        let mut events = Vec::new();
+        let mut supervisor = Supervisor::new("what color are blossoms?", &[]);
+        supervisor
+            .run(&engine, &tools(&engine), |event| match event {
+                Event::Calling { depth, tool_call } => {
+                    events.push(format!("{} {}", depth, tool_call.name()))
+                }
+                Event::Repairing { .. } => events.push("repairing".to_string()),
+                Event::Finished(_) => events.push("finished".to_string()),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            events,
+            vec!["1 lookup", "finished", "2 converse", "finished"]
+        );
+
+        let outcomes = supervisor.outcomes();
+        assert_eq!(outcomes.len(), 2);
+        assert!(matches!(
+            &outcomes[0],
+            Outcome::Response { tool_call, response }
+                if tool_call.name() == "lookup" && response == "blossoms are pink"
+        ));
+        assert!(matches!(
+            &outcomes[1],
+            Outcome::Answer { tool_call: Some(tool_call), answer }
+                if tool_call.name() == "converse" && answer == "Blossoms are pink!"
+        ));
+
+        // The second supervisor call sees the lookup result
+        let chats = chats(&mock);
+        assert_eq!(chats.len(), 2);
+        let tool_response = chats[1].last().unwrap();
+        assert_eq!(tool_response.role(), Role::User);
+        assert!(tool_response
+            .content()
+            .starts_with("<tool_response name=\"lookup\">"));
+        assert!(tool_response.content().contains("blossoms are pink"));
+
+        assert!(matches!(
+            mock.requests().last(),
+            Some(MockRequest::ChatStream { model, messages })
+                if model == "conversational"
+                    && messages.last() == Some(&Message::user("Tell the user blossoms are pink"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_run_reports_tool_failures() {
+        let mock = MockLanguageModel::new()
+            .chat_response(r#"<tool-call name="missing"><argument name="a" type="String" value="b"/></tool-call>"#)
+            .chat_response("I can't do that");
+        let engine = mock.engine();
+
+        let mut supervisor = Supervisor::new("hi", &[]);
+        supervisor
+            .run(&engine, &tools(&engine), |_| {})
+            .await
+            .unwrap();
+
+        let outcomes = supervisor.outcomes();
+        assert!(matches!(
+            &outcomes[0],
+            Outcome::Failed { error, .. } if error.contains("unknown tool: missing")
+        ));
+        assert!(supervisor.messages()[2]
+            .content()
+            .contains("unknown tool: missing"));
+    }
+
+    #[tokio::test]
+    async fn test_run_max_depth() {
+        let mut mock = MockLanguageModel::new();
+        for _ in 0..=MAX_DEPTH {
+            mock = mock.chat_response(LOOKUP);
+        }
+        let engine = mock.engine();
+
+        let mut supervisor = Supervisor::new("hi", &[]);
+        let result = supervisor.run(&engine, &tools(&engine), |_| {}).await;
+        assert!(matches!(result, Err(SupervisorError::MaxDepth(MAX_DEPTH))));
+        assert_eq!(supervisor.depth(), MAX_DEPTH);
+        assert_eq!(supervisor.outcomes().len(), MAX_DEPTH);
+    }
+
+    const BROKEN: &str = r#"<tool-call name="lookup"><argument name=></tool-call>"#;
+
+    #[tokio::test]
+    async fn test_run_repairs_tool_calls() {
+        let mock = MockLanguageModel::new()
+            .chat_response(BROKEN)
+            .chat_response(&format!("Let me check.\n```xml\n{}\n```", LOOKUP))
+            .chat_response("They're pink");
+        let engine = mock.engine();
+
+        let mut repairs = Vec::new();
+        let mut supervisor = Supervisor::new("what color are blossoms?", &[]);
+        supervisor
+            .run(&engine, &tools(&engine), |event| {
+                if let Event::Repairing { attempt, .. } = event {
+                    repairs.push(attempt);
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(repairs, vec![1]);
+        assert_eq!(supervisor.outcomes().len(), 2);
+
+        // The supervisor was shown what was wrong with its first attempt
+        let chats = chats(&mock);
+        let repair = &chats[1].last().unwrap();
+        assert_eq!(repair.role(), Role::User);
+        assert!(repair
+            .content()
+            .starts_with("Your tool call could not be parsed"));
+    }
+
+    #[tokio::test]
+    async fn test_run_falls_back_to_converse() {
+        let mut mock = MockLanguageModel::new();
+        for _ in 0..=DEFAULT_TOOL_CALL_REPAIRS {
+            mock = mock.chat_response(BROKEN);
+        }
+        let mock = mock.completion_response("Blossoms are pink!");
+        let engine = mock.engine();
+
+        let mut supervisor = Supervisor::new("what color are blossoms?", &[]);
+        supervisor
+            .run(&engine, &tools(&engine), |_| {})
+            .await
+            .unwrap();
+        assert!(matches!(
+            supervisor.outcomes(),
+            [Outcome::Answer { tool_call: Some(tool_call), answer }]
+                if tool_call.name() == "converse" && answer == "Blossoms are pink!"
+        ));
+        assert_eq!(chats(&mock).len(), DEFAULT_TOOL_CALL_REPAIRS + 1);
+        assert!(matches!(
+            mock.requests().last(),
+            Some(MockRequest::ChatStream { messages, .. })
+                if messages.last() == Some(&Message::user("what color are blossoms?"))
+        ));
+
+        // Without anything to fall back on, the parse error is reported
+        let mock = MockLanguageModel::new().chat_response(BROKEN);
+        let engine = mock.engine().with_tool_call_repairs(0);
+        let mut tools = ToolRegistry::new();
+        tools.register(Lookup);
+        let result = Supervisor::new("hi", &[])
+            .run(&engine, &tools, |_| {})
+            .await;
+        assert!(matches!(result, Err(SupervisorError::ToolCall(_))));
+    }
+
+    #[tokio::test]
+    async fn test_run_repairs_within_max_depth() {
+        let mut mock = MockLanguageModel::new();
+        for _ in 0..MAX_DEPTH {
+            mock = mock.chat_response(BROKEN);
+        }
+        let mock = mock.completion_response("Blossoms are pink!");
+        let engine = mock.engine().with_tool_call_repairs(MAX_DEPTH);
+
+        let mut repairs = Vec::new();
+        let mut supervisor = Supervisor::new("what color are blossoms?", &[]);
+        supervisor
+            .run(&engine, &tools(&engine), |event| {
+                if let Event::Repairing { attempt, .. } = event {
+                    repairs.push(attempt);
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(repairs, (1..MAX_DEPTH).collect::<Vec<_>>());
+        assert_eq!(supervisor.depth(), MAX_DEPTH);
+        assert!(matches!(
+            supervisor.outcomes(),
+            [Outcome::Answer { tool_call: Some(tool_call), answer }]
+                if tool_call.name() == "converse" && answer == "Blossoms are pink!"
+        ));
+        assert!(matches!(
+            mock.requests().last(),
+            Some(MockRequest::ChatStream { messages, .. })
+                if messages.last() == Some(&Message::user("what color are blossoms?"))
+        ));
+    }
+
+    #[test]
+    fn test_new_lists_images() {
+        let supervisor = Supervisor::new("what is this?", &[PathBuf::from("data/blossom.jpg")]);
+        assert_eq!(
+            supervisor.messages()[0].content(),
+            "what is this?\n<images>\n  <image path=\"data/blossom.jpg\"/>\n</images>"
+        );
+    }
+}
@@ -14,6 +14,14 @@ pub struct Argument {
 }
 
 impl Argument {
+    pub fn new(name: &str, r#type: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            r#type: r#type.to_string(),
+            value: value.to_string(),
+        }
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -30,29 +38,103 @@ impl Argument {
 pub struct ToolCall {
     #[serde(rename = "@name")]
     name: String,
+    #[serde(default)]
     argument: Vec<Argument>,
 }
 
 impl ToolCall {
+    pub fn new(name: &str, arguments: Vec<Argument>) -> Self {
+        Self {
+            name: name.to_string(),
+            argument: arguments,
+        }
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
     pub fn args(&self) -> &[Argument] {
         &self.argument
     }
+
+    /// The arguments as a JSON object of names to values, for storing alongside the call
+    pub fn arguments_json(&self) -> String {
+        let arguments = self
+            .argument
+            .iter()
+            .map(|argument| (argument.name.clone(), argument.value.clone().into()))
+            .collect::<serde_json::Map<String, serde_json::Value>>();
+        serde_json::Value::Object(arguments).to_string()
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum ToolCallError {
     #[error("Parse error: {0}")]
     ParseError(#[from] quick_xml::de::DeError),
+    #[error("No <tool-call> element found")]
+    NotFound,
 }
 
+/// Parses the first `<tool-call>` element found in a model's response,
+/// whatever text, code fences or XML declaration surround it
 impl TryFrom<&str> for ToolCall {
     type Error = ToolCallError;
-    fn try_from(xml: &str) -> Result<Self, Self::Error> {
-        from_str(xml).map_err(ToolCallError::ParseError)
+    fn try_from(text: &str) -> Result<Self, Self::Error> {
+        let xml = extract(text).ok_or(ToolCallError::NotFound)?;
+        from_str(&escape_ampersands(xml)).map_err(ToolCallError::ParseError)
+    }
+}
+
+const OPEN: &str = "<tool-call";
+const CLOSE: &str = "</tool-call>";
+
+/// The `<tool-call>` element within some text, closing it if the model stopped short
+fn extract(text: &str) -> Option<String> {
+    let start = text.find(OPEN)?;
+    let element = &text[start..];
+    if let Some(end) = element.find(CLOSE) {
+        return Some(element[..end + CLOSE.len()].to_string());
+    }
+    let open_end = tag_end(element)?;
+    if element[..open_end].ends_with('/') {
+        return Some(element[..=open_end].to_string());
+    }
+    // Keep whatever complete tags made it out and close the element ourselves
+    let last = element.rfind('>')?;
+    Some(format!("{}\n{}", &element[..=last], CLOSE))
+}
+
+/// The index of the `>` ending the tag at the start of `text`, skipping over quoted values
+fn tag_end(text: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Escape any `&` which doesn't already start an entity, as models rarely do
+fn escape_ampersands(xml: String) -> String {
+    let mut escaped = String::with_capacity(xml.len());
+    for (i, piece) in xml.split('&').enumerate() {
+        if i > 0 {
+            let is_entity = piece.split_once(';').is_some_and(|(name, _)| {
+                matches!(name, "amp" | "lt" | "gt" | "quot" | "apos")
+                    || name.strip_prefix('#').is_some_and(|code| {
+                        !code.is_empty() && code.chars().all(|c| c.is_ascii_alphanumeric())
+                    })
+            });
+            escaped.push_str(if is_entity { "&" } else { "&amp;" });
+        }
+        escaped.push_str(piece);
     }
+    escaped
 }
 
 #[cfg(test)]
@@ -80,4 +162,60 @@ mod test {
             }
         );
     }
+
+    #[test]
+    fn test_arguments_json() {
+        let tool_call = ToolCall::new(
+            "search",
+            vec![
+                Argument::new("query", "String", r#"a "quoted" \ path"#),
+                Argument::new("title", "String", "桜 🌸 café"),
+            ],
+        );
+        let json = tool_call.arguments_json();
+        assert_eq!(
+            json,
+            r#"{"query":"a \"quoted\" \\ path","title":"桜 🌸 café"}"#
+        );
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed["query"], r#"a "quoted" \ path"#);
+        assert_eq!(parsed["title"], "桜 🌸 café");
+    }
+
+    #[test]
+    fn test_tolerates_surroundings() {
+        let expected = ToolCall::new(
+            "search",
+            vec![Argument::new("query", "String", "cats & dogs")],
+        );
+        let responses = [
+            // Chatter and a code fence around the call
+            "Sure, let me look that up.\n```xml\n<?xml version='1.0'?>\n<tool-call name=\"search\">\n  <argument name=\"query\" type=\"String\" value=\"cats &amp; dogs\"/>\n</tool-call>\n```\nOne moment!",
+            // Single quotes and an unescaped ampersand
+            "<tool-call name='search'><argument name='query' type='String' value='cats & dogs'/></tool-call>",
+            // Cut off before the closing tag
+            "<tool-call name=\"search\">\n  <argument name=\"query\" type=\"String\" value=\"cats & dogs\"/>\n```",
+        ];
+        for response in responses {
+            assert_eq!(
+                ToolCall::try_from(response).unwrap(),
+                expected,
+                "{}",
+                response
+            );
+        }
+
+        assert_eq!(
+            ToolCall::try_from("Calling <tool-call name=\"branches\"/> now").unwrap(),
+            ToolCall::new("branches", Vec::new())
+        );
+        assert!(matches!(
+            ToolCall::try_from("no call here"),
+            Err(ToolCallError::NotFound)
+        ));
+        assert!(matches!(
+            ToolCall::try_from("<tool-call name=\"search\"><argument name=></tool-call>"),
+            Err(ToolCallError::ParseError(_))
+        ));
+    }
 }
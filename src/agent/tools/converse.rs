@@ -0,0 +1,186 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+
+use super::{
+    stream_to_stdout, ArgumentSpec, ArgumentType, Arguments, Retrieved, Tool, ToolError, ToolOutput,
+};
+use crate::agent::language_model::Context;
+use crate::agent::llm_engine::{Conversation, LlmEngine};
+
+/// The conversation carried on by the converse tool, shared so it can be
+/// brought up to date between turns
+#[derive(Debug, Clone, Default)]
+pub struct SharedConversation(Arc<Mutex<Conversation>>);
+
+impl SharedConversation {
+    pub fn new(conversation: Conversation) -> Self {
+        Self(Arc::new(Mutex::new(conversation)))
+    }
+
+    pub fn get(&self) -> Conversation {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn set(&self, conversation: Conversation) {
+        *self.0.lock().unwrap() = conversation;
+    }
+
+    fn push(&self, input: &str, response: &str, context: Option<Context>) {
+        self.0.lock().unwrap().push(input, response, context);
+    }
+}
+
+/// Streams a response from the conversational model straight to the user.
+/// Keeps track of the conversation so follow up turns carry over,
+/// and hands any documents retrieved earlier in the turn to the model.
+pub struct ConverseTool {
+    engine: LlmEngine,
+    retrieved: Retrieved,
+    conversation: SharedConversation,
+}
+
+impl ConverseTool {
+    pub fn new(engine: LlmEngine, retrieved: Retrieved) -> Self {
+        Self {
+            engine,
+            retrieved,
+            conversation: SharedConversation::default(),
+        }
+    }
+
+    /// Carry on a conversation kept up to date elsewhere, such as from the database
+    pub fn with_conversation(mut self, conversation: SharedConversation) -> Self {
+        self.conversation = conversation;
+        self
+    }
+}
+
+#[async_trait]
+impl Tool for ConverseTool {
+    fn name(&self) -> &'static str {
+        "converse"
+    }
+
+    fn description(&self) -> &'static str {
+        "Continue a conversation based on input"
+    }
+
+    fn arguments(&self) -> Vec<ArgumentSpec> {
+        vec![ArgumentSpec::required(
+            "input",
+            ArgumentType::String,
+            "The message to respond to",
+        )]
+    }
+
+    async fn execute(&self, args: Arguments) -> Result<ToolOutput, ToolError> {
+        let input = args.require("input")?;
+        let documents = self.retrieved.take();
+        let conversation = self.conversation.get();
+
+        // Complete on the response to std out
+        let stream = self
+            .engine
+            .converse(input, &documents, &conversation)
+            .await?;
+        let (response, final_context) = stream_to_stdout(stream).await?;
+        self.conversation.push(input, &response, final_context);
+
+        Ok(ToolOutput::Final(response))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::agent::language_model::Message;
+    use crate::agent::llm_engine::ConversationMemory;
+    use crate::tests::prelude::*;
+    use crate::vector_store::Passage;
+
+    use super::*;
+
+    fn converse_call(input: &str) -> Arguments {
+        let specs = vec![ArgumentSpec::required("input", ArgumentType::String, "")];
+        let tool_call = crate::agent::ToolCall::try_from(
+            format!(r#"<tool-call name="converse"><argument name="input" type="String" value="{}"/></tool-call>"#, input)
+                .as_str(),
+        )
+        .unwrap();
+        Arguments::validate(&specs, tool_call.args()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_execute_carries_history_and_documents() {
+        let mock = MockLanguageModel::new()
+            .completion_response("First answer")
+            .completion_response("Second answer");
+        let retrieved = Retrieved::default();
+        let earlier = Conversation::new(vec![Message::user("hi"), Message::assistant("hello")]);
+        let tool = ConverseTool::new(mock.engine(), retrieved.clone())
+            .with_conversation(SharedConversation::new(earlier));
+
+        retrieved.extend(vec![Passage {
+            id: "0".to_string(),
+            text: "a relevant passage".to_string(),
+            source: None,
+        }]);
+        let output = tool.execute(converse_call("first")).await.unwrap();
+        assert_eq!(output, ToolOutput::Final("First answer".to_string()));
+        assert_eq!(retrieved.cited().len(), 1);
+        let output = tool.execute(converse_call("second")).await.unwrap();
+        assert_eq!(output, ToolOutput::Final("Second answer".to_string()));
+
+        let requests = mock.requests();
+        let MockRequest::ChatStream { messages, .. } = &requests[0] else {
+            panic!("expected a streamed chat, got {:?}", requests[0]);
+        };
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[1], Message::user("hi"));
+        assert!(messages[3].content().contains("a relevant passage"));
+        assert!(messages[3].content().ends_with("first"));
+
+        // Documents are only shown to the model for the turn they were retrieved in
+        let MockRequest::ChatStream { messages, .. } = &requests[1] else {
+            panic!("expected a streamed chat, got {:?}", requests[1]);
+        };
+        assert_eq!(
+            messages[1..],
+            [
+                Message::user("hi"),
+                Message::assistant("hello"),
+                Message::user("first"),
+                Message::assistant("First answer"),
+                Message::user("second"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_execute_carries_context() {
+        let mock = MockLanguageModel::new()
+            .completion_response("First answer")
+            .completion_response("Second answer");
+        let engine = mock
+            .engine()
+            .with_conversation_memory(ConversationMemory::Context);
+        let tool = ConverseTool::new(engine, Retrieved::default());
+
+        tool.execute(converse_call("first")).await.unwrap();
+        tool.execute(converse_call("second")).await.unwrap();
+
+        let requests = mock.requests();
+        assert!(matches!(
+            &requests[0],
+            MockRequest::Complete { context: None, .. }
+        ));
+        assert_eq!(
+            requests[1],
+            MockRequest::Complete {
+                model: "conversational".to_string(),
+                prompt: "second".to_string(),
+                context: Some(Context::from(vec![1])),
+            }
+        );
+    }
+}
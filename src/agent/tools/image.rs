@@ -0,0 +1,122 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use image::ImageFormat;
+
+use super::{stream_to_stdout, ArgumentSpec, ArgumentType, Arguments, Tool, ToolError, ToolOutput};
+use crate::agent::llm_engine::LlmEngine;
+
+const DEFAULT_QUESTION: &str = "Please analyze this image";
+
+/// Whether the file at `path` looks like an image the image model can read
+pub fn is_image(path: &Path) -> bool {
+    ImageFormat::from_path(path).is_ok()
+}
+
+/// The images attached to a chat, which are the only files the image tool may read
+#[derive(Debug, Clone, Default)]
+pub struct AttachedImages(Arc<Mutex<Vec<PathBuf>>>);
+
+impl AttachedImages {
+    pub fn set(&self, paths: Vec<PathBuf>) {
+        *self.0.lock().unwrap() = paths;
+    }
+
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Whether `path` is one of the images, however it is spelled
+    fn contains(&self, path: &Path) -> bool {
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.0.lock().unwrap().contains(&path)
+    }
+}
+
+/// Answers a question about an image, streaming the analysis straight to the user
+pub struct ImageTool {
+    engine: LlmEngine,
+    images: AttachedImages,
+}
+
+impl ImageTool {
+    pub fn new(engine: LlmEngine, images: AttachedImages) -> Self {
+        Self { engine, images }
+    }
+}
+
+#[async_trait]
+impl Tool for ImageTool {
+    fn name(&self) -> &'static str {
+        "image"
+    }
+
+    fn description(&self) -> &'static str {
+        "Answer a question about an image attached to this chat"
+    }
+
+    fn arguments(&self) -> Vec<ArgumentSpec> {
+        vec![
+            ArgumentSpec::required("path", ArgumentType::PathBuf, "The path to the image"),
+            ArgumentSpec::optional(
+                "question",
+                ArgumentType::String,
+                "What the user wants to know about the image",
+            ),
+        ]
+    }
+
+    async fn execute(&self, args: Arguments) -> Result<ToolOutput, ToolError> {
+        let path = PathBuf::from(args.require("path")?.trim());
+        if !self.images.contains(&path) {
+            return Err(ToolError::NotAttached(path));
+        }
+        let question = match args.get("question") {
+            Some(question) if !question.trim().is_empty() => question,
+            _ => DEFAULT_QUESTION,
+        };
+
+        let stream = self.engine.image(&path, question).await?;
+        let (response, _) = stream_to_stdout(stream).await?;
+        Ok(ToolOutput::Final(response))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::agent::tool_call::Argument;
+    use crate::tests::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_is_image() {
+        assert!(is_image(Path::new("data/blossom.jpg")));
+        assert!(is_image(Path::new("images/1.PNG")));
+        assert!(!is_image(Path::new("data/blossom.txt")));
+        assert!(!is_image(Path::new("data")));
+    }
+
+    #[tokio::test]
+    async fn test_only_reads_attached_images() {
+        let mock = MockLanguageModel::new();
+        let images = AttachedImages::default();
+        let tool = ImageTool::new(mock.engine(), images.clone());
+        let arguments = |path: &str| {
+            Arguments::validate(&tool.arguments(), &[Argument::new("path", "PathBuf", path)])
+                .unwrap()
+        };
+
+        let result = tool.execute(arguments("/etc/passwd")).await;
+        assert!(
+            matches!(result, Err(ToolError::NotAttached(path)) if path == Path::new("/etc/passwd"))
+        );
+        assert!(mock.requests().is_empty());
+
+        let path = std::env::current_dir().unwrap().join("data/blossom.jpg");
+        images.set(vec![path]);
+        assert!(images.contains(Path::new("data/../data/blossom.jpg")));
+        assert!(!images.contains(Path::new("data/other.jpg")));
+    }
+}
@@ -0,0 +1,207 @@
+mod converse;
+mod image;
+mod registry;
+mod search_documents;
+
+use std::fmt::{self, Display, Formatter};
+
+use async_trait::async_trait;
+use tokio::io::{stdout, AsyncWriteExt};
+use tokio_stream::StreamExt;
+
+use super::language_model::{CompletionStream, Context};
+use super::tool_call::Argument;
+
+pub use converse::{ConverseTool, SharedConversation};
+pub use image::{is_image, AttachedImages, ImageTool};
+pub use registry::ToolRegistry;
+pub use search_documents::{Retrieved, SearchDocumentsTool};
+
+/// The types a tool argument may be declared with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgumentType {
+    String,
+    PathBuf,
+    Integer,
+}
+
+impl ArgumentType {
+    /// Check that a raw argument value can be interpreted as this type
+    fn validate(&self, value: &str) -> bool {
+        match self {
+            ArgumentType::String => true,
+            ArgumentType::PathBuf => !value.trim().is_empty(),
+            ArgumentType::Integer => value.trim().parse::<i64>().is_ok(),
+        }
+    }
+}
+
+impl Display for ArgumentType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let r#type = match self {
+            ArgumentType::String => "String",
+            ArgumentType::PathBuf => "PathBuf",
+            ArgumentType::Integer => "Integer",
+        };
+        write!(f, "{}", r#type)
+    }
+}
+
+/// Describes a single argument a tool accepts
+#[derive(Debug, Clone)]
+pub struct ArgumentSpec {
+    name: &'static str,
+    r#type: ArgumentType,
+    description: &'static str,
+    required: bool,
+}
+
+impl ArgumentSpec {
+    pub fn required(name: &'static str, r#type: ArgumentType, description: &'static str) -> Self {
+        Self {
+            name,
+            r#type,
+            description,
+            required: true,
+        }
+    }
+
+    pub fn optional(name: &'static str, r#type: ArgumentType, description: &'static str) -> Self {
+        Self {
+            name,
+            r#type,
+            description,
+            required: false,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.name
+    }
+
+    pub fn r#type(&self) -> ArgumentType {
+        self.r#type
+    }
+
+    pub fn description(&self) -> &str {
+        self.description
+    }
+
+    pub fn is_required(&self) -> bool {
+        self.required
+    }
+}
+
+/// Validated arguments passed to a tool's `execute`
+#[derive(Debug, Clone)]
+pub struct Arguments(Vec<Argument>);
+
+impl Arguments {
+    /// Check raw tool call arguments against a tool's argument schema
+    pub fn validate(specs: &[ArgumentSpec], args: &[Argument]) -> Result<Self, ToolError> {
+        for arg in args {
+            let spec = specs
+                .iter()
+                .find(|spec| spec.name() == arg.name())
+                .ok_or_else(|| ToolError::UnknownArgument(arg.name().to_string()))?;
+            if !spec.r#type().validate(arg.value()) {
+                return Err(ToolError::InvalidArgument {
+                    name: arg.name().to_string(),
+                    r#type: spec.r#type(),
+                    value: arg.value().to_string(),
+                });
+            }
+        }
+        for spec in specs.iter().filter(|spec| spec.is_required()) {
+            if !args.iter().any(|arg| arg.name() == spec.name()) {
+                return Err(ToolError::MissingArgument(spec.name().to_string()));
+            }
+        }
+        Ok(Self(args.to_vec()))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|arg| arg.name() == name)
+            .map(|arg| arg.value())
+    }
+
+    pub fn require(&self, name: &str) -> Result<&str, ToolError> {
+        self.get(name)
+            .ok_or_else(|| ToolError::MissingArgument(name.to_string()))
+    }
+}
+
+/// What a tool produced once it finished executing
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolOutput {
+    /// A result to feed back to the supervisor within `<tool_response>` tags
+    Response(String),
+    /// A final answer that has already been shown to the user, ending the agent loop
+    Final(String),
+}
+
+/// A capability the supervisor can invoke with a `<tool-call>`
+#[async_trait]
+pub trait Tool: Send + Sync {
+    /// The name the supervisor uses to call this tool
+    fn name(&self) -> &'static str;
+
+    /// A short description of what the tool does, shown to the supervisor
+    fn description(&self) -> &'static str;
+
+    /// The arguments this tool accepts
+    fn arguments(&self) -> Vec<ArgumentSpec>;
+
+    async fn execute(&self, args: Arguments) -> Result<ToolOutput, ToolError>;
+}
+
+/// Write a streamed generation to std out as it arrives.
+/// Returns the full response along with the final generation context, if any.
+async fn stream_to_stdout(
+    mut stream: CompletionStream,
+) -> Result<(String, Option<Context>), ToolError> {
+    let mut stdout = stdout();
+    let mut response = String::new();
+    let mut context = None;
+    while let Some(completion) = stream.next().await {
+        let completion = completion?;
+        response.push_str(&completion.text);
+        stdout.write_all(completion.text.as_bytes()).await?;
+        stdout.flush().await?;
+
+        if completion.context.is_some() {
+            context = completion.context;
+        }
+    }
+    stdout.write_all(b"\n").await?;
+    stdout.flush().await?;
+    Ok((response, context))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ToolError {
+    #[error("unknown tool: {0}")]
+    UnknownTool(String),
+    #[error("unknown argument: {0}")]
+    UnknownArgument(String),
+    #[error("missing required argument: {0}")]
+    MissingArgument(String),
+    #[error("argument `{name}` expects a value of type `{r#type}`, got: {value}")]
+    InvalidArgument {
+        name: String,
+        r#type: ArgumentType,
+        value: String,
+    },
+    #[error("engine error: {0}")]
+    Engine(#[from] super::llm_engine::LlmEngineError),
+    #[error("language model error: {0}")]
+    LanguageModel(#[from] super::language_model::LanguageModelError),
+    #[error("retrieval error: {0}")]
+    Retrieval(#[from] crate::retrieval::RetrievalError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0} is not an image attached to this chat")]
+    NotAttached(std::path::PathBuf),
+}
@@ -0,0 +1,166 @@
+use super::{Arguments, Tool, ToolError, ToolOutput};
+use crate::agent::tool_call::ToolCall;
+
+/// The set of tools available to the supervisor.
+/// Responsible for both dispatching tool calls and describing
+/// the available tools within the supervisor's system prompt.
+#[derive(Default)]
+pub struct ToolRegistry {
+    tools: Vec<Box<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a tool, replacing any tool already registered under the same name
+    pub fn register(&mut self, tool: impl Tool + 'static) {
+        self.tools.retain(|t| t.name() != tool.name());
+        self.tools.push(Box::new(tool));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Tool> {
+        self.tools
+            .iter()
+            .find(|tool| tool.name() == name)
+            .map(|tool| tool.as_ref())
+    }
+
+    /// Render the `<tools>` block describing every registered tool
+    pub fn prompt(&self) -> String {
+        let mut prompt = String::from("<tools>\n");
+        for tool in self.tools.iter() {
+            let arguments = tool.arguments();
+            let signature = arguments
+                .iter()
+                .map(|arg| format!("{}: {}", arg.name(), arg.r#type()))
+                .collect::<Vec<_>>()
+                .join(", ");
+            prompt.push_str(&format!("  <tool name=\"{}\">\n", tool.name()));
+            prompt.push_str(&format!(
+                "    <description>{}({}) - {}</description>\n",
+                tool.name(),
+                signature,
+                tool.description()
+            ));
+            for arg in arguments.iter() {
+                prompt.push_str(&format!(
+                    "    <argument name=\"{}\" type=\"{}\" required=\"{}\" description=\"{}\" value=\"{}\"/>\n",
+                    arg.name(),
+                    arg.r#type(),
+                    arg.is_required(),
+                    arg.description(),
+                    arg.name()
+                ));
+            }
+            prompt.push_str("  </tool>\n");
+        }
+        prompt.push_str("</tools>");
+        prompt
+    }
+
+    /// Validate a tool call against the matching tool's schema and execute it
+    pub async fn dispatch(&self, tool_call: &ToolCall) -> Result<ToolOutput, ToolError> {
+        let tool = self
+            .get(tool_call.name())
+            .ok_or_else(|| ToolError::UnknownTool(tool_call.name().to_string()))?;
+        let args = Arguments::validate(&tool.arguments(), tool_call.args())?;
+        tool.execute(args).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::agent::tools::{ArgumentSpec, ArgumentType};
+
+    struct Echo;
+
+    #[async_trait]
+    impl Tool for Echo {
+        fn name(&self) -> &'static str {
+            "echo"
+        }
+
+        fn description(&self) -> &'static str {
+            "Repeat the input back"
+        }
+
+        fn arguments(&self) -> Vec<ArgumentSpec> {
+            vec![
+                ArgumentSpec::required("input", ArgumentType::String, "The text to repeat"),
+                ArgumentSpec::optional("times", ArgumentType::Integer, "How many times"),
+            ]
+        }
+
+        async fn execute(&self, args: Arguments) -> Result<ToolOutput, ToolError> {
+            let input = args.require("input")?;
+            let times = args
+                .get("times")
+                .map(|times| times.parse::<usize>().unwrap())
+                .unwrap_or(1);
+            Ok(ToolOutput::Response(input.repeat(times)))
+        }
+    }
+
+    fn registry() -> ToolRegistry {
+        let mut registry = ToolRegistry::new();
+        registry.register(Echo);
+        registry
+    }
+
+    #[test]
+    fn test_prompt() {
+        let prompt = registry().prompt();
+        assert!(prompt.starts_with("<tools>"));
+        assert!(prompt.ends_with("</tools>"));
+        assert!(prompt.contains("<tool name=\"echo\">"));
+        assert!(prompt.contains("echo(input: String, times: Integer) - Repeat the input back"));
+        assert!(prompt.contains("<argument name=\"times\" type=\"Integer\" required=\"false\""));
+    }
+
+    #[tokio::test]
+    async fn test_dispatch() {
+        let tool_call = ToolCall::try_from(
+            r#"<tool-call name="echo">
+                <argument name="input" type="String" value="ab"/>
+                <argument name="times" type="Integer" value="2"/>
+            </tool-call>"#,
+        )
+        .unwrap();
+        let output = registry().dispatch(&tool_call).await.unwrap();
+        assert_eq!(output, ToolOutput::Response("abab".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_errors() {
+        let registry = registry();
+
+        let unknown_tool = ToolCall::try_from(
+            r#"<tool-call name="nope"><argument name="input" type="String" value="x"/></tool-call>"#,
+        )
+        .unwrap();
+        let err = registry.dispatch(&unknown_tool).await.unwrap_err();
+        assert!(matches!(err, ToolError::UnknownTool(name) if name == "nope"));
+
+        let missing = ToolCall::try_from(
+            r#"<tool-call name="echo"><argument name="times" type="Integer" value="2"/></tool-call>"#,
+        )
+        .unwrap();
+        let err = registry.dispatch(&missing).await.unwrap_err();
+        assert!(matches!(err, ToolError::MissingArgument(name) if name == "input"));
+
+        let invalid = ToolCall::try_from(
+            r#"<tool-call name="echo">
+                <argument name="input" type="String" value="x"/>
+                <argument name="times" type="Integer" value="many"/>
+            </tool-call>"#,
+        )
+        .unwrap();
+        let err = registry.dispatch(&invalid).await.unwrap_err();
+        assert!(matches!(err, ToolError::InvalidArgument { .. }));
+    }
+}
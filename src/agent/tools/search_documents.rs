@@ -0,0 +1,195 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+
+use super::{ArgumentSpec, ArgumentType, Arguments, Tool, ToolError, ToolOutput};
+use crate::retrieval::Retriever;
+use crate::vector_store::Passage;
+
+const DEFAULT_LIMIT: usize = 3;
+
+#[derive(Debug, Default)]
+struct Passages {
+    pending: Vec<Passage>,
+    cited: Vec<Passage>,
+}
+
+/// Passages retrieved during a turn, waiting to be handed to the conversational model.
+/// Once handed over they are kept as the turn's citations.
+#[derive(Debug, Clone, Default)]
+pub struct Retrieved(Arc<Mutex<Passages>>);
+
+impl Retrieved {
+    pub fn extend(&self, passages: impl IntoIterator<Item = Passage>) {
+        self.0.lock().unwrap().pending.extend(passages);
+    }
+
+    /// Take all pending passages, remembering them as cited
+    pub fn take(&self) -> Vec<Passage> {
+        let mut passages = self.0.lock().unwrap();
+        let pending = std::mem::take(&mut passages.pending);
+        passages.cited = pending.clone();
+        pending
+    }
+
+    /// The passages handed to the conversational model this turn, in the order they were numbered
+    pub fn cited(&self) -> Vec<Passage> {
+        self.0.lock().unwrap().cited.clone()
+    }
+
+    /// Forget everything from the previous turn
+    pub fn clear(&self) {
+        *self.0.lock().unwrap() = Passages::default();
+    }
+}
+
+/// Searches the documents attached to a chat for passages relevant to a query,
+/// by keyword and by meaning
+pub struct SearchDocumentsTool {
+    retriever: Retriever,
+    collection_name: String,
+    retrieved: Retrieved,
+}
+
+impl SearchDocumentsTool {
+    pub fn new(retriever: Retriever, collection_name: String, retrieved: Retrieved) -> Self {
+        Self {
+            retriever,
+            collection_name,
+            retrieved,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for SearchDocumentsTool {
+    fn name(&self) -> &'static str {
+        "search_documents"
+    }
+
+    fn description(&self) -> &'static str {
+        "Search the documents attached to this chat for passages relevant to a query. \
+         Matching passages are passed along to `converse`"
+    }
+
+    fn arguments(&self) -> Vec<ArgumentSpec> {
+        vec![
+            ArgumentSpec::required("query", ArgumentType::String, "What to search for"),
+            ArgumentSpec::optional(
+                "limit",
+                ArgumentType::Integer,
+                "The maximum number of passages to return",
+            ),
+        ]
+    }
+
+    async fn execute(&self, args: Arguments) -> Result<ToolOutput, ToolError> {
+        let query = args.require("query")?;
+        let limit = match args.get("limit") {
+            Some(limit) => limit.trim().parse::<usize>().unwrap_or(DEFAULT_LIMIT),
+            None => DEFAULT_LIMIT,
+        };
+
+        let passages = self
+            .retriever
+            .retrieve(&self.collection_name, query, limit)
+            .await?;
+        if passages.is_empty() {
+            return Ok(ToolOutput::Response(format!(
+                "No attached documents matched the query: {}",
+                query
+            )));
+        }
+
+        let mut response = format!("Found {} relevant passages:\n", passages.len());
+        for passage in passages.iter() {
+            let source = match &passage.source {
+                Some(source) => format!(" source=\"{}\"", source),
+                None => String::new(),
+            };
+            response.push_str(&format!(
+                "<document{}>\n{}\n</document>\n",
+                source,
+                passage.text.trim()
+            ));
+        }
+        self.retrieved.extend(passages);
+        Ok(ToolOutput::Response(response))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tests::prelude::*;
+    use crate::vector_store::{Source, SqliteVectorStore, VectorDocument, VectorStore};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_execute() {
+        let database = test_database().await;
+        let vector_store = Arc::new(SqliteVectorStore::new(database.clone()));
+        let documents = ["cherry blossoms are pink", "zzzz"]
+            .iter()
+            .enumerate()
+            .map(|(i, text)| VectorDocument {
+                id: i.to_string(),
+                document: text.to_string(),
+                embedding: mock_embedding(text).into_iter().map(|x| x as f32).collect(),
+                source: Some(Source {
+                    path: "notes.txt".to_string(),
+                    start: 0,
+                    end: text.len(),
+                }),
+            })
+            .collect();
+        vector_store.upsert("chat", documents).await.unwrap();
+
+        let retriever = Retriever::new(database, MockLanguageModel::new().engine(), vector_store);
+        let retrieved = Retrieved::default();
+        let tool = SearchDocumentsTool::new(retriever, "chat".to_string(), retrieved.clone());
+        let tool_call = crate::agent::ToolCall::try_from(
+            r#"<tool-call name="search_documents">
+                <argument name="query" type="String" value="cherry blossoms"/>
+                <argument name="limit" type="Integer" value="1"/>
+            </tool-call>"#,
+        )
+        .unwrap();
+        let args = Arguments::validate(&tool.arguments(), tool_call.args()).unwrap();
+
+        let output = tool.execute(args).await.unwrap();
+        assert!(matches!(
+            output,
+            ToolOutput::Response(response)
+                if response.contains("cherry blossoms are pink")
+                    && response.contains("source=\"notes.txt (bytes 0-24)\"")
+        ));
+        let passages = retrieved.take();
+        assert_eq!(passages.len(), 1);
+        assert_eq!(passages[0].text, "cherry blossoms are pink");
+        assert_eq!(passages[0].source.as_ref().unwrap().path, "notes.txt");
+    }
+
+    fn passage(text: &str) -> Passage {
+        Passage {
+            id: text.to_string(),
+            text: text.to_string(),
+            source: None,
+        }
+    }
+
+    #[test]
+    fn test_retrieved_take() {
+        let retrieved = Retrieved::default();
+        let shared = retrieved.clone();
+        shared.extend(vec![passage("a"), passage("b")]);
+        assert_eq!(retrieved.take(), vec![passage("a"), passage("b")]);
+        assert!(retrieved.take().is_empty());
+        // What was handed over last is remembered as cited until cleared
+        shared.extend(vec![passage("c")]);
+        retrieved.take();
+        assert_eq!(retrieved.cited(), vec![passage("c")]);
+        retrieved.clear();
+        assert!(retrieved.cited().is_empty());
+    }
+}
@@ -1,20 +1,43 @@
 use dotenvy::dotenv;
 use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
 
 use url::Url;
 
+use crate::agent::{ConversationMemory, DEFAULT_CONTEXT_LENGTH, DEFAULT_TOOL_CALL_REPAIRS};
+use crate::chunking::{Chunker, ChunkingStrategy, DEFAULT_MAX_TOKENS, DEFAULT_OVERLAP};
+use crate::vector_store::{CollectionNaming, DEFAULT_CHROMA_DATABASE, DEFAULT_CHROMA_TENANT};
+
 #[derive(Debug)]
 pub struct Config {
     // Database Config
     sqlite_database_url: Url,
 
-    // Chroma Config
+    // Vector Store Config
+    vector_store: VectorStoreKind,
     chroma_database_url: Url,
+    chroma_auth_token: Option<String>,
+    chroma_tenant: String,
+    chroma_database: String,
+    collection_naming: CollectionNaming,
+
+    // Chunking Config
+    chunker: Chunker,
+
+    // Language Model Config
+    llm_backend: LlmBackend,
+    openai_base_url: Url,
+    openai_api_key: Option<String>,
+    conversation_memory: ConversationMemory,
+    prompt_directory: Option<PathBuf>,
+    tool_call_repairs: usize,
 
     // Ollama Config
     ollama_server_url: Url,
     ollama_supervisor_model: String,
     ollama_conversational_model: String,
+    ollama_conversational_context_length: usize,
     ollama_image_model: String,
     ollama_embedding_model: String,
 }
@@ -35,15 +58,95 @@ impl Config {
         };
         let sqlite_database_url = Url::parse(&sqlite_database_url_str)?;
 
+        let vector_store = match env::var("VECTOR_STORE") {
+            Ok(kind) => kind.parse()?,
+            Err(_) => {
+                tracing::warn!("No VECTOR_STORE found in .env, using default");
+                VectorStoreKind::Sqlite
+            }
+        };
+
         let chroma_database_url_str = match env::var("CHROMA_DATABASE_URL") {
             Ok(url) => url,
             Err(_) => {
-                tracing::warn!("No CHROMA_DATABASE_URL found in .env, using default");
+                if vector_store == VectorStoreKind::Chroma {
+                    tracing::warn!("No CHROMA_DATABASE_URL found in .env, using default");
+                }
                 "http://localhost:8000".to_string()
             }
         };
         let chroma_database_url = Url::parse(&chroma_database_url_str)?;
 
+        let chroma_auth_token = env::var("CHROMA_AUTH_TOKEN").ok();
+
+        let chroma_tenant =
+            env::var("CHROMA_TENANT").unwrap_or_else(|_| DEFAULT_CHROMA_TENANT.to_string());
+
+        let chroma_database =
+            env::var("CHROMA_DATABASE").unwrap_or_else(|_| DEFAULT_CHROMA_DATABASE.to_string());
+
+        let collection_naming = match env::var("CHROMA_COLLECTION_NAME") {
+            Ok(template) => {
+                if !template.contains("{chat_id}") && !template.contains("{chat_name}") {
+                    tracing::warn!(
+                        "CHROMA_COLLECTION_NAME has no {{chat_id}} or {{chat_name}}, collections will be named {}-<chat id>",
+                        template
+                    );
+                }
+                CollectionNaming::new(&template)
+            }
+            Err(_) => CollectionNaming::default(),
+        };
+
+        let chunk_max_tokens = match env::var("CHUNK_MAX_TOKENS") {
+            Ok(max_tokens) => max_tokens.trim().parse()?,
+            Err(_) => DEFAULT_MAX_TOKENS,
+        };
+        let chunk_overlap = match env::var("CHUNK_OVERLAP") {
+            Ok(overlap) => overlap.trim().parse()?,
+            Err(_) => DEFAULT_OVERLAP,
+        };
+        let mut chunker = Chunker::new(chunk_max_tokens, chunk_overlap);
+        // Without a strategy, one is picked for each file from its extension
+        if let Ok(strategy) = env::var("CHUNK_STRATEGY") {
+            if strategy.trim().to_lowercase() != "auto" {
+                chunker = chunker.with_strategy(strategy.parse()?);
+            }
+        }
+
+        let llm_backend = match env::var("LLM_BACKEND") {
+            Ok(backend) => backend.parse()?,
+            Err(_) => {
+                tracing::warn!("No LLM_BACKEND found in .env, using default");
+                LlmBackend::Ollama
+            }
+        };
+
+        let openai_base_url_str = match env::var("OPENAI_BASE_URL") {
+            Ok(url) => url,
+            Err(_) => {
+                if llm_backend == LlmBackend::OpenAi {
+                    tracing::warn!("No OPENAI_BASE_URL found in .env, using default");
+                }
+                "http://localhost:8080/v1".to_string()
+            }
+        };
+        let openai_base_url = Url::parse(&openai_base_url_str)?;
+
+        let openai_api_key = env::var("OPENAI_API_KEY").ok();
+
+        let conversation_memory = match env::var("CONVERSATION_MEMORY") {
+            Ok(memory) => memory.parse()?,
+            Err(_) => ConversationMemory::default(),
+        };
+
+        let prompt_directory = env::var("PROMPT_DIRECTORY").ok().map(PathBuf::from);
+
+        let tool_call_repairs = match env::var("TOOL_CALL_REPAIRS") {
+            Ok(repairs) => repairs.trim().parse()?,
+            Err(_) => DEFAULT_TOOL_CALL_REPAIRS,
+        };
+
         let ollama_server_url_str = match env::var("OLLAMA_SERVER_URL") {
             Ok(url) => url,
             Err(_) => {
@@ -69,6 +172,12 @@ impl Config {
             }
         };
 
+        let ollama_conversational_context_length =
+            match env::var("OLLAMA_CONVERSATIONAL_CONTEXT_LENGTH") {
+                Ok(context_length) => context_length.trim().parse()?,
+                Err(_) => DEFAULT_CONTEXT_LENGTH,
+            };
+
         let ollama_image_model = match env::var("OLLAMA_IMAGE_MODEL") {
             Ok(model) => model,
             Err(_) => {
@@ -87,10 +196,23 @@ impl Config {
 
         Ok(Config {
             sqlite_database_url,
+            vector_store,
             chroma_database_url,
+            chroma_auth_token,
+            chroma_tenant,
+            chroma_database,
+            collection_naming,
+            chunker,
+            llm_backend,
+            openai_base_url,
+            openai_api_key,
+            conversation_memory,
+            prompt_directory,
+            tool_call_repairs,
             ollama_server_url,
             ollama_supervisor_model,
             ollama_conversational_model,
+            ollama_conversational_context_length,
             ollama_image_model,
             ollama_embedding_model,
         })
@@ -100,10 +222,60 @@ impl Config {
         &self.sqlite_database_url
     }
 
+    pub fn vector_store(&self) -> VectorStoreKind {
+        self.vector_store
+    }
+
     pub fn chroma_database_url(&self) -> &Url {
         &self.chroma_database_url
     }
 
+    pub fn chroma_auth_token(&self) -> Option<&str> {
+        self.chroma_auth_token.as_deref()
+    }
+
+    pub fn chroma_tenant(&self) -> &str {
+        &self.chroma_tenant
+    }
+
+    pub fn chroma_database(&self) -> &str {
+        &self.chroma_database
+    }
+
+    pub fn collection_naming(&self) -> &CollectionNaming {
+        &self.collection_naming
+    }
+
+    pub fn chunker(&self) -> &Chunker {
+        &self.chunker
+    }
+
+    pub fn llm_backend(&self) -> LlmBackend {
+        self.llm_backend
+    }
+
+    pub fn openai_base_url(&self) -> &Url {
+        &self.openai_base_url
+    }
+
+    pub fn openai_api_key(&self) -> Option<&str> {
+        self.openai_api_key.as_deref()
+    }
+
+    pub fn conversation_memory(&self) -> ConversationMemory {
+        self.conversation_memory
+    }
+
+    /// Where to look for prompts overriding the built in ones
+    pub fn prompt_directory(&self) -> Option<&PathBuf> {
+        self.prompt_directory.as_ref()
+    }
+
+    /// How many times the supervisor is asked to fix a tool call which can't be parsed
+    pub fn tool_call_repairs(&self) -> usize {
+        self.tool_call_repairs
+    }
+
     pub fn ollama_server_url(&self) -> &Url {
         &self.ollama_server_url
     }
@@ -116,6 +288,11 @@ impl Config {
         &self.ollama_conversational_model
     }
 
+    /// How many tokens of context the conversational model is run with
+    pub fn ollama_conversational_context_length(&self) -> usize {
+        self.ollama_conversational_context_length
+    }
+
     pub fn ollama_image_model(&self) -> &str {
         &self.ollama_image_model
     }
@@ -125,10 +302,88 @@ impl Config {
     }
 }
 
+/// Which kind of inference server the models are served from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LlmBackend {
+    /// Ollama's native API
+    Ollama,
+    /// Any server speaking the OpenAI chat completions and embeddings protocol
+    OpenAi,
+}
+
+impl FromStr for LlmBackend {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "ollama" => Ok(LlmBackend::Ollama),
+            "openai" => Ok(LlmBackend::OpenAi),
+            _ => Err(ConfigError::UnknownBackend(s.to_string())),
+        }
+    }
+}
+
+/// Where document embeddings are stored and searched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VectorStoreKind {
+    /// Alongside everything else in the SQLite database
+    Sqlite,
+    /// A separate Chroma server
+    Chroma,
+}
+
+impl FromStr for VectorStoreKind {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "sqlite" => Ok(VectorStoreKind::Sqlite),
+            "chroma" => Ok(VectorStoreKind::Chroma),
+            _ => Err(ConfigError::UnknownVectorStore(s.to_string())),
+        }
+    }
+}
+
+impl FromStr for ChunkingStrategy {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "tokens" => Ok(ChunkingStrategy::Tokens),
+            "sentences" => Ok(ChunkingStrategy::Sentences),
+            "markdown" => Ok(ChunkingStrategy::Markdown),
+            "code" => Ok(ChunkingStrategy::Code),
+            _ => Err(ConfigError::UnknownChunkingStrategy(s.to_string())),
+        }
+    }
+}
+
+impl FromStr for ConversationMemory {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "history" => Ok(ConversationMemory::History),
+            "context" => Ok(ConversationMemory::Context),
+            _ => Err(ConfigError::UnknownConversationMemory(s.to_string())),
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum ConfigError {
     #[error("Invalid URL: {0}")]
     InvalidUrl(#[from] url::ParseError),
     #[error("Missing Env: {0}")]
     InvalidEnv(#[from] env::VarError),
+    #[error("Unknown LLM backend: {0}")]
+    UnknownBackend(String),
+    #[error("Unknown vector store: {0}")]
+    UnknownVectorStore(String),
+    #[error("Unknown chunking strategy: {0}")]
+    UnknownChunkingStrategy(String),
+    #[error("Unknown conversation memory: {0}")]
+    UnknownConversationMemory(String),
+    #[error("Invalid number: {0}")]
+    InvalidNumber(#[from] std::num::ParseIntError),
 }
@@ -2,6 +2,6 @@ mod config;
 mod state;
 mod version;
 
-pub use config::Config;
+pub use config::{Config, LlmBackend, VectorStoreKind};
 pub use state::State;
 pub use version::Version;
@@ -1,12 +1,20 @@
-use chromadb::v1::{client::ChromaClientOptions, ChromaClient};
+use std::sync::Arc;
 
-use crate::agent::LlmEngine;
-use crate::app::Config;
+use crate::agent::{
+    LanguageModel, LlmEngine, OllamaBackend, OpenAiBackend, PromptError, Prompts, TokenBudget,
+};
+use crate::app::{Config, LlmBackend, VectorStoreKind};
+use crate::chunking::Chunker;
 use crate::database::Database;
+use crate::vector_store::{
+    ChromaVectorStore, CollectionNaming, SqliteVectorStore, VectorStore, VectorStoreError,
+};
 
 pub struct State {
     sqlite_database: Database,
-    chroma_database: ChromaClient,
+    vector_store: Arc<dyn VectorStore>,
+    collection_naming: CollectionNaming,
+    chunker: Chunker,
     llm_engine: LlmEngine,
 }
 
@@ -16,8 +24,16 @@ impl State {
         &self.sqlite_database
     }
 
-    pub fn chroma_database(&self) -> &ChromaClient {
-        &self.chroma_database
+    pub fn vector_store(&self) -> &Arc<dyn VectorStore> {
+        &self.vector_store
+    }
+
+    pub fn collection_naming(&self) -> &CollectionNaming {
+        &self.collection_naming
+    }
+
+    pub fn chunker(&self) -> &Chunker {
+        &self.chunker
     }
 
     pub fn llm_engine(&self) -> &LlmEngine {
@@ -26,21 +42,51 @@ impl State {
 
     pub async fn from_config(config: &Config) -> Result<Self, StateSetupError> {
         let sqlite_database = Database::connect(config.sqlite_database_url()).await?;
-        // TODO: Add Chroma configuration
-        let chroma_connection_options = ChromaClientOptions::default();
-        let chroma_database = ChromaClient::new(chroma_connection_options);
+        let vector_store: Arc<dyn VectorStore> = match config.vector_store() {
+            VectorStoreKind::Sqlite => Arc::new(SqliteVectorStore::new(sqlite_database.clone())),
+            VectorStoreKind::Chroma => {
+                let chroma_database = ChromaVectorStore::new(
+                    config.chroma_database_url(),
+                    config.chroma_auth_token().map(String::from),
+                    config.chroma_tenant().to_string(),
+                    config.chroma_database().to_string(),
+                );
+                // Fail early rather than on the first attachment
+                chroma_database.heartbeat().await?;
+                Arc::new(chroma_database)
+            }
+        };
 
+        let language_model: Arc<dyn LanguageModel> = match config.llm_backend() {
+            LlmBackend::Ollama => Arc::new(OllamaBackend::new(config.ollama_server_url())),
+            LlmBackend::OpenAi => Arc::new(OpenAiBackend::new(
+                config.openai_base_url(),
+                config.openai_api_key().map(String::from),
+            )),
+        };
+        let prompts = match config.prompt_directory() {
+            Some(directory) => Prompts::load(directory)?,
+            None => Prompts::default(),
+        };
         let llm_engine = LlmEngine::new(
-            config.ollama_server_url(),
+            language_model,
             config.ollama_supervisor_model().to_string(),
             config.ollama_conversational_model().to_string(),
             config.ollama_image_model().to_string(),
             config.ollama_embedding_model().to_string(),
-        );
+        )
+        .with_conversation_memory(config.conversation_memory())
+        .with_token_budget(TokenBudget::new(
+            config.ollama_conversational_context_length(),
+        ))
+        .with_prompts(prompts)
+        .with_tool_call_repairs(config.tool_call_repairs());
 
         Ok(Self {
             sqlite_database,
-            chroma_database,
+            vector_store,
+            collection_naming: config.collection_naming().clone(),
+            chunker: *config.chunker(),
             llm_engine,
         })
     }
@@ -49,9 +95,11 @@ impl State {
 #[derive(Debug, thiserror::Error)]
 pub enum StateSetupError {
     #[error("failed to connect chroma database: {0}")]
-    ChromaDatabase(#[from] anyhow::Error),
+    ChromaDatabase(#[from] VectorStoreError),
     #[error("failed to setup the database: {0}")]
     DatabaseSetup(#[from] crate::database::DatabaseSetupError),
     #[error("failed to setup the Chroma database: {0}")]
     EngineSetup(#[from] crate::agent::LlmEngineError),
+    #[error("failed to load prompts: {0}")]
+    Prompts(#[from] PromptError),
 }
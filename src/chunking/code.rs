@@ -0,0 +1,49 @@
+use std::ops::Range;
+
+/// Extensions of the source files chunked as code
+pub const CODE_EXTENSIONS: &[&str] = &[
+    "c", "cc", "cpp", "cs", "go", "h", "hpp", "java", "js", "jsx", "kt", "lua", "php", "py", "rb",
+    "rs", "scala", "sh", "swift", "ts", "tsx", "zig",
+];
+
+pub(super) fn is_code_extension(extension: &str) -> bool {
+    CODE_EXTENSIONS.contains(&extension)
+}
+
+/// Split source code into top level blocks, such as functions, types and
+/// groups of imports. A block starts at an unindented line following a blank
+/// line, unless that line closes an earlier block.
+pub(super) fn blocks(text: &str) -> Vec<Range<usize>> {
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    let mut previous_blank = false;
+    for line in text.split_inclusive('\n') {
+        let blank = line.trim().is_empty();
+        let starts_block =
+            !blank && !line.starts_with(char::is_whitespace) && !line.starts_with(['}', ')', ']']);
+        if starts_block && previous_blank && offset > start {
+            blocks.push(start..offset);
+            start = offset;
+        }
+        previous_blank = blank;
+        offset += line.len();
+    }
+    if start < text.len() {
+        blocks.push(start..text.len());
+    }
+    blocks
+}
+
+/// Split a range of text into its lines
+pub(super) fn lines(text: &str, range: Range<usize>) -> Vec<Range<usize>> {
+    let mut offset = range.start;
+    text[range]
+        .split_inclusive('\n')
+        .map(|line| {
+            let start = offset;
+            offset += line.len();
+            start..offset
+        })
+        .collect()
+}
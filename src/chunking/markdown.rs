@@ -0,0 +1,61 @@
+use std::ops::Range;
+
+/// Split Markdown into sections, each starting at a heading.
+/// Lines inside fenced code blocks are never treated as headings.
+pub(super) fn sections(text: &str) -> Vec<Range<usize>> {
+    let mut sections = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    let mut fence: Option<&str> = None;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        match fence {
+            Some(marker) if trimmed.starts_with(marker) => fence = None,
+            Some(_) => {}
+            None if trimmed.starts_with("```") => fence = Some("```"),
+            None if trimmed.starts_with("~~~") => fence = Some("~~~"),
+            None if is_heading(line) && offset > start => {
+                sections.push(start..offset);
+                start = offset;
+            }
+            None => {}
+        }
+        offset += line.len();
+    }
+    if start < text.len() {
+        sections.push(start..text.len());
+    }
+    sections
+}
+
+/// An ATX heading: one to six `#`s followed by a space or the end of the line
+fn is_heading(line: &str) -> bool {
+    let hashes = line.chars().take_while(|c| *c == '#').count();
+    (1..=6).contains(&hashes)
+        && line[hashes..]
+            .chars()
+            .next()
+            .is_none_or(char::is_whitespace)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sections() {
+        let text = "intro\n# One\na\n```\n# not a heading\n```\n#hashtag\n## Two\nb";
+        let sections = sections(text)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sections,
+            vec![
+                "intro\n",
+                "# One\na\n```\n# not a heading\n```\n#hashtag\n",
+                "## Two\nb"
+            ]
+        );
+    }
+}
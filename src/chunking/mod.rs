@@ -0,0 +1,315 @@
+use std::ops::Range;
+use std::path::Path;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+mod code;
+mod markdown;
+mod tokens;
+
+pub use code::CODE_EXTENSIONS;
+pub use tokens::count_tokens;
+
+/// The most tokens a chunk may hold unless configured otherwise
+pub const DEFAULT_MAX_TOKENS: usize = 256;
+/// How many tokens consecutive chunks share unless configured otherwise
+pub const DEFAULT_OVERLAP: usize = 32;
+
+/// A piece of a document small enough to embed, along with the byte range
+/// of the document it was taken from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// How a document is broken up into chunks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkingStrategy {
+    /// Fixed windows of tokens, ignoring the structure of the text
+    Tokens,
+    /// Whole sentences, packed together up to the token limit
+    Sentences,
+    /// Sections under each Markdown heading, split further by sentence
+    Markdown,
+    /// Top level blocks of source code such as functions, split further by line
+    Code,
+}
+
+impl ChunkingStrategy {
+    /// The best strategy for a file, going by its extension
+    pub fn for_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        match extension.as_str() {
+            "md" | "markdown" => ChunkingStrategy::Markdown,
+            _ if code::is_code_extension(&extension) => ChunkingStrategy::Code,
+            _ => ChunkingStrategy::Sentences,
+        }
+    }
+}
+
+/// Breaks documents up into chunks of at most `max_tokens` tokens,
+/// with consecutive chunks sharing up to `overlap` tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chunker {
+    strategy: Option<ChunkingStrategy>,
+    max_tokens: usize,
+    overlap: usize,
+}
+
+impl Default for Chunker {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_TOKENS, DEFAULT_OVERLAP)
+    }
+}
+
+impl Chunker {
+    /// A chunker which picks a strategy for each file from its extension
+    pub fn new(max_tokens: usize, overlap: usize) -> Self {
+        let max_tokens = max_tokens.max(1);
+        Self {
+            strategy: None,
+            max_tokens,
+            overlap: overlap.min(max_tokens - 1),
+        }
+    }
+
+    /// Use the same strategy for every file
+    pub fn with_strategy(mut self, strategy: ChunkingStrategy) -> Self {
+        self.strategy = Some(strategy);
+        self
+    }
+
+    pub fn strategy(&self) -> Option<ChunkingStrategy> {
+        self.strategy
+    }
+
+    pub fn max_tokens(&self) -> usize {
+        self.max_tokens
+    }
+
+    pub fn overlap(&self) -> usize {
+        self.overlap
+    }
+
+    /// Chunk the contents of the file at `path`
+    pub fn chunk_path(&self, path: &Path, text: &str) -> Vec<Chunk> {
+        let strategy = self
+            .strategy
+            .unwrap_or_else(|| ChunkingStrategy::for_path(path));
+        self.chunk(strategy, text)
+    }
+
+    pub fn chunk(&self, strategy: ChunkingStrategy, text: &str) -> Vec<Chunk> {
+        let all = 0..text.len();
+        let ranges = match strategy {
+            ChunkingStrategy::Tokens => self.windows(text, all),
+            ChunkingStrategy::Sentences => self.sentences(text, all),
+            ChunkingStrategy::Markdown => markdown::sections(text)
+                .into_iter()
+                .flat_map(|section| self.sentences(text, section))
+                .collect(),
+            ChunkingStrategy::Code => {
+                let blocks = code::blocks(text);
+                self.pack(text, blocks, &|range| {
+                    self.pack(text, code::lines(text, range), &|range| {
+                        self.windows(text, range)
+                    })
+                })
+            }
+        };
+        ranges
+            .into_iter()
+            .filter_map(|range| trimmed_chunk(text, range))
+            .collect()
+    }
+
+    fn windows(&self, text: &str, range: Range<usize>) -> Vec<Range<usize>> {
+        tokens::windows(text, range, self.max_tokens, self.overlap)
+    }
+
+    fn sentences(&self, text: &str, range: Range<usize>) -> Vec<Range<usize>> {
+        let offset = range.start;
+        let sentences = text[range]
+            .split_sentence_bound_indices()
+            .map(|(start, sentence)| offset + start..offset + start + sentence.len())
+            .collect();
+        self.pack(text, sentences, &|range| self.windows(text, range))
+    }
+
+    /// Greedily merge consecutive spans into ranges of at most `max_tokens` tokens,
+    /// handing any span that is too big on its own to `split`
+    fn pack(
+        &self,
+        text: &str,
+        spans: Vec<Range<usize>>,
+        split: &dyn Fn(Range<usize>) -> Vec<Range<usize>>,
+    ) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        // The spans making up the range being built, with their token counts
+        let mut current: Vec<(Range<usize>, usize)> = Vec::new();
+        for span in spans {
+            let span_tokens = count_tokens(&text[span.clone()]);
+            if span_tokens == 0 {
+                continue;
+            }
+            if span_tokens > self.max_tokens {
+                if let Some(range) = covering(&current) {
+                    ranges.push(range);
+                }
+                current.clear();
+                ranges.extend(split(span));
+                continue;
+            }
+
+            let current_tokens: usize = current.iter().map(|(_, tokens)| tokens).sum();
+            if current_tokens + span_tokens > self.max_tokens {
+                if let Some(range) = covering(&current) {
+                    ranges.push(range);
+                }
+                current = self.carry_over(current, span_tokens);
+            }
+            current.push((span, span_tokens));
+        }
+        if let Some(range) = covering(&current) {
+            ranges.push(range);
+        }
+        ranges
+    }
+
+    /// The trailing spans of a finished range to repeat at the start of the next one
+    fn carry_over(
+        &self,
+        spans: Vec<(Range<usize>, usize)>,
+        next_tokens: usize,
+    ) -> Vec<(Range<usize>, usize)> {
+        let budget = self.overlap.min(self.max_tokens - next_tokens);
+        let mut carried = Vec::new();
+        let mut carried_tokens = 0;
+        for (span, tokens) in spans.into_iter().rev() {
+            if carried_tokens + tokens > budget {
+                break;
+            }
+            carried_tokens += tokens;
+            carried.push((span, tokens));
+        }
+        carried.reverse();
+        carried
+    }
+}
+
+fn covering(spans: &[(Range<usize>, usize)]) -> Option<Range<usize>> {
+    let first = spans.first()?;
+    let last = spans.last()?;
+    Some(first.0.start..last.0.end)
+}
+
+/// Strip surrounding whitespace from a range, dropping it if nothing is left
+fn trimmed_chunk(text: &str, range: Range<usize>) -> Option<Chunk> {
+    let slice = &text[range.clone()];
+    let start = range.start + (slice.len() - slice.trim_start().len());
+    let end = range.end - (slice.len() - slice.trim_end().len());
+    if start >= end {
+        return None;
+    }
+    Some(Chunk {
+        text: text[start..end].to_string(),
+        start,
+        end,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_offsets(text: &str, chunks: &[Chunk]) {
+        for chunk in chunks {
+            assert_eq!(&text[chunk.start..chunk.end], chunk.text);
+        }
+    }
+
+    #[test]
+    fn test_tokens_overlap() {
+        let text = "one two three four five six seven eight";
+        let chunks = Chunker::new(4, 1).chunk(ChunkingStrategy::Tokens, text);
+        let texts = chunks.iter().map(|c| c.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            texts,
+            vec!["one two three four", "four five six seven", "seven eight"]
+        );
+        assert_offsets(text, &chunks);
+    }
+
+    #[test]
+    fn test_sentences() {
+        let text = "Blossoms are pink. They bloom in spring.\n\nPetals fall in the wind!";
+        let chunks = Chunker::new(8, 0).chunk(ChunkingStrategy::Sentences, text);
+        let texts = chunks.iter().map(|c| c.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            texts,
+            vec![
+                "Blossoms are pink.",
+                "They bloom in spring.",
+                "Petals fall in the wind!"
+            ]
+        );
+        assert_offsets(text, &chunks);
+
+        // Everything fits in one chunk given enough room
+        let chunks = Chunker::new(64, 0).chunk(ChunkingStrategy::Sentences, text);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].start, 0);
+        assert_eq!(chunks[0].end, text.len());
+    }
+
+    #[test]
+    fn test_markdown_keeps_sections_apart() {
+        let text = "# Blossoms\nThey are pink.\n\n## Petals\nThey fall.\n";
+        let chunks = Chunker::new(64, 0).chunk(ChunkingStrategy::Markdown, text);
+        let texts = chunks.iter().map(|c| c.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            texts,
+            vec!["# Blossoms\nThey are pink.", "## Petals\nThey fall."]
+        );
+        assert_offsets(text, &chunks);
+    }
+
+    #[test]
+    fn test_code_splits_on_blocks() {
+        let text = "fn one() {\n    1\n}\n\nfn two() {\n    2\n}\n";
+        let chunks = Chunker::new(8, 0).chunk(ChunkingStrategy::Code, text);
+        let texts = chunks.iter().map(|c| c.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(texts, vec!["fn one() {\n    1\n}", "fn two() {\n    2\n}"]);
+        assert_offsets(text, &chunks);
+    }
+
+    #[test]
+    fn test_oversized_spans_are_split() {
+        let sentence = "word ".repeat(20);
+        let chunks = Chunker::new(8, 0).chunk(ChunkingStrategy::Sentences, &sentence);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|c| count_tokens(&c.text) <= 8));
+        assert_offsets(&sentence, &chunks);
+    }
+
+    #[test]
+    fn test_strategy_for_path() {
+        let strategy = |path: &str| ChunkingStrategy::for_path(Path::new(path));
+        assert_eq!(strategy("README.md"), ChunkingStrategy::Markdown);
+        assert_eq!(strategy("src/main.rs"), ChunkingStrategy::Code);
+        assert_eq!(strategy("notes.txt"), ChunkingStrategy::Sentences);
+        assert_eq!(
+            Chunker::default()
+                .with_strategy(ChunkingStrategy::Tokens)
+                .chunk_path(Path::new("README.md"), "a b")[0]
+                .text,
+            "a b"
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use std::ops::Range;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Roughly how many characters of a word make up one token
+const CHARACTERS_PER_TOKEN: usize = 5;
+
+/// Estimate how many tokens an embedding model will see in some text.
+/// Every word or piece of punctuation costs at least one token, and longer
+/// words cost one more for every few characters. This errs on the side of
+/// overcounting, which keeps chunks safely inside the model's context.
+pub fn count_tokens(text: &str) -> usize {
+    text.split_word_bounds().map(word_tokens).sum()
+}
+
+fn word_tokens(word: &str) -> usize {
+    if word.trim().is_empty() {
+        return 0;
+    }
+    word.chars().count().div_ceil(CHARACTERS_PER_TOKEN)
+}
+
+/// Split a range of text into windows of at most `max_tokens` tokens,
+/// each starting `overlap` tokens before the previous one ended
+pub(super) fn windows(
+    text: &str,
+    range: Range<usize>,
+    max_tokens: usize,
+    overlap: usize,
+) -> Vec<Range<usize>> {
+    let offset = range.start;
+    let words = text[range]
+        .split_word_bound_indices()
+        .filter_map(|(start, word)| {
+            let tokens = word_tokens(word);
+            (tokens > 0).then_some((offset + start..offset + start + word.len(), tokens))
+        })
+        .collect::<Vec<_>>();
+
+    let mut windows = Vec::new();
+    let mut start = 0;
+    while start < words.len() {
+        // Always take at least one word, even if it's too big on its own
+        let mut end = start;
+        let mut tokens = 0;
+        while end < words.len() && (end == start || tokens + words[end].1 <= max_tokens) {
+            tokens += words[end].1;
+            end += 1;
+        }
+        windows.push(words[start].0.start..words[end - 1].0.end);
+        if end == words.len() {
+            break;
+        }
+
+        // Back up to share some words with the next window, while still making progress
+        let mut next = end;
+        let mut carried = 0;
+        while next > start + 1 && carried + words[next - 1].1 <= overlap {
+            carried += words[next - 1].1;
+            next -= 1;
+        }
+        start = next;
+    }
+    windows
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_count_tokens() {
+        assert_eq!(count_tokens(""), 0);
+        assert_eq!(count_tokens("  \n "), 0);
+        assert_eq!(count_tokens("one two"), 2);
+        assert_eq!(count_tokens("Blossoms are pink."), 5);
+    }
+}
@@ -1,4 +1,7 @@
-use clap::{command, Parser, Subcommand};
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use time::{Date, Month};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -13,12 +16,129 @@ pub enum Command {
     New {
         #[clap(long = "name", short = 'n')]
         maybe_name: Option<String>,
+        #[clap(flatten)]
+        settings: SettingsArgs,
+    },
+    // List all chats, optionally filtered and sorted
+    Ls {
+        // Only chats whose name contains this text
+        #[clap(long)]
+        contains: Option<String>,
+        // Only chats created on or after this date, as YYYY-MM-DD
+        #[clap(long, value_parser = parse_date)]
+        since: Option<Date>,
+        #[clap(long, value_enum, default_value_t = SortOrder::Oldest)]
+        sort: SortOrder,
     },
-    // List all chats
-    Ls,
     // Continue a chat
     Cont {
         #[clap(long, short)]
         name: String,
     },
+    // Rename a chat
+    Rename {
+        #[clap(long, short)]
+        name: String,
+        new_name: String,
+    },
+    // Delete a chat along with its messages and attached documents
+    Rm {
+        #[clap(long, short)]
+        name: String,
+    },
+    // Print the transcript of a chat
+    Show {
+        #[clap(long, short)]
+        name: String,
+    },
+    // Write a chat out for sharing or moving to another machine
+    Export {
+        #[clap(long, short)]
+        name: String,
+        #[clap(long, short, value_enum, default_value_t = ExportFormat::Md)]
+        format: ExportFormat,
+        // Where to write the export, rather than standard output
+        #[clap(long, short)]
+        output: Option<PathBuf>,
+    },
+    // Recreate a chat from a JSON or JSON Lines export
+    Import {
+        path: PathBuf,
+        // Name the chat something other than in the export
+        #[clap(long, short)]
+        name: Option<String>,
+        // Embed the attached files again, if they can be found
+        #[clap(long)]
+        reembed: bool,
+    },
+    // Manage the files attached to a chat
+    Attachments {
+        #[clap(long, short)]
+        name: String,
+        #[clap(subcommand)]
+        command: Option<AttachmentsCommand>,
+    },
+}
+
+// Overrides of the configured models and sampling options for a single chat
+#[derive(Args, Debug)]
+pub struct SettingsArgs {
+    // Replace the conversational system prompt with the contents of this file
+    #[clap(long)]
+    pub system_file: Option<PathBuf>,
+    // The conversational model
+    #[clap(long, short)]
+    pub model: Option<String>,
+    #[clap(long)]
+    pub supervisor_model: Option<String>,
+    #[clap(long)]
+    pub image_model: Option<String>,
+    #[clap(long)]
+    pub embedding_model: Option<String>,
+    #[clap(long)]
+    pub temperature: Option<f32>,
+    #[clap(long)]
+    pub top_p: Option<f32>,
+    // The size of the conversational model's context window in tokens
+    #[clap(long)]
+    pub num_ctx: Option<u32>,
+    #[clap(long)]
+    pub seed: Option<i32>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum SortOrder {
+    Oldest,
+    Newest,
+    Name,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ExportFormat {
+    Md,
+    Json,
+    Jsonl,
+}
+
+fn parse_date(value: &str) -> Result<Date, String> {
+    let invalid = || format!("expected a date like 2024-05-01, got: {}", value);
+    let parts = value
+        .split('-')
+        .map(|part| part.parse::<i32>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let [year, month, day] = parts[..] else {
+        return Err(invalid());
+    };
+    let month = Month::try_from(month as u8).map_err(|_| invalid())?;
+    Date::from_calendar_date(year, month, day as u8).map_err(|_| invalid())
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AttachmentsCommand {
+    // List the attached files, the default
+    Ls,
+    // Remove an attached file and everything indexed from it
+    Detach { path: PathBuf },
+    // Show what was indexed from an attached file
+    Inspect { path: PathBuf },
 }
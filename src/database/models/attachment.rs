@@ -0,0 +1,365 @@
+use sqlx::FromRow;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::database::types::DId;
+use crate::database::DatabaseConnection;
+
+/*
+CREATE TABLE attachments (
+  id BLOB NOT NULL PRIMARY KEY DEFAULT (randomblob(16)),
+  chat_id BLOB NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+  path TEXT NOT NULL,
+  content_hash TEXT NOT NULL,
+  size INTEGER NOT NULL DEFAULT 0,
+  chunk_count INTEGER NOT NULL DEFAULT 0,
+  embedding_model TEXT,
+  indexed_at TIMESTAMP,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE (chat_id, path)
+);
+
+CREATE TABLE attachment_chunks (
+  attachment_id BLOB NOT NULL REFERENCES attachments(id) ON DELETE CASCADE,
+  content_hash TEXT NOT NULL,
+  vector_id TEXT NOT NULL,
+  PRIMARY KEY (attachment_id, content_hash)
+);
+*/
+
+/// A file attached to a chat, along with what its contents looked like when it was last indexed
+#[derive(FromRow, Debug)]
+pub struct Attachment {
+    id: DId,
+    chat_id: DId,
+    path: String,
+    content_hash: String,
+    size: i64,
+    chunk_count: i64,
+    embedding_model: Option<String>,
+    indexed_at: Option<OffsetDateTime>,
+    created_at: OffsetDateTime,
+}
+
+impl Attachment {
+    /// Find the attachment for a path within a chat, creating it if it doesn't exist yet.
+    /// New attachments have an empty content hash, as nothing has been indexed.
+    pub async fn get_or_create(
+        chat_id: Uuid,
+        path: &str,
+        conn: &mut DatabaseConnection,
+    ) -> Result<Attachment, sqlx::Error> {
+        let chat_id: DId = chat_id.into();
+        sqlx::query!(
+            r#"
+            INSERT INTO attachments (chat_id, path, content_hash, created_at)
+            VALUES ($1, $2, '', CURRENT_TIMESTAMP)
+            ON CONFLICT (chat_id, path) DO NOTHING
+            "#,
+            chat_id,
+            path
+        )
+        .execute(&mut *conn)
+        .await?;
+        Self::read_by_path(*chat_id, path, conn).await
+    }
+
+    pub async fn read_by_path(
+        chat_id: Uuid,
+        path: &str,
+        conn: &mut DatabaseConnection,
+    ) -> Result<Attachment, sqlx::Error> {
+        let chat_id: DId = chat_id.into();
+        let attachment = sqlx::query_as!(
+            Attachment,
+            r#"
+            SELECT
+                id as "id: DId",
+                chat_id as "chat_id: DId",
+                path,
+                content_hash,
+                size,
+                chunk_count,
+                embedding_model,
+                indexed_at,
+                created_at
+            FROM attachments
+            WHERE chat_id = $1 AND path = $2
+            "#,
+            chat_id,
+            path
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+        Ok(attachment)
+    }
+
+    /// Every file attached to a chat, ordered by path
+    pub async fn read_by_chat(
+        chat_id: Uuid,
+        conn: &mut DatabaseConnection,
+    ) -> Result<Vec<Attachment>, sqlx::Error> {
+        let chat_id: DId = chat_id.into();
+        let attachments = sqlx::query_as!(
+            Attachment,
+            r#"
+            SELECT
+                id as "id: DId",
+                chat_id as "chat_id: DId",
+                path,
+                content_hash,
+                size,
+                chunk_count,
+                embedding_model,
+                indexed_at,
+                created_at
+            FROM attachments
+            WHERE chat_id = $1
+            ORDER BY path
+            "#,
+            chat_id
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(attachments)
+    }
+
+    /// Record what was indexed: the hash and size of the contents, how many
+    /// chunks they were split into and the model that embedded them
+    pub async fn mark_indexed(
+        id: Uuid,
+        content_hash: &str,
+        size: i64,
+        chunk_count: i64,
+        embedding_model: &str,
+        conn: &mut DatabaseConnection,
+    ) -> Result<(), sqlx::Error> {
+        let id: DId = id.into();
+        sqlx::query!(
+            r#"
+            UPDATE attachments
+            SET content_hash = $2,
+                size = $3,
+                chunk_count = $4,
+                embedding_model = $5,
+                indexed_at = CURRENT_TIMESTAMP
+            WHERE id = $1
+            "#,
+            id,
+            content_hash,
+            size,
+            chunk_count,
+            embedding_model
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Remove an attachment along with its record of chunks
+    pub async fn delete(id: Uuid, conn: &mut DatabaseConnection) -> Result<(), sqlx::Error> {
+        let id: DId = id.into();
+        sqlx::query!(
+            r#"
+            DELETE FROM attachments
+            WHERE id = $1
+            "#,
+            id
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    pub fn id(&self) -> Uuid {
+        *self.id.to_owned()
+    }
+
+    pub fn chat_id(&self) -> Uuid {
+        *self.chat_id.to_owned()
+    }
+
+    pub fn path(&self) -> &str {
+        self.path.as_str()
+    }
+
+    pub fn content_hash(&self) -> &str {
+        self.content_hash.as_str()
+    }
+
+    /// The size in bytes of the file when it was last indexed
+    pub fn size(&self) -> i64 {
+        self.size
+    }
+
+    pub fn chunk_count(&self) -> i64 {
+        self.chunk_count
+    }
+
+    pub fn embedding_model(&self) -> Option<&str> {
+        self.embedding_model.as_deref()
+    }
+
+    /// When the file was last indexed, or `None` if it never finished indexing
+    pub fn indexed_at(&self) -> Option<OffsetDateTime> {
+        self.indexed_at
+    }
+
+    pub fn created_at(&self) -> OffsetDateTime {
+        self.created_at
+    }
+}
+
+/// A chunk of an attachment stored in the vector store, identified by a hash of its text
+#[derive(FromRow, Debug)]
+pub struct AttachmentChunk {
+    content_hash: String,
+    vector_id: String,
+}
+
+impl AttachmentChunk {
+    pub async fn create(
+        attachment_id: Uuid,
+        content_hash: &str,
+        vector_id: &str,
+        conn: &mut DatabaseConnection,
+    ) -> Result<(), sqlx::Error> {
+        let attachment_id: DId = attachment_id.into();
+        sqlx::query!(
+            r#"
+            INSERT INTO attachment_chunks (attachment_id, content_hash, vector_id)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (attachment_id, content_hash) DO UPDATE SET
+                vector_id = excluded.vector_id
+            "#,
+            attachment_id,
+            content_hash,
+            vector_id
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn read_by_attachment(
+        attachment_id: Uuid,
+        conn: &mut DatabaseConnection,
+    ) -> Result<Vec<AttachmentChunk>, sqlx::Error> {
+        let attachment_id: DId = attachment_id.into();
+        let chunks = sqlx::query_as!(
+            AttachmentChunk,
+            r#"
+            SELECT content_hash, vector_id
+            FROM attachment_chunks
+            WHERE attachment_id = $1
+            "#,
+            attachment_id
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(chunks)
+    }
+
+    pub async fn delete(
+        attachment_id: Uuid,
+        content_hash: &str,
+        conn: &mut DatabaseConnection,
+    ) -> Result<(), sqlx::Error> {
+        let attachment_id: DId = attachment_id.into();
+        sqlx::query!(
+            r#"
+            DELETE FROM attachment_chunks
+            WHERE attachment_id = $1 AND content_hash = $2
+            "#,
+            attachment_id,
+            content_hash
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    pub fn content_hash(&self) -> &str {
+        self.content_hash.as_str()
+    }
+
+    pub fn vector_id(&self) -> &str {
+        self.vector_id.as_str()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::database::models::Chat;
+    use crate::tests::prelude::*;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_attachment_chunks() {
+        let db_pool = test_database().await;
+        let mut conn = db_pool
+            .acquire()
+            .await
+            .expect("Failed to acquire a connection");
+
+        let chat_id = Chat::create("test_chat", &mut conn).await.unwrap();
+        let attachment = Attachment::get_or_create(chat_id, "/notes.txt", &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(attachment.content_hash(), "");
+        assert!(attachment.indexed_at().is_none());
+        Attachment::mark_indexed(attachment.id(), "abc", 42, 2, "embedding", &mut conn)
+            .await
+            .unwrap();
+        let attachment = Attachment::get_or_create(chat_id, "/notes.txt", &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(attachment.content_hash(), "abc");
+        assert_eq!(attachment.size(), 42);
+        assert_eq!(attachment.chunk_count(), 2);
+        assert_eq!(attachment.embedding_model(), Some("embedding"));
+        assert!(attachment.indexed_at().is_some());
+
+        AttachmentChunk::create(attachment.id(), "one", "/notes.txt#one", &mut conn)
+            .await
+            .unwrap();
+        AttachmentChunk::create(attachment.id(), "two", "/notes.txt#two", &mut conn)
+            .await
+            .unwrap();
+        AttachmentChunk::delete(attachment.id(), "one", &mut conn)
+            .await
+            .unwrap();
+        let chunks = AttachmentChunk::read_by_attachment(attachment.id(), &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].content_hash(), "two");
+        assert_eq!(chunks[0].vector_id(), "/notes.txt#two");
+
+        Attachment::get_or_create(chat_id, "/a.txt", &mut conn)
+            .await
+            .unwrap();
+        let attachments = Attachment::read_by_chat(chat_id, &mut conn).await.unwrap();
+        let paths = attachments.iter().map(|a| a.path()).collect::<Vec<_>>();
+        assert_eq!(paths, vec!["/a.txt", "/notes.txt"]);
+
+        // Deleting an attachment takes its chunks with it
+        Attachment::delete(attachment.id(), &mut conn)
+            .await
+            .unwrap();
+        assert!(
+            AttachmentChunk::read_by_attachment(attachment.id(), &mut conn)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            Attachment::read_by_chat(chat_id, &mut conn)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
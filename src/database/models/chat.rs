@@ -15,7 +15,12 @@ CREATE TABLE chats (
         LOWER(HEX(RANDOMBLOB(6)))) NOT NULL,
   name TEXT NOT NULL,
   created_at TIMESTAMP WITH TIME ZONE DEFAULT now(),
+  head_id BLOB REFERENCES messages(id) ON DELETE SET NULL,
+  summary TEXT,
+  summary_through BLOB REFERENCES messages(id) ON DELETE SET NULL,
 );
+
+CREATE UNIQUE INDEX idx_chats_name ON chats(name);
 */
 
 #[derive(FromRow, Debug)]
@@ -23,6 +28,8 @@ pub struct Chat {
     id: DId,
     name: String,
     created_at: OffsetDateTime,
+    summary: Option<String>,
+    summary_through: Option<DId>,
 }
 
 impl Chat {
@@ -39,11 +46,19 @@ impl Chat {
         Ok(*chat_id.to_owned())
     }
 
+    /// Every chat, oldest first
     pub async fn read_all(conn: &mut DatabaseConnection) -> Result<Vec<Chat>, sqlx::Error> {
         let chats = sqlx::query_as!(
             Chat,
             r#"
-            SELECT id as "id: DId", name, created_at FROM chats
+            SELECT
+                id as "id: DId",
+                name,
+                created_at,
+                summary,
+                summary_through as "summary_through: DId"
+            FROM chats
+            ORDER BY created_at, rowid
             "#
         )
         .fetch_all(&mut *conn)
@@ -56,7 +71,13 @@ impl Chat {
         let chat = sqlx::query_as!(
             Chat,
             r#"
-            SELECT id as "id: DId", name, created_at FROM chats
+            SELECT
+                id as "id: DId",
+                name,
+                created_at,
+                summary,
+                summary_through as "summary_through: DId"
+            FROM chats
             WHERE id = $1
             "#,
             d_id
@@ -73,7 +94,13 @@ impl Chat {
         let chat = sqlx::query_as!(
             Chat,
             r#"
-            SELECT id as "id: DId", name, created_at FROM chats
+            SELECT
+                id as "id: DId",
+                name,
+                created_at,
+                summary,
+                summary_through as "summary_through: DId"
+            FROM chats
             WHERE name = $1
             "#,
             name
@@ -83,6 +110,88 @@ impl Chat {
         Ok(chat)
     }
 
+    pub async fn rename(
+        id: Uuid,
+        name: &str,
+        conn: &mut DatabaseConnection,
+    ) -> Result<(), sqlx::Error> {
+        let d_id: DId = id.into();
+        sqlx::query!(
+            r#"
+            UPDATE chats
+            SET name = $2
+            WHERE id = $1
+            "#,
+            d_id,
+            name
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Follow a different branch of the chat, ending at `message_id`.
+    /// New messages are appended after the head, so moving it back starts a new branch.
+    pub async fn set_head(
+        id: Uuid,
+        message_id: Option<Uuid>,
+        conn: &mut DatabaseConnection,
+    ) -> Result<(), sqlx::Error> {
+        let d_id: DId = id.into();
+        let message_id: Option<DId> = message_id.map(DId::from);
+        sqlx::query!(
+            r#"
+            UPDATE chats
+            SET head_id = $2
+            WHERE id = $1
+            "#,
+            d_id,
+            message_id
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Remember a summary of the chat's messages up to and including `through`
+    pub async fn set_summary(
+        id: Uuid,
+        summary: &str,
+        through: Uuid,
+        conn: &mut DatabaseConnection,
+    ) -> Result<(), sqlx::Error> {
+        let d_id: DId = id.into();
+        let through: DId = through.into();
+        sqlx::query!(
+            r#"
+            UPDATE chats
+            SET summary = $2, summary_through = $3
+            WHERE id = $1
+            "#,
+            d_id,
+            summary,
+            through
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Delete a chat, taking its messages and attachments with it
+    pub async fn delete(id: Uuid, conn: &mut DatabaseConnection) -> Result<(), sqlx::Error> {
+        let d_id: DId = id.into();
+        sqlx::query!(
+            r#"
+            DELETE FROM chats
+            WHERE id = $1
+            "#,
+            d_id
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
     pub fn id(&self) -> Uuid {
         *self.id.to_owned()
     }
@@ -94,10 +203,21 @@ impl Chat {
     pub fn created_at(&self) -> OffsetDateTime {
         self.created_at
     }
+
+    /// A summary of the older messages of the chat, if it has grown long enough to need one
+    pub fn summary(&self) -> Option<&str> {
+        self.summary.as_deref()
+    }
+
+    /// The last message covered by the summary
+    pub fn summary_through(&self) -> Option<Uuid> {
+        self.summary_through.as_ref().map(|through| **through)
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use crate::database::models::{Attachment, Message, MessageRole};
     use crate::tests::prelude::*;
 
     use super::*;
@@ -113,5 +233,90 @@ mod test {
         let id = Chat::create("test_chat", &mut conn).await.unwrap();
         let chat = Chat::read(id, &mut conn).await.unwrap();
         assert_eq!(chat.name(), "test_chat");
+        assert_eq!(chat.summary(), None);
+    }
+
+    #[tokio::test]
+    async fn test_summary() {
+        let db_pool = test_database().await;
+        let mut conn = db_pool
+            .acquire()
+            .await
+            .expect("Failed to acquire a connection");
+
+        let id = Chat::create("test_chat", &mut conn).await.unwrap();
+        let message_id = Message::create(id, MessageRole::User, "hello", None, None, &mut conn)
+            .await
+            .unwrap();
+        Chat::set_summary(id, "The user said hello", message_id, &mut conn)
+            .await
+            .unwrap();
+        let chat = Chat::read(id, &mut conn).await.unwrap();
+        assert_eq!(chat.summary(), Some("The user said hello"));
+        assert_eq!(chat.summary_through(), Some(message_id));
+    }
+
+    #[tokio::test]
+    async fn test_names_are_unique() {
+        let db_pool = test_database().await;
+        let mut conn = db_pool
+            .acquire()
+            .await
+            .expect("Failed to acquire a connection");
+
+        let id = Chat::create("first", &mut conn).await.unwrap();
+        Chat::create("second", &mut conn).await.unwrap();
+        let duplicate = Chat::create("first", &mut conn).await;
+        assert!(matches!(
+            duplicate,
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation()
+        ));
+        let renamed = Chat::rename(id, "second", &mut conn).await;
+        assert!(matches!(
+            renamed,
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation()
+        ));
+
+        Chat::rename(id, "third", &mut conn).await.unwrap();
+        let chat = Chat::read_by_name("third", &mut conn).await.unwrap();
+        assert_eq!(chat.id(), id);
+        let names = Chat::read_all(&mut conn)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|chat| chat.name().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["third", "second"]);
+    }
+
+    #[tokio::test]
+    async fn test_delete_cascades() {
+        let db_pool = test_database().await;
+        let mut conn = db_pool
+            .acquire()
+            .await
+            .expect("Failed to acquire a connection");
+
+        let id = Chat::create("test_chat", &mut conn).await.unwrap();
+        Message::create(id, MessageRole::User, "hello", None, None, &mut conn)
+            .await
+            .unwrap();
+        Attachment::get_or_create(id, "/notes.txt", &mut conn)
+            .await
+            .unwrap();
+
+        Chat::delete(id, &mut conn).await.unwrap();
+        assert!(matches!(
+            Chat::read(id, &mut conn).await,
+            Err(sqlx::Error::RowNotFound)
+        ));
+        assert!(Message::read_by_chat(id, &mut conn)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(Attachment::read_by_chat(id, &mut conn)
+            .await
+            .unwrap()
+            .is_empty());
     }
 }
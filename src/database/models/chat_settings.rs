@@ -0,0 +1,164 @@
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::database::types::DId;
+use crate::database::DatabaseConnection;
+
+/*
+CREATE TABLE chat_settings (
+  chat_id BLOB NOT NULL PRIMARY KEY REFERENCES chats(id) ON DELETE CASCADE,
+  system_prompt TEXT,
+  supervisor_model TEXT,
+  conversational_model TEXT,
+  image_model TEXT,
+  embedding_model TEXT,
+  temperature REAL,
+  top_p REAL,
+  num_ctx INTEGER,
+  seed INTEGER
+);
+*/
+
+/// How a chat overrides the models, persona and sampling options set in the environment.
+/// Anything left as `None` falls back to the global configuration.
+#[derive(FromRow, Debug, Clone, Default, PartialEq)]
+pub struct ChatSettings {
+    /// Replaces the conversational model's system prompt
+    pub system_prompt: Option<String>,
+    pub supervisor_model: Option<String>,
+    pub conversational_model: Option<String>,
+    pub image_model: Option<String>,
+    pub embedding_model: Option<String>,
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    pub num_ctx: Option<i64>,
+    pub seed: Option<i64>,
+}
+
+impl ChatSettings {
+    /// The settings of a chat, all unset if it has never been given any
+    pub async fn read(
+        chat_id: Uuid,
+        conn: &mut DatabaseConnection,
+    ) -> Result<ChatSettings, sqlx::Error> {
+        let chat_id: DId = chat_id.into();
+        let settings = sqlx::query_as!(
+            ChatSettings,
+            r#"
+            SELECT
+                system_prompt,
+                supervisor_model,
+                conversational_model,
+                image_model,
+                embedding_model,
+                temperature,
+                top_p,
+                num_ctx,
+                seed
+            FROM chat_settings
+            WHERE chat_id = $1
+            "#,
+            chat_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+        Ok(settings.unwrap_or_default())
+    }
+
+    /// Replace the settings of a chat
+    pub async fn save(
+        &self,
+        chat_id: Uuid,
+        conn: &mut DatabaseConnection,
+    ) -> Result<(), sqlx::Error> {
+        let chat_id: DId = chat_id.into();
+        sqlx::query!(
+            r#"
+            INSERT INTO chat_settings (
+                chat_id,
+                system_prompt,
+                supervisor_model,
+                conversational_model,
+                image_model,
+                embedding_model,
+                temperature,
+                top_p,
+                num_ctx,
+                seed
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            ON CONFLICT (chat_id) DO UPDATE SET
+                system_prompt = excluded.system_prompt,
+                supervisor_model = excluded.supervisor_model,
+                conversational_model = excluded.conversational_model,
+                image_model = excluded.image_model,
+                embedding_model = excluded.embedding_model,
+                temperature = excluded.temperature,
+                top_p = excluded.top_p,
+                num_ctx = excluded.num_ctx,
+                seed = excluded.seed
+            "#,
+            chat_id,
+            self.system_prompt,
+            self.supervisor_model,
+            self.conversational_model,
+            self.image_model,
+            self.embedding_model,
+            self.temperature,
+            self.top_p,
+            self.num_ctx,
+            self.seed
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::database::models::Chat;
+    use crate::tests::prelude::*;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_save_read() {
+        let db_pool = test_database().await;
+        let mut conn = db_pool
+            .acquire()
+            .await
+            .expect("Failed to acquire a connection");
+
+        let chat_id = Chat::create("test_chat", &mut conn).await.unwrap();
+        let settings = ChatSettings::read(chat_id, &mut conn).await.unwrap();
+        assert_eq!(settings, ChatSettings::default());
+
+        let mut settings = ChatSettings {
+            system_prompt: Some("You are a botanist".to_string()),
+            conversational_model: Some("llama3".to_string()),
+            temperature: Some(0.2),
+            seed: Some(42),
+            ..Default::default()
+        };
+        settings.save(chat_id, &mut conn).await.unwrap();
+        assert_eq!(
+            ChatSettings::read(chat_id, &mut conn).await.unwrap(),
+            settings
+        );
+
+        settings.conversational_model = None;
+        settings.num_ctx = Some(4096);
+        settings.save(chat_id, &mut conn).await.unwrap();
+        assert_eq!(
+            ChatSettings::read(chat_id, &mut conn).await.unwrap(),
+            settings
+        );
+
+        Chat::delete(chat_id, &mut conn).await.unwrap();
+        assert_eq!(
+            ChatSettings::read(chat_id, &mut conn).await.unwrap(),
+            ChatSettings::default()
+        );
+    }
+}
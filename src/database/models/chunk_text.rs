@@ -0,0 +1,268 @@
+use sqlx::FromRow;
+
+use crate::database::DatabaseConnection;
+
+/*
+CREATE VIRTUAL TABLE chunks_fts USING fts5 (
+  collection UNINDEXED,
+  id UNINDEXED,
+  document,
+  metadata UNINDEXED,
+  tokenize = 'unicode61'
+);
+*/
+
+/// The text of an attached chunk, indexed for keyword search
+#[derive(FromRow, Debug)]
+pub struct ChunkText {
+    id: String,
+    document: String,
+    metadata: Option<String>,
+}
+
+impl ChunkText {
+    /// Index a chunk, replacing any chunk in the collection with the same id
+    pub async fn upsert(
+        collection: &str,
+        id: &str,
+        document: &str,
+        metadata: Option<&str>,
+        conn: &mut DatabaseConnection,
+    ) -> Result<(), sqlx::Error> {
+        // FTS5 tables have no constraints to resolve a conflict against
+        Self::delete(collection, id, &mut *conn).await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO chunks_fts (collection, id, document, metadata)
+            VALUES ($1, $2, $3, $4)
+            "#,
+            collection,
+            id,
+            document,
+            metadata
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn read(
+        collection: &str,
+        id: &str,
+        conn: &mut DatabaseConnection,
+    ) -> Result<Option<ChunkText>, sqlx::Error> {
+        let chunk = sqlx::query_as!(
+            ChunkText,
+            r#"
+            SELECT id as "id!: String", document as "document!: String", metadata as "metadata: String"
+            FROM chunks_fts
+            WHERE collection = $1 AND id = $2
+            "#,
+            collection,
+            id
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+        Ok(chunk)
+    }
+
+    /// Find the chunks in a collection best matching a full text `query`, best first
+    pub async fn search(
+        collection: &str,
+        query: &str,
+        limit: i64,
+        conn: &mut DatabaseConnection,
+    ) -> Result<Vec<ChunkText>, sqlx::Error> {
+        let chunks = sqlx::query_as!(
+            ChunkText,
+            r#"
+            SELECT id as "id!: String", document as "document!: String", metadata as "metadata: String"
+            FROM chunks_fts
+            WHERE chunks_fts MATCH $2 AND collection = $1
+            ORDER BY bm25(chunks_fts)
+            LIMIT $3
+            "#,
+            collection,
+            query,
+            limit
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(chunks)
+    }
+
+    /// Replace the metadata of a chunk without reindexing its text
+    pub async fn update_metadata(
+        collection: &str,
+        id: &str,
+        metadata: Option<&str>,
+        conn: &mut DatabaseConnection,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE chunks_fts
+            SET metadata = $3
+            WHERE collection = $1 AND id = $2
+            "#,
+            collection,
+            id,
+            metadata
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete(
+        collection: &str,
+        id: &str,
+        conn: &mut DatabaseConnection,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            DELETE FROM chunks_fts
+            WHERE collection = $1 AND id = $2
+            "#,
+            collection,
+            id
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn rename_collection(
+        from: &str,
+        to: &str,
+        conn: &mut DatabaseConnection,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE chunks_fts
+            SET collection = $2
+            WHERE collection = $1
+            "#,
+            from,
+            to
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_by_collection(
+        collection: &str,
+        conn: &mut DatabaseConnection,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            DELETE FROM chunks_fts
+            WHERE collection = $1
+            "#,
+            collection
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    pub fn id(&self) -> &str {
+        self.id.as_str()
+    }
+
+    pub fn document(&self) -> &str {
+        self.document.as_str()
+    }
+
+    pub fn metadata(&self) -> Option<&str> {
+        self.metadata.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tests::prelude::*;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_upsert_search_delete() {
+        let db_pool = test_database().await;
+        let mut conn = db_pool
+            .acquire()
+            .await
+            .expect("Failed to acquire a connection");
+
+        ChunkText::upsert("chat", "a", "error E1234 on startup", None, &mut conn)
+            .await
+            .unwrap();
+        ChunkText::upsert("chat", "b", "blossoms are pink", None, &mut conn)
+            .await
+            .unwrap();
+        ChunkText::upsert("chat", "b", "blossoms fall", Some("{}"), &mut conn)
+            .await
+            .unwrap();
+        ChunkText::upsert("other", "c", "blossoms elsewhere", None, &mut conn)
+            .await
+            .unwrap();
+
+        let chunks = ChunkText::search("chat", "\"E1234\"", 10, &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].id(), "a");
+
+        let chunks = ChunkText::search("chat", "blossoms", 10, &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].document(), "blossoms fall");
+        assert_eq!(chunks[0].metadata(), Some("{}"));
+
+        let chunk = ChunkText::read("chat", "a", &mut conn).await.unwrap();
+        assert_eq!(chunk.unwrap().document(), "error E1234 on startup");
+        assert!(ChunkText::read("chat", "z", &mut conn)
+            .await
+            .unwrap()
+            .is_none());
+
+        ChunkText::update_metadata("chat", "b", Some("[]"), &mut conn)
+            .await
+            .unwrap();
+        let chunks = ChunkText::search("chat", "blossoms", 10, &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(chunks[0].metadata(), Some("[]"));
+
+        ChunkText::delete("chat", "b", &mut conn).await.unwrap();
+        assert!(ChunkText::search("chat", "blossoms", 10, &mut conn)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            ChunkText::search("other", "blossoms", 10, &mut conn)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        ChunkText::rename_collection("other", "renamed", &mut conn)
+            .await
+            .unwrap();
+        assert!(ChunkText::search("other", "blossoms", 10, &mut conn)
+            .await
+            .unwrap()
+            .is_empty());
+        let chunks = ChunkText::search("renamed", "blossoms", 10, &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(chunks[0].id(), "c");
+        ChunkText::delete_by_collection("renamed", &mut conn)
+            .await
+            .unwrap();
+        assert!(ChunkText::search("renamed", "blossoms", 10, &mut conn)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
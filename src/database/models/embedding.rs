@@ -0,0 +1,288 @@
+use sqlx::FromRow;
+
+use crate::database::types::Vector;
+use crate::database::DatabaseConnection;
+
+/*
+CREATE TABLE embeddings (
+  collection TEXT NOT NULL,
+  id TEXT NOT NULL,
+  document TEXT NOT NULL,
+  embedding BLOB NOT NULL,
+  metadata TEXT,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (collection, id)
+);
+
+CREATE TABLE embedding_collections (
+  collection TEXT NOT NULL PRIMARY KEY,
+  dimension INTEGER NOT NULL
+);
+*/
+
+#[derive(FromRow, Debug)]
+pub struct Embedding {
+    id: String,
+    document: String,
+    embedding: Vector,
+    metadata: Option<String>,
+}
+
+impl Embedding {
+    /// Insert a document into a collection, replacing any document with the same id
+    pub async fn upsert(
+        collection: &str,
+        id: &str,
+        document: &str,
+        embedding: &Vector,
+        metadata: Option<&str>,
+        conn: &mut DatabaseConnection,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO embeddings (collection, id, document, embedding, metadata, created_at)
+            VALUES ($1, $2, $3, $4, $5, CURRENT_TIMESTAMP)
+            ON CONFLICT (collection, id) DO UPDATE SET
+                document = excluded.document,
+                embedding = excluded.embedding,
+                metadata = excluded.metadata,
+                created_at = excluded.created_at
+            "#,
+            collection,
+            id,
+            document,
+            embedding,
+            metadata
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn read_by_collection(
+        collection: &str,
+        conn: &mut DatabaseConnection,
+    ) -> Result<Vec<Embedding>, sqlx::Error> {
+        let embeddings = sqlx::query_as!(
+            Embedding,
+            r#"
+            SELECT id, document, embedding as "embedding: Vector", metadata
+            FROM embeddings
+            WHERE collection = $1
+            "#,
+            collection
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(embeddings)
+    }
+
+    /// Replace the metadata of a document without touching its embedding
+    pub async fn update_metadata(
+        collection: &str,
+        id: &str,
+        metadata: Option<&str>,
+        conn: &mut DatabaseConnection,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE embeddings
+            SET metadata = $3
+            WHERE collection = $1 AND id = $2
+            "#,
+            collection,
+            id,
+            metadata
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete(
+        collection: &str,
+        id: &str,
+        conn: &mut DatabaseConnection,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            DELETE FROM embeddings
+            WHERE collection = $1 AND id = $2
+            "#,
+            collection,
+            id
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    /// The number of dimensions of the embeddings in a collection,
+    /// or `None` if it holds none and so will take whatever is stored next
+    pub async fn dimension(
+        collection: &str,
+        conn: &mut DatabaseConnection,
+    ) -> Result<Option<usize>, sqlx::Error> {
+        let dimension = sqlx::query_scalar!(
+            r#"
+            SELECT dimension
+            FROM embedding_collections
+            WHERE collection = $1
+                AND EXISTS (SELECT 1 FROM embeddings WHERE collection = $1)
+            "#,
+            collection
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+        Ok(dimension.map(|dimension| dimension as usize))
+    }
+
+    pub async fn set_dimension(
+        collection: &str,
+        dimension: usize,
+        conn: &mut DatabaseConnection,
+    ) -> Result<(), sqlx::Error> {
+        let dimension = dimension as i64;
+        sqlx::query!(
+            r#"
+            INSERT INTO embedding_collections (collection, dimension)
+            VALUES ($1, $2)
+            ON CONFLICT (collection) DO UPDATE SET dimension = excluded.dimension
+            "#,
+            collection,
+            dimension
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn rename_collection(
+        from: &str,
+        to: &str,
+        conn: &mut DatabaseConnection,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE embeddings
+            SET collection = $2
+            WHERE collection = $1
+            "#,
+            from,
+            to
+        )
+        .execute(&mut *conn)
+        .await?;
+        sqlx::query!(
+            r#"
+            UPDATE OR REPLACE embedding_collections
+            SET collection = $2
+            WHERE collection = $1
+            "#,
+            from,
+            to
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_by_collection(
+        collection: &str,
+        conn: &mut DatabaseConnection,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            DELETE FROM embeddings
+            WHERE collection = $1
+            "#,
+            collection
+        )
+        .execute(&mut *conn)
+        .await?;
+        sqlx::query!(
+            r#"
+            DELETE FROM embedding_collections
+            WHERE collection = $1
+            "#,
+            collection
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+
+    pub fn id(&self) -> &str {
+        self.id.as_str()
+    }
+
+    pub fn document(&self) -> &str {
+        self.document.as_str()
+    }
+
+    pub fn embedding(&self) -> &Vector {
+        &self.embedding
+    }
+
+    pub fn metadata(&self) -> Option<&str> {
+        self.metadata.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tests::prelude::*;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_upsert_read_delete() {
+        let db_pool = test_database().await;
+        let mut conn = db_pool
+            .acquire()
+            .await
+            .expect("Failed to acquire a connection");
+
+        let vector = Vector::from(vec![1.0, 0.0]);
+        Embedding::upsert("chat", "a", "first", &vector, None, &mut conn)
+            .await
+            .unwrap();
+        Embedding::upsert("chat", "a", "replaced", &vector, Some("{}"), &mut conn)
+            .await
+            .unwrap();
+        Embedding::upsert("other", "a", "elsewhere", &vector, None, &mut conn)
+            .await
+            .unwrap();
+
+        let embeddings = Embedding::read_by_collection("chat", &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(embeddings.len(), 1);
+        assert_eq!(embeddings[0].document(), "replaced");
+        assert_eq!(embeddings[0].embedding(), &vector);
+        assert_eq!(embeddings[0].metadata(), Some("{}"));
+
+        Embedding::update_metadata("chat", "a", Some("[]"), &mut conn)
+            .await
+            .unwrap();
+        let embeddings = Embedding::read_by_collection("chat", &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(embeddings[0].metadata(), Some("[]"));
+
+        Embedding::delete_by_collection("chat", &mut conn)
+            .await
+            .unwrap();
+        assert!(Embedding::read_by_collection("chat", &mut conn)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            Embedding::read_by_collection("other", &mut conn)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
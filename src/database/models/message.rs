@@ -0,0 +1,480 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use sqlx::{Connection, FromRow};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::database::types::DId;
+use crate::database::DatabaseConnection;
+
+/*
+CREATE TABLE messages (
+  id BLOB NOT NULL PRIMARY KEY DEFAULT (randomblob(16)),
+  chat_id BLOB NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+  parent_id BLOB REFERENCES messages(id) ON DELETE CASCADE,
+  position INTEGER NOT NULL,
+  role TEXT NOT NULL,
+  content TEXT NOT NULL,
+  tool_name TEXT,
+  tool_arguments TEXT,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE (chat_id, position)
+);
+*/
+
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(rename_all = "lowercase")]
+pub enum MessageRole {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+impl Display for MessageRole {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let role = match self {
+            MessageRole::System => "system",
+            MessageRole::User => "user",
+            MessageRole::Assistant => "assistant",
+            MessageRole::Tool => "tool",
+        };
+        write!(f, "{}", role)
+    }
+}
+
+impl FromStr for MessageRole {
+    type Err = UnknownMessageRole;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "system" => Ok(MessageRole::System),
+            "user" => Ok(MessageRole::User),
+            "assistant" => Ok(MessageRole::Assistant),
+            "tool" => Ok(MessageRole::Tool),
+            _ => Err(UnknownMessageRole(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("unknown message role: {0}")]
+pub struct UnknownMessageRole(String);
+
+#[derive(FromRow, Debug)]
+pub struct Message {
+    id: DId,
+    chat_id: DId,
+    parent_id: Option<DId>,
+    position: i64,
+    role: MessageRole,
+    content: String,
+    tool_name: Option<String>,
+    tool_arguments: Option<String>,
+    created_at: OffsetDateTime,
+}
+
+impl Message {
+    /// Append a message to the branch of the chat currently being followed, making it the head.
+    /// Messages from tools record the tool's name and the JSON arguments it was called with.
+    /// The message and the new head are written in one transaction, or a savepoint
+    /// if `conn` is already in one, so the chat never points at the wrong message.
+    pub async fn create(
+        chat_id: Uuid,
+        role: MessageRole,
+        content: &str,
+        tool_name: Option<&str>,
+        tool_arguments: Option<&str>,
+        conn: &mut DatabaseConnection,
+    ) -> Result<Uuid, sqlx::Error> {
+        Self::insert(
+            chat_id,
+            role,
+            content,
+            tool_name,
+            tool_arguments,
+            None,
+            conn,
+        )
+        .await
+    }
+
+    /// Append a message written at `created_at`, such as one being imported, as `create` does
+    pub async fn create_at(
+        chat_id: Uuid,
+        role: MessageRole,
+        content: &str,
+        tool_name: Option<&str>,
+        tool_arguments: Option<&str>,
+        created_at: OffsetDateTime,
+        conn: &mut DatabaseConnection,
+    ) -> Result<Uuid, sqlx::Error> {
+        Self::insert(
+            chat_id,
+            role,
+            content,
+            tool_name,
+            tool_arguments,
+            Some(created_at),
+            conn,
+        )
+        .await
+    }
+
+    async fn insert(
+        chat_id: Uuid,
+        role: MessageRole,
+        content: &str,
+        tool_name: Option<&str>,
+        tool_arguments: Option<&str>,
+        created_at: Option<OffsetDateTime>,
+        conn: &mut DatabaseConnection,
+    ) -> Result<Uuid, sqlx::Error> {
+        let chat_id: DId = chat_id.into();
+        let mut transaction = conn.begin().await?;
+        let message_id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO messages (chat_id, parent_id, position, role, content, tool_name, tool_arguments, created_at)
+            VALUES (
+                $1,
+                (SELECT head_id FROM chats WHERE id = $1),
+                (SELECT COALESCE(MAX(position) + 1, 0) FROM messages WHERE chat_id = $1),
+                $2,
+                $3,
+                $4,
+                $5,
+                COALESCE($6, CURRENT_TIMESTAMP)
+            )
+            RETURNING id as 'id: DId'"#,
+            chat_id,
+            role,
+            content,
+            tool_name,
+            tool_arguments,
+            created_at
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+        sqlx::query!(
+            r#"
+            UPDATE chats
+            SET head_id = $2
+            WHERE id = $1
+            "#,
+            chat_id,
+            message_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(*message_id.to_owned())
+    }
+
+    /// Read all messages belonging to a chat across every branch, in the order they were written
+    pub async fn read_by_chat(
+        chat_id: Uuid,
+        conn: &mut DatabaseConnection,
+    ) -> Result<Vec<Message>, sqlx::Error> {
+        let chat_id: DId = chat_id.into();
+        let messages = sqlx::query_as!(
+            Message,
+            r#"
+            SELECT
+                id as "id: DId",
+                chat_id as "chat_id: DId",
+                parent_id as "parent_id: DId",
+                position,
+                role as "role: MessageRole",
+                content,
+                tool_name,
+                tool_arguments,
+                created_at
+            FROM messages
+            WHERE chat_id = $1
+            ORDER BY position ASC
+            "#,
+            chat_id
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(messages)
+    }
+
+    /// Read the branch of a chat currently being followed, from its first message to its head
+    pub async fn read_branch(
+        chat_id: Uuid,
+        conn: &mut DatabaseConnection,
+    ) -> Result<Vec<Message>, sqlx::Error> {
+        let chat_id: DId = chat_id.into();
+        let messages = sqlx::query_as!(
+            Message,
+            r#"
+            WITH RECURSIVE branch (id, depth) AS (
+                SELECT head_id, 0 FROM chats WHERE id = $1 AND head_id IS NOT NULL
+                UNION ALL
+                SELECT messages.parent_id, branch.depth + 1
+                FROM messages JOIN branch ON messages.id = branch.id
+                WHERE messages.parent_id IS NOT NULL
+            )
+            SELECT
+                messages.id as "id!: DId",
+                messages.chat_id as "chat_id!: DId",
+                messages.parent_id as "parent_id: DId",
+                messages.position as "position!: i64",
+                messages.role as "role!: MessageRole",
+                messages.content as "content!: String",
+                messages.tool_name as "tool_name: String",
+                messages.tool_arguments as "tool_arguments: String",
+                messages.created_at as "created_at!: OffsetDateTime"
+            FROM messages JOIN branch ON messages.id = branch.id
+            ORDER BY branch.depth DESC
+            "#,
+            chat_id
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(messages)
+    }
+
+    /// Read the last message of every branch in a chat, in the order they were written
+    pub async fn read_leaves(
+        chat_id: Uuid,
+        conn: &mut DatabaseConnection,
+    ) -> Result<Vec<Message>, sqlx::Error> {
+        let chat_id: DId = chat_id.into();
+        let messages = sqlx::query_as!(
+            Message,
+            r#"
+            SELECT
+                id as "id: DId",
+                chat_id as "chat_id: DId",
+                parent_id as "parent_id: DId",
+                position,
+                role as "role: MessageRole",
+                content,
+                tool_name,
+                tool_arguments,
+                created_at
+            FROM messages
+            WHERE chat_id = $1
+                AND NOT EXISTS (SELECT 1 FROM messages children WHERE children.parent_id = messages.id)
+            ORDER BY position ASC
+            "#,
+            chat_id
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(messages)
+    }
+
+    pub fn id(&self) -> Uuid {
+        *self.id.to_owned()
+    }
+
+    pub fn chat_id(&self) -> Uuid {
+        *self.chat_id.to_owned()
+    }
+
+    /// The message this one follows on, or `None` for the first message of a branch
+    pub fn parent_id(&self) -> Option<Uuid> {
+        self.parent_id.as_ref().map(|parent_id| **parent_id)
+    }
+
+    pub fn position(&self) -> i64 {
+        self.position
+    }
+
+    pub fn role(&self) -> MessageRole {
+        self.role
+    }
+
+    pub fn content(&self) -> &str {
+        self.content.as_str()
+    }
+
+    pub fn tool_name(&self) -> Option<&str> {
+        self.tool_name.as_deref()
+    }
+
+    pub fn tool_arguments(&self) -> Option<&str> {
+        self.tool_arguments.as_deref()
+    }
+
+    pub fn created_at(&self) -> OffsetDateTime {
+        self.created_at
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::database::models::Chat;
+    use crate::tests::prelude::*;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_create_read_by_chat() {
+        let db_pool = test_database().await;
+        let mut conn = db_pool
+            .acquire()
+            .await
+            .expect("Failed to acquire a connection");
+
+        let chat_id = Chat::create("test_chat", &mut conn).await.unwrap();
+        let other_chat_id = Chat::create("other_chat", &mut conn).await.unwrap();
+
+        Message::create(chat_id, MessageRole::User, "hello", None, None, &mut conn)
+            .await
+            .unwrap();
+        Message::create(
+            chat_id,
+            MessageRole::Assistant,
+            "hi there",
+            Some("converse"),
+            Some(r#"{"input":"hi"}"#),
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        Message::create(
+            other_chat_id,
+            MessageRole::User,
+            "elsewhere",
+            None,
+            None,
+            &mut conn,
+        )
+        .await
+        .unwrap();
+
+        let messages = Message::read_by_chat(chat_id, &mut conn).await.unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].position(), 0);
+        assert_eq!(messages[0].role(), MessageRole::User);
+        assert_eq!(messages[0].content(), "hello");
+        assert_eq!(messages[0].tool_name(), None);
+        assert_eq!(messages[1].position(), 1);
+        assert_eq!(messages[1].role(), MessageRole::Assistant);
+        assert_eq!(messages[1].tool_name(), Some("converse"));
+        assert_eq!(messages[1].tool_arguments(), Some(r#"{"input":"hi"}"#));
+
+        let other_messages = Message::read_by_chat(other_chat_id, &mut conn)
+            .await
+            .unwrap();
+        assert_eq!(other_messages.len(), 1);
+        assert_eq!(other_messages[0].position(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_create_concurrently() {
+        let (database, directory) = test_file_database().await;
+        let mut conn = database.acquire().await.unwrap();
+        let chat_id = Chat::create("test_chat", &mut conn).await.unwrap();
+
+        // Every message lands on the head left by the one before it
+        let writers = (0..8).map(|i| {
+            let database = database.clone();
+            tokio::spawn(async move {
+                let mut conn = database.acquire().await.unwrap();
+                let content = format!("message {}", i);
+                Message::create(chat_id, MessageRole::User, &content, None, None, &mut conn)
+                    .await
+                    .unwrap();
+            })
+        });
+        for writer in writers.collect::<Vec<_>>() {
+            writer.await.unwrap();
+        }
+        let branch = Message::read_branch(chat_id, &mut conn).await.unwrap();
+        assert_eq!(branch.len(), 8);
+        let positions = branch.iter().map(|m| m.position()).collect::<Vec<_>>();
+        assert_eq!(positions, (0..8).collect::<Vec<_>>());
+
+        // Rolling back the caller's transaction takes the message and the new head with it
+        let mut transaction = conn.begin().await.unwrap();
+        Message::create(
+            chat_id,
+            MessageRole::User,
+            "undone",
+            None,
+            None,
+            &mut transaction,
+        )
+        .await
+        .unwrap();
+        transaction.rollback().await.unwrap();
+        let branch = Message::read_branch(chat_id, &mut conn).await.unwrap();
+        assert_eq!(branch.len(), 8);
+        drop(conn);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_branches() {
+        let db_pool = test_database().await;
+        let mut conn = db_pool
+            .acquire()
+            .await
+            .expect("Failed to acquire a connection");
+
+        let chat_id = Chat::create("test_chat", &mut conn).await.unwrap();
+        let question = Message::create(chat_id, MessageRole::User, "q", None, None, &mut conn)
+            .await
+            .unwrap();
+        let first = Message::create(chat_id, MessageRole::Assistant, "a1", None, None, &mut conn)
+            .await
+            .unwrap();
+
+        // Branch off after the question
+        Chat::set_head(chat_id, Some(question), &mut conn)
+            .await
+            .unwrap();
+        let second = Message::create(chat_id, MessageRole::Assistant, "a2", None, None, &mut conn)
+            .await
+            .unwrap();
+        let branch = Message::read_branch(chat_id, &mut conn).await.unwrap();
+        let contents = branch.iter().map(|m| m.content()).collect::<Vec<_>>();
+        assert_eq!(contents, vec!["q", "a2"]);
+        assert_eq!(branch[1].parent_id(), Some(question));
+        assert_eq!(branch[0].parent_id(), None);
+
+        // Both answers are still around, each at the end of a branch
+        let leaves = Message::read_leaves(chat_id, &mut conn).await.unwrap();
+        let ids = leaves.iter().map(|m| m.id()).collect::<Vec<_>>();
+        assert_eq!(ids, vec![first, second]);
+        assert_eq!(
+            Message::read_by_chat(chat_id, &mut conn)
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+
+        Chat::set_head(chat_id, Some(first), &mut conn)
+            .await
+            .unwrap();
+        let branch = Message::read_branch(chat_id, &mut conn).await.unwrap();
+        assert_eq!(branch[1].content(), "a1");
+
+        // Starting over from the very beginning gives an empty branch
+        Chat::set_head(chat_id, None, &mut conn).await.unwrap();
+        assert!(Message::read_branch(chat_id, &mut conn)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_role_round_trip() {
+        for role in [
+            MessageRole::System,
+            MessageRole::User,
+            MessageRole::Assistant,
+            MessageRole::Tool,
+        ] {
+            assert_eq!(role.to_string().parse::<MessageRole>().unwrap(), role);
+        }
+        assert!("robot".parse::<MessageRole>().is_err());
+    }
+}
@@ -1,3 +1,13 @@
+mod attachment;
 mod chat;
+mod chat_settings;
+mod chunk_text;
+mod embedding;
+mod message;
 
+pub use attachment::{Attachment, AttachmentChunk};
 pub use chat::Chat;
+pub use chat_settings::ChatSettings;
+pub use chunk_text::ChunkText;
+pub use embedding::Embedding;
+pub use message::{Message, MessageRole, UnknownMessageRole};
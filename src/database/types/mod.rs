@@ -1,3 +1,5 @@
 mod did;
+mod vector;
 
 pub use did::DId;
+pub use vector::Vector;
@@ -0,0 +1,119 @@
+use std::borrow::Cow;
+use std::fmt::{self, Debug, Formatter};
+use std::ops::Deref;
+
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef};
+use sqlx::{Decode, Encode, Sqlite, Type};
+
+/// An embedding vector, stored as a blob of little-endian `f32`s
+#[derive(Clone, Default, PartialEq)]
+pub struct Vector(Vec<f32>);
+
+impl Decode<'_, Sqlite> for Vector {
+    fn decode(value: SqliteValueRef<'_>) -> Result<Self, BoxDynError> {
+        let inner_val = <Vec<u8> as Decode<Sqlite>>::decode(value)?;
+
+        if inner_val.len() % 4 != 0 {
+            return Err(VectorError::CorruptSize.into());
+        }
+
+        let values = inner_val
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        Ok(Self(values))
+    }
+}
+
+impl Encode<'_, Sqlite> for Vector {
+    fn encode_by_ref(&self, args: &mut Vec<SqliteArgumentValue<'_>>) -> IsNull {
+        let encoded_bytes = self
+            .0
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<u8>>();
+        args.push(SqliteArgumentValue::Blob(Cow::Owned(encoded_bytes)));
+        IsNull::No
+    }
+}
+
+impl Type<Sqlite> for Vector {
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <Vec<u8> as Type<Sqlite>>::compatible(ty)
+    }
+
+    fn type_info() -> SqliteTypeInfo {
+        <Vec<u8> as Type<Sqlite>>::type_info()
+    }
+}
+
+impl Debug for Vector {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.0, f)
+    }
+}
+
+impl Deref for Vector {
+    type Target = [f32];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<Vec<f32>> for Vector {
+    fn from(val: Vec<f32>) -> Self {
+        Self(val)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum VectorError {
+    #[error("the vector representation isn't a whole number of f32s")]
+    CorruptSize,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tests::prelude::*;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sqlx_round_trip() {
+        let db_pool = test_database().await;
+        let mut transact = db_pool.begin().await.expect("transaction");
+
+        sqlx::query("CREATE TABLE vector_encoding_test (vector BLOB NOT NULL);")
+            .execute(&mut *transact)
+            .await
+            .expect("setup to succeed");
+
+        let sample = Vector::from(vec![1.0, -0.5, 0.25]);
+        let returned: Vector = sqlx::query_scalar(
+            "INSERT INTO vector_encoding_test (vector) VALUES ($1) RETURNING vector;",
+        )
+        .bind(sample.clone())
+        .fetch_one(&mut *transact)
+        .await
+        .expect("insert to succeed");
+        assert_eq!(sample, returned);
+
+        let raw: Vec<u8> = sqlx::query_scalar("SELECT vector FROM vector_encoding_test;")
+            .fetch_one(&mut *transact)
+            .await
+            .expect("return to succeed");
+        assert_eq!(raw.len(), 12);
+        assert_eq!(&raw[..4], &1.0f32.to_le_bytes());
+
+        let corrupt: Result<Vector, _> =
+            sqlx::query_scalar("SELECT CAST(X'00112233445566' AS BLOB);")
+                .fetch_one(&mut *transact)
+                .await;
+        assert!(matches!(corrupt, Err(sqlx::Error::ColumnDecode { .. })));
+
+        transact.rollback().await.expect("rollback")
+    }
+}
@@ -0,0 +1,716 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use futures::{stream, StreamExt};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::agent::{is_image, LlmEngine, LlmEngineError};
+use crate::chunking::Chunker;
+use crate::database::models::{Attachment, AttachmentChunk, ChunkText};
+use crate::database::Database;
+use crate::loaders::{LoaderError, LoaderRegistry};
+use crate::retrieval::{self, RetrievalError};
+use crate::vector_store::{Passage, Source, VectorDocument, VectorStore, VectorStoreError};
+
+/// How many chunks are written to the vector store at once
+pub const DEFAULT_BATCH_SIZE: usize = 16;
+/// How many chunks are embedded at the same time
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Progress reported while a file is indexed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress {
+    /// The file was split into chunks, some of which changed since it was last indexed
+    Chunked { chunks: usize, changed: usize },
+    /// Another changed chunk was embedded
+    Embedded { done: usize, total: usize },
+}
+
+/// What came of indexing a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indexed {
+    /// The file hasn't changed since it was last indexed
+    Unchanged,
+    /// Changed chunks were embedded and chunks no longer in the file were removed
+    Updated {
+        chunks: usize,
+        embedded: usize,
+        removed: usize,
+    },
+}
+
+/// Loads, chunks, embeds and stores files so they can be searched later.
+/// Every chunk is identified by a hash of its text, so re-indexing a file
+/// only embeds the chunks that changed.
+pub struct Indexer {
+    database: Database,
+    engine: LlmEngine,
+    vector_store: Arc<dyn VectorStore>,
+    loaders: LoaderRegistry,
+    chunker: Chunker,
+    batch_size: usize,
+    concurrency: usize,
+}
+
+impl Indexer {
+    pub fn new(
+        database: Database,
+        engine: LlmEngine,
+        vector_store: Arc<dyn VectorStore>,
+        loaders: LoaderRegistry,
+        chunker: Chunker,
+    ) -> Self {
+        Self {
+            database,
+            engine,
+            vector_store,
+            loaders,
+            chunker,
+            batch_size: DEFAULT_BATCH_SIZE,
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn loaders(&self) -> &LoaderRegistry {
+        &self.loaders
+    }
+
+    /// Index a file attached to a chat into the chat's collection.
+    /// Changed chunks are written in batches as they are embedded, and the file
+    /// is only marked as indexed once everything succeeded, so a failure part
+    /// way through picks up where it left off next time.
+    pub async fn index_file(
+        &self,
+        chat_id: Uuid,
+        path: &Path,
+        collection: &str,
+        mut on_progress: impl FnMut(Progress),
+    ) -> Result<Indexed, IngestError> {
+        // Track files by absolute path so they match however they were named
+        let path = std::fs::canonicalize(path).map_err(LoaderError::Io)?;
+        let path_name = path.display().to_string();
+        let bytes = std::fs::read(&path).map_err(LoaderError::Io)?;
+        let content_hash = hash(&bytes);
+
+        let mut conn = self.database.acquire().await?;
+        let attachment = Attachment::get_or_create(chat_id, &path_name, &mut conn).await?;
+        // Embeddings from another model can't be reused, so everything is embedded again
+        let same_model = attachment.embedding_model() == Some(self.engine.embedding_model());
+        if attachment.content_hash() == content_hash && same_model {
+            return Ok(Indexed::Unchanged);
+        }
+        let stored = AttachmentChunk::read_by_attachment(attachment.id(), &mut conn)
+            .await?
+            .into_iter()
+            .map(|chunk| {
+                (
+                    chunk.content_hash().to_string(),
+                    chunk.vector_id().to_string(),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+
+        // Identical chunks only need storing once
+        let text = self.loaders.load(&path)?;
+        let mut hashes = HashSet::new();
+        let chunks = self
+            .chunker
+            .chunk_path(&path, &text)
+            .into_iter()
+            .map(|chunk| (hash(chunk.text.as_bytes()), chunk))
+            .filter(|(chunk_hash, _)| hashes.insert(chunk_hash.clone()))
+            .collect::<Vec<_>>();
+        let source = |start, end| Source {
+            path: path_name.clone(),
+            start,
+            end,
+        };
+        let (kept, changed): (Vec<_>, Vec<_>) = chunks
+            .into_iter()
+            .partition(|(chunk_hash, _)| same_model && stored.contains_key(chunk_hash));
+        let total = changed.len();
+        on_progress(Progress::Chunked {
+            chunks: hashes.len(),
+            changed: total,
+        });
+
+        let mut embeddings = stream::iter(changed)
+            .map(|(chunk_hash, chunk)| {
+                let id = format!("{}#{}", path_name, chunk_hash);
+                let source = source(chunk.start, chunk.end);
+                async move {
+                    let embedding = self.engine.embed(&chunk.text).await?;
+                    let document = VectorDocument {
+                        id,
+                        document: chunk.text,
+                        embedding: embedding.iter().map(|x| *x as f32).collect(),
+                        source: Some(source),
+                    };
+                    Ok::<_, IngestError>((chunk_hash, document))
+                }
+            })
+            .buffered(self.concurrency);
+
+        let mut batch = Vec::with_capacity(self.batch_size);
+        let mut done = 0;
+        while let Some(embedded) = embeddings.next().await {
+            batch.push(embedded?);
+            done += 1;
+            on_progress(Progress::Embedded { done, total });
+            if batch.len() >= self.batch_size {
+                self.store(attachment.id(), collection, std::mem::take(&mut batch))
+                    .await?;
+            }
+        }
+        self.store(attachment.id(), collection, batch).await?;
+
+        // Chunks which didn't change may still have moved within the file
+        let moved = kept
+            .into_iter()
+            .map(|(chunk_hash, chunk)| {
+                (stored[&chunk_hash].clone(), source(chunk.start, chunk.end))
+            })
+            .collect::<Vec<_>>();
+        let mut conn = self.database.begin().await?;
+        for (id, source) in moved.iter() {
+            let metadata = serde_json::to_string(source).map_err(RetrievalError::from)?;
+            ChunkText::update_metadata(collection, id, Some(&metadata), &mut conn).await?;
+        }
+        conn.commit().await?;
+        self.vector_store.update_sources(collection, moved).await?;
+
+        // Drop whatever is no longer in the file
+        let removed = stored
+            .into_iter()
+            .filter(|(chunk_hash, _)| !hashes.contains(chunk_hash))
+            .collect::<Vec<_>>();
+        let removed_ids = removed.iter().map(|(_, id)| id.clone()).collect::<Vec<_>>();
+        self.vector_store.delete(collection, &removed_ids).await?;
+        let mut conn = self.database.begin().await?;
+        for (chunk_hash, id) in removed.iter() {
+            AttachmentChunk::delete(attachment.id(), chunk_hash, &mut conn).await?;
+            ChunkText::delete(collection, id, &mut conn).await?;
+        }
+        Attachment::mark_indexed(
+            attachment.id(),
+            &content_hash,
+            bytes.len() as i64,
+            hashes.len() as i64,
+            self.engine.embedding_model(),
+            &mut conn,
+        )
+        .await?;
+        conn.commit().await?;
+
+        Ok(Indexed::Updated {
+            chunks: hashes.len(),
+            embedded: total,
+            removed: removed.len(),
+        })
+    }
+
+    /// Attach an image to a chat. Images aren't indexed, they are only
+    /// kept track of so the image tool knows which files it may read.
+    pub async fn attach_image(&self, chat_id: Uuid, path: &Path) -> Result<(), IngestError> {
+        let path = std::fs::canonicalize(path).map_err(LoaderError::Io)?;
+        let mut conn = self.database.acquire().await?;
+        Attachment::get_or_create(chat_id, &path.display().to_string(), &mut conn).await?;
+        Ok(())
+    }
+
+    /// The images attached to a chat, ordered by path
+    pub async fn images(&self, chat_id: Uuid) -> Result<Vec<PathBuf>, IngestError> {
+        Ok(self
+            .attachments(chat_id)
+            .await?
+            .iter()
+            .map(|attachment| PathBuf::from(attachment.path()))
+            .filter(|path| is_image(path))
+            .collect())
+    }
+
+    /// Every file attached to a chat, ordered by path
+    pub async fn attachments(&self, chat_id: Uuid) -> Result<Vec<Attachment>, IngestError> {
+        let mut conn = self.database.acquire().await?;
+        Ok(Attachment::read_by_chat(chat_id, &mut conn).await?)
+    }
+
+    /// The attachment for a path within a chat, if it has been attached
+    pub async fn attachment(
+        &self,
+        chat_id: Uuid,
+        path: &Path,
+    ) -> Result<Option<Attachment>, IngestError> {
+        let mut conn = self.database.acquire().await?;
+        match Attachment::read_by_path(chat_id, &attachment_path(path), &mut conn).await {
+            Ok(attachment) => Ok(Some(attachment)),
+            Err(sqlx::Error::RowNotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The indexed chunks of an attachment, in the order they appear in the file
+    pub async fn chunks(
+        &self,
+        attachment: &Attachment,
+        collection: &str,
+    ) -> Result<Vec<Passage>, IngestError> {
+        let mut conn = self.database.acquire().await?;
+        let mut passages = Vec::new();
+        for chunk in AttachmentChunk::read_by_attachment(attachment.id(), &mut conn).await? {
+            if let Some(text) = ChunkText::read(collection, chunk.vector_id(), &mut conn).await? {
+                passages.push(retrieval::passage(&text)?);
+            }
+        }
+        passages.sort_by_key(|passage| passage.source.as_ref().map(|source| source.start));
+        Ok(passages)
+    }
+
+    /// Remove a file from a chat along with everything indexed from it,
+    /// returning how many chunks were removed or `None` if it wasn't attached
+    pub async fn detach(
+        &self,
+        chat_id: Uuid,
+        path: &Path,
+        collection: &str,
+    ) -> Result<Option<usize>, IngestError> {
+        let Some(attachment) = self.attachment(chat_id, path).await? else {
+            return Ok(None);
+        };
+        let mut conn = self.database.acquire().await?;
+        let ids = AttachmentChunk::read_by_attachment(attachment.id(), &mut conn)
+            .await?
+            .into_iter()
+            .map(|chunk| chunk.vector_id().to_string())
+            .collect::<Vec<_>>();
+        drop(conn);
+        // The vector store may write to the same database, so it goes first
+        // rather than leaving a transaction with an outdated snapshot
+        self.vector_store.delete(collection, &ids).await?;
+        let mut conn = self.database.begin().await?;
+        for id in ids.iter() {
+            ChunkText::delete(collection, id, &mut conn).await?;
+        }
+        Attachment::delete(attachment.id(), &mut conn).await?;
+        conn.commit().await?;
+        Ok(Some(ids.len()))
+    }
+
+    /// Remove everything indexed into a collection, such as when its chat is deleted
+    pub async fn delete_collection(&self, collection: &str) -> Result<(), IngestError> {
+        self.vector_store.delete_collection(collection).await?;
+        let mut conn = self.database.acquire().await?;
+        ChunkText::delete_by_collection(collection, &mut conn).await?;
+        Ok(())
+    }
+
+    /// Move everything indexed into a collection over to a new name
+    pub async fn rename_collection(&self, from: &str, to: &str) -> Result<(), IngestError> {
+        if from == to {
+            return Ok(());
+        }
+        self.vector_store.rename_collection(from, to).await?;
+        let mut conn = self.database.acquire().await?;
+        ChunkText::rename_collection(from, to, &mut conn).await?;
+        Ok(())
+    }
+
+    /// Write a batch of embedded chunks to the vector store, then index their text
+    /// and record them against the attachment
+    async fn store(
+        &self,
+        attachment_id: Uuid,
+        collection: &str,
+        batch: Vec<(String, VectorDocument)>,
+    ) -> Result<(), IngestError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let (hashes, documents): (Vec<_>, Vec<_>) = batch.into_iter().unzip();
+        self.vector_store
+            .upsert(collection, documents.clone())
+            .await?;
+
+        let mut conn = self.database.begin().await?;
+        for (chunk_hash, document) in hashes.iter().zip(documents.iter()) {
+            retrieval::index_text(
+                collection,
+                &document.id,
+                &document.document,
+                document.source.as_ref(),
+                &mut conn,
+            )
+            .await?;
+            AttachmentChunk::create(attachment_id, chunk_hash, &document.id, &mut conn).await?;
+        }
+        conn.commit().await?;
+        Ok(())
+    }
+}
+
+/// Attachments are tracked by absolute path. Files which no longer exist can't
+/// be canonicalized, so are resolved against the working directory instead.
+fn attachment_path(path: &Path) -> String {
+    let path = std::fs::canonicalize(path)
+        .or_else(|_| std::env::current_dir().map(|dir| dir.join(path)))
+        .unwrap_or_else(|_| path.to_path_buf());
+    path.display().to_string()
+}
+
+fn hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum IngestError {
+    #[error("loader error: {0}")]
+    Loader(#[from] LoaderError),
+    #[error("engine error: {0}")]
+    Engine(#[from] LlmEngineError),
+    #[error("vector store error: {0}")]
+    VectorStore(#[from] VectorStoreError),
+    #[error("retrieval error: {0}")]
+    Retrieval(#[from] RetrievalError),
+    #[error("sqlx error: {0}")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use crate::chunking::ChunkingStrategy;
+    use crate::database::models::Chat;
+    use crate::tests::prelude::*;
+    use crate::vector_store::SqliteVectorStore;
+
+    use super::*;
+
+    fn sentences(range: std::ops::Range<usize>) -> String {
+        range
+            .map(|i| format!("Sentence number {} is here.", i))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn embeds(mock: &MockLanguageModel) -> usize {
+        mock.requests()
+            .iter()
+            .filter(|request| matches!(request, MockRequest::Embed { .. }))
+            .count()
+    }
+
+    #[tokio::test]
+    async fn test_index_file_only_embeds_changes() {
+        let path =
+            std::env::temp_dir().join(format!("blossom-index-{}.txt", rand::random::<u64>()));
+        std::fs::write(&path, sentences(0..7)).unwrap();
+
+        let database = test_database().await;
+        let mut conn = database.acquire().await.unwrap();
+        let chat_id = Chat::create("test_chat", &mut conn).await.unwrap();
+        let mock = MockLanguageModel::new();
+        let vector_store = Arc::new(SqliteVectorStore::new(database.clone()));
+        let chunker = Chunker::new(10, 0).with_strategy(ChunkingStrategy::Sentences);
+        let indexer = Indexer::new(
+            database,
+            mock.engine(),
+            vector_store.clone(),
+            LoaderRegistry::default(),
+            chunker,
+        )
+        .with_batch_size(3)
+        .with_concurrency(2);
+        let stored = || async {
+            let query = vec![1.0; MOCK_EMBEDDING_DIMENSIONS];
+            vector_store.query("chat", &query, 100).await.unwrap()
+        };
+
+        let mut events = Vec::new();
+        let indexed = indexer
+            .index_file(chat_id, &path, "chat", |progress| events.push(progress))
+            .await
+            .unwrap();
+        assert_eq!(
+            indexed,
+            Indexed::Updated {
+                chunks: 7,
+                embedded: 7,
+                removed: 0
+            }
+        );
+        assert_eq!(
+            events.first(),
+            Some(&Progress::Chunked {
+                chunks: 7,
+                changed: 7
+            })
+        );
+        assert_eq!(
+            events.last(),
+            Some(&Progress::Embedded { done: 7, total: 7 })
+        );
+        // The final partial batch makes it into the store too
+        assert_eq!(stored().await.len(), 7);
+        assert_eq!(embeds(&mock), 7);
+
+        // Nothing is embedded again for an unchanged file
+        let indexed = indexer.index_file(chat_id, &path, "chat", |_| {}).await;
+        assert_eq!(indexed.unwrap(), Indexed::Unchanged);
+        assert_eq!(embeds(&mock), 7);
+
+        // Only new chunks are embedded, and removed ones are dropped
+        std::fs::write(&path, sentences(2..9)).unwrap();
+        let indexed = indexer.index_file(chat_id, &path, "chat", |_| {}).await;
+        assert_eq!(
+            indexed.unwrap(),
+            Indexed::Updated {
+                chunks: 7,
+                embedded: 2,
+                removed: 2
+            }
+        );
+        assert_eq!(embeds(&mock), 9);
+        let mut documents = stored()
+            .await
+            .into_iter()
+            .map(|m| m.document)
+            .collect::<Vec<_>>();
+        documents.sort();
+        assert_eq!(documents.len(), 7);
+        assert_eq!(documents[0], "Sentence number 2 is here.");
+
+        // Kept chunks point at where they now sit in the file
+        let first = stored()
+            .await
+            .into_iter()
+            .find(|m| m.document == "Sentence number 2 is here.")
+            .unwrap();
+        let source = first.source.unwrap();
+        assert_eq!(source.start, 0);
+        assert_eq!(source.end, "Sentence number 2 is here.".len());
+        assert!(source.path.ends_with(".txt"));
+
+        // Chunks are indexed by keyword too, and dropped along with their embeddings
+        let mut conn = indexer.database.acquire().await.unwrap();
+        let found = ChunkText::search("chat", "8", 10, &mut conn).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].metadata().map(|m| m.contains("\"start\"")),
+            Some(true)
+        );
+        let found = ChunkText::search("chat", "0", 10, &mut conn).await.unwrap();
+        assert!(found.is_empty());
+
+        let attachment = indexer.attachment(chat_id, &path).await.unwrap().unwrap();
+        assert_eq!(attachment.chunk_count(), 7);
+        assert_eq!(attachment.size(), sentences(2..9).len() as i64);
+        assert_eq!(attachment.embedding_model(), Some("embedding"));
+        let chunks = indexer.chunks(&attachment, "chat").await.unwrap();
+        assert_eq!(chunks.len(), 7);
+        assert_eq!(chunks[0].text, "Sentence number 2 is here.");
+        assert_eq!(chunks[6].text, "Sentence number 8 is here.");
+
+        // Detaching removes the file and everything indexed from it
+        let removed = indexer.detach(chat_id, &path, "chat").await.unwrap();
+        assert_eq!(removed, Some(7));
+        assert!(stored().await.is_empty());
+        assert!(indexer.attachments(chat_id).await.unwrap().is_empty());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(indexer.detach(chat_id, &path, "chat").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_index_file_embeds_again_for_new_model() {
+        let path =
+            std::env::temp_dir().join(format!("blossom-index-{}.txt", rand::random::<u64>()));
+        std::fs::write(&path, sentences(0..3)).unwrap();
+
+        let database = test_database().await;
+        let mut conn = database.acquire().await.unwrap();
+        let chat_id = Chat::create("test_chat", &mut conn).await.unwrap();
+        let mock = MockLanguageModel::new();
+        let vector_store = Arc::new(SqliteVectorStore::new(database.clone()));
+        let chunker = Chunker::new(10, 0).with_strategy(ChunkingStrategy::Sentences);
+        let indexer = |engine: LlmEngine| {
+            Indexer::new(
+                database.clone(),
+                engine,
+                vector_store.clone(),
+                LoaderRegistry::default(),
+                chunker,
+            )
+        };
+
+        indexer(mock.engine())
+            .index_file(chat_id, &path, "chat", |_| {})
+            .await
+            .unwrap();
+        let engine = mock.engine().with_embedding_model("other".to_string());
+        let indexed = indexer(engine)
+            .index_file(chat_id, &path, "chat", |_| {})
+            .await
+            .unwrap();
+        assert_eq!(
+            indexed,
+            Indexed::Updated {
+                chunks: 3,
+                embedded: 3,
+                removed: 0
+            }
+        );
+        assert_eq!(embeds(&mock), 6);
+        let query = vec![1.0; MOCK_EMBEDDING_DIMENSIONS];
+        assert_eq!(
+            vector_store.query("chat", &query, 100).await.unwrap().len(),
+            3
+        );
+        let attachment = indexer(mock.engine())
+            .attachment(chat_id, &path)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(attachment.embedding_model(), Some("other"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_index_file_keeps_file_offsets() {
+        let files = [
+            (
+                "txt",
+                "\n\nFirst sentence here.   \n\n\n\nSecond sentence here.\t\nThird one.  \n",
+            ),
+            (
+                "rs",
+                "use std::fmt;  \n\n\n\nfn main() {\n    println!(\"hi\");   \n}\n\n\nfn other() {}\n",
+            ),
+        ];
+        for (extension, content) in files {
+            let path = std::env::temp_dir().join(format!(
+                "blossom-index-{}.{}",
+                rand::random::<u64>(),
+                extension
+            ));
+            std::fs::write(&path, content).unwrap();
+
+            let database = test_database().await;
+            let mut conn = database.acquire().await.unwrap();
+            let chat_id = Chat::create("test_chat", &mut conn).await.unwrap();
+            let vector_store = Arc::new(SqliteVectorStore::new(database.clone()));
+            let indexer = Indexer::new(
+                database,
+                MockLanguageModel::new().engine(),
+                vector_store.clone(),
+                LoaderRegistry::default(),
+                Chunker::new(8, 0),
+            );
+            indexer
+                .index_file(chat_id, &path, "chat", |_| {})
+                .await
+                .unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            let query = vec![1.0; MOCK_EMBEDDING_DIMENSIONS];
+            let stored = vector_store.query("chat", &query, 100).await.unwrap();
+            assert!(stored.len() > 1);
+            for stored in stored {
+                let source = stored.source.unwrap();
+                assert_eq!(&content[source.start..source.end], stored.document);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_detach_from_file_database() {
+        let (database, directory) = test_file_database().await;
+        let path = directory.join("notes.txt");
+        std::fs::write(&path, sentences(0..3)).unwrap();
+
+        let mut conn = database.acquire().await.unwrap();
+        let chat_id = Chat::create("test_chat", &mut conn).await.unwrap();
+        drop(conn);
+        let vector_store = Arc::new(SqliteVectorStore::new(database.clone()));
+        let indexer = Indexer::new(
+            database,
+            MockLanguageModel::new().engine(),
+            vector_store.clone(),
+            LoaderRegistry::default(),
+            Chunker::new(10, 0).with_strategy(ChunkingStrategy::Sentences),
+        );
+        indexer
+            .index_file(chat_id, &path, "chat", |_| {})
+            .await
+            .unwrap();
+
+        // The vector store deletes on a connection of its own while detaching
+        let removed = indexer.detach(chat_id, &path, "chat").await.unwrap();
+        assert_eq!(removed, Some(3));
+        let query = vec![1.0; MOCK_EMBEDDING_DIMENSIONS];
+        assert!(vector_store
+            .query("chat", &query, 10)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(indexer.attachments(chat_id).await.unwrap().is_empty());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_attach_image() {
+        let database = test_database().await;
+        let mut conn = database.acquire().await.unwrap();
+        let chat_id = Chat::create("test_chat", &mut conn).await.unwrap();
+        let indexer = Indexer::new(
+            database.clone(),
+            MockLanguageModel::new().engine(),
+            Arc::new(SqliteVectorStore::new(database)),
+            LoaderRegistry::default(),
+            Chunker::default(),
+        );
+        let image = Path::new("data/blossom.jpg");
+        indexer.attach_image(chat_id, image).await.unwrap();
+        indexer.attach_image(chat_id, image).await.unwrap();
+        assert_eq!(
+            indexer.images(chat_id).await.unwrap(),
+            vec![std::fs::canonicalize(image).unwrap()]
+        );
+        assert_eq!(indexer.attachments(chat_id).await.unwrap().len(), 1);
+
+        // Images are detached like any other file
+        assert_eq!(
+            indexer.detach(chat_id, image, "chat").await.unwrap(),
+            Some(0)
+        );
+        assert!(indexer.images(chat_id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_index_file_surfaces_errors() {
+        let database = test_database().await;
+        let indexer = Indexer::new(
+            database.clone(),
+            MockLanguageModel::new().engine(),
+            Arc::new(SqliteVectorStore::new(database)),
+            LoaderRegistry::default(),
+            Chunker::default(),
+        );
+        let result = indexer
+            .index_file(Uuid::nil(), Path::new("does/not/exist.txt"), "chat", |_| {})
+            .await;
+        assert!(matches!(
+            result,
+            Err(IngestError::Loader(LoaderError::Io(_)))
+        ));
+    }
+}
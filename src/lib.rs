@@ -4,8 +4,17 @@ mod database;
 use app::Version;
 
 pub mod agent;
+pub mod chunking;
+pub mod ingest;
+pub mod loaders;
+pub mod retrieval;
+pub mod transcript;
+pub mod vector_store;
 pub use app::{Config, State};
+pub use database::models::Attachment as AttachmentModel;
 pub use database::models::Chat as ChatModel;
+pub use database::models::ChatSettings as ChatSettingsModel;
+pub use database::models::{Message as MessageModel, MessageRole};
 
 /// Sets up system panics to use the tracing infrastructure to log reported issues. This doesn't
 /// prevent the panic from taking out the service but ensures that it and any available information
@@ -0,0 +1,52 @@
+use std::path::Path;
+
+use super::{Loader, LoaderError};
+
+/// How wide to lay out text extracted from HTML. Wide enough that
+/// paragraphs aren't broken up by wrapping.
+const TEXT_WIDTH: usize = 10_000;
+
+/// Extracts the readable text from HTML pages, dropping markup, scripts and styles
+pub struct HtmlLoader;
+
+impl Loader for HtmlLoader {
+    fn name(&self) -> &'static str {
+        "html"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["html", "htm", "xhtml"]
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        &["text/html", "application/xhtml+xml"]
+    }
+
+    fn load(&self, path: &Path) -> Result<String, LoaderError> {
+        let html = std::fs::read(path)?;
+        html_to_text(&html)
+    }
+}
+
+fn html_to_text(html: &[u8]) -> Result<String, LoaderError> {
+    html2text::config::plain()
+        .string_from_read(html, TEXT_WIDTH)
+        .map_err(LoaderError::Html)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_html_to_text() {
+        let html = br#"<html><head><title>Ignored</title><style>p { color: pink; }</style></head>
+            <body><h1>Blossoms</h1><p>They are <b>pink</b>.</p><script>alert(1)</script></body></html>"#;
+        let text = html_to_text(html).unwrap();
+        assert!(text.contains("Blossoms"));
+        assert!(text.contains("They are pink."));
+        assert!(!text.contains("<p>"));
+        assert!(!text.contains("color"));
+        assert!(!text.contains("alert"));
+    }
+}
@@ -0,0 +1,93 @@
+use std::path::{Path, PathBuf};
+
+mod html;
+mod pdf;
+mod registry;
+mod structured;
+mod text;
+
+pub use html::HtmlLoader;
+pub use pdf::PdfLoader;
+pub use registry::LoaderRegistry;
+pub use structured::{CsvLoader, JsonLoader};
+pub use text::TextLoader;
+
+/// Extracts clean text from files of a particular kind, ready to be chunked and embedded
+pub trait Loader: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// File extensions this loader handles, without the leading dot
+    fn extensions(&self) -> &'static [&'static str];
+
+    /// MIME types this loader handles. A type ending in `/*` matches any subtype.
+    fn mime_types(&self) -> &'static [&'static str];
+
+    fn load(&self, path: &Path) -> Result<String, LoaderError>;
+
+    /// Whether `load` returns the file as it is, so that offsets into the text
+    /// are also byte offsets into the file
+    fn verbatim(&self) -> bool {
+        false
+    }
+}
+
+/// Every file under `root`, skipping hidden files and anything matched by
+/// a `.gitignore`, `.ignore` or git exclude file along the way
+pub fn walk(root: &Path) -> Result<Vec<PathBuf>, LoaderError> {
+    let mut paths = Vec::new();
+    for entry in ignore::WalkBuilder::new(root).require_git(false).build() {
+        let entry = entry?;
+        if entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_file())
+        {
+            paths.push(entry.into_path());
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoaderError {
+    #[error("no loader can read {0}")]
+    Unsupported(PathBuf),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to walk directory: {0}")]
+    Walk(#[from] ignore::Error),
+    #[error("failed to read html: {0}")]
+    Html(#[from] html2text::Error),
+    #[error("failed to read pdf: {0}")]
+    Pdf(String),
+    #[error("failed to read json: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("failed to read csv: {0}")]
+    Csv(#[from] csv::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_walk_respects_gitignore() {
+        let root = std::env::temp_dir().join(format!("blossom-walk-{}", rand::random::<u64>()));
+        fs::create_dir_all(root.join("docs/target")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(root.join("README.md"), "# Hello").unwrap();
+        fs::write(root.join("debug.log"), "noise").unwrap();
+        fs::write(root.join("docs/guide.txt"), "guide").unwrap();
+        fs::write(root.join("docs/target/build.txt"), "built").unwrap();
+
+        let paths = walk(&root).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+        let paths = paths
+            .iter()
+            .map(|path| path.strip_prefix(&root).unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["README.md", "docs/guide.txt"]);
+    }
+}
@@ -0,0 +1,35 @@
+use std::path::Path;
+
+use super::{Loader, LoaderError};
+
+/// Extracts the text layer of PDF documents. Scanned pages without one come out empty.
+pub struct PdfLoader;
+
+impl Loader for PdfLoader {
+    fn name(&self) -> &'static str {
+        "pdf"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["pdf"]
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        &["application/pdf"]
+    }
+
+    fn load(&self, path: &Path) -> Result<String, LoaderError> {
+        // Malformed documents can make the extractor panic rather than fail
+        match std::panic::catch_unwind(|| pdf_extract::extract_text(path)) {
+            Ok(result) => result.map_err(|e| LoaderError::Pdf(e.to_string())),
+            Err(panic) => {
+                let message = panic
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+                    .or_else(|| panic.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "the extractor panicked".to_string());
+                Err(LoaderError::Pdf(message))
+            }
+        }
+    }
+}
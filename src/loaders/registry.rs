@@ -0,0 +1,150 @@
+use std::path::Path;
+
+use super::{CsvLoader, HtmlLoader, JsonLoader, Loader, LoaderError, PdfLoader, TextLoader};
+
+/// The set of loaders available for attaching files to a chat.
+/// Loaders are picked by file extension first, falling back to the MIME type
+/// guessed from the path so that less common extensions still find a home.
+pub struct LoaderRegistry {
+    loaders: Vec<Box<dyn Loader>>,
+}
+
+impl Default for LoaderRegistry {
+    /// A registry holding every built-in loader
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(TextLoader);
+        registry.register(HtmlLoader);
+        registry.register(PdfLoader);
+        registry.register(JsonLoader);
+        registry.register(CsvLoader);
+        registry
+    }
+}
+
+impl LoaderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry without any loaders
+    pub fn empty() -> Self {
+        Self {
+            loaders: Vec::new(),
+        }
+    }
+
+    /// Register a loader, replacing any loader already registered under the same name.
+    /// Loaders registered later take precedence for the extensions and types they share.
+    pub fn register(&mut self, loader: impl Loader + 'static) {
+        self.loaders.retain(|l| l.name() != loader.name());
+        self.loaders.insert(0, Box::new(loader));
+    }
+
+    /// The loader able to read the file at `path`, if any
+    pub fn for_path(&self, path: &Path) -> Option<&dyn Loader> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        if let Some(loader) = self
+            .loaders
+            .iter()
+            .find(|loader| loader.extensions().contains(&extension.as_str()))
+        {
+            return Some(loader.as_ref());
+        }
+
+        let mime = mime_guess::from_path(path).first()?;
+        let mime = mime.essence_str();
+        // Prefer an exact match over a wildcard
+        self.loaders
+            .iter()
+            .find(|loader| loader.mime_types().contains(&mime))
+            .or_else(|| {
+                self.loaders.iter().find(|loader| {
+                    loader
+                        .mime_types()
+                        .iter()
+                        .any(|pattern| matches_wildcard(pattern, mime))
+                })
+            })
+            .map(|loader| loader.as_ref())
+    }
+
+    /// Extract text from the file at `path`. Text extracted from other formats is
+    /// cleaned up, while plain text is kept as it is so chunks point into the file itself.
+    pub fn load(&self, path: &Path) -> Result<String, LoaderError> {
+        let loader = self
+            .for_path(path)
+            .ok_or_else(|| LoaderError::Unsupported(path.to_path_buf()))?;
+        let text = loader.load(path)?;
+        Ok(if loader.verbatim() {
+            text
+        } else {
+            clean(&text)
+        })
+    }
+}
+
+fn matches_wildcard(pattern: &str, mime: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(top_level) => mime.split('/').next() == Some(top_level),
+        None => false,
+    }
+}
+
+/// Strip trailing whitespace from every line and collapse runs of blank lines
+fn clean(text: &str) -> String {
+    let mut cleaned = String::with_capacity(text.len());
+    let mut blank_lines = 0;
+    for line in text.lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank_lines += 1;
+            if blank_lines > 1 {
+                continue;
+            }
+        } else {
+            blank_lines = 0;
+        }
+        cleaned.push_str(line);
+        cleaned.push('\n');
+    }
+    cleaned.trim().to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn loader_name(registry: &LoaderRegistry, path: &str) -> Option<&'static str> {
+        registry
+            .for_path(Path::new(path))
+            .map(|loader| loader.name())
+    }
+
+    #[test]
+    fn test_for_path() {
+        let registry = LoaderRegistry::default();
+        assert_eq!(loader_name(&registry, "notes.txt"), Some("text"));
+        assert_eq!(loader_name(&registry, "README.MD"), Some("text"));
+        assert_eq!(loader_name(&registry, "src/main.rs"), Some("text"));
+        // `.ts` guesses as a video, but we know better
+        assert_eq!(loader_name(&registry, "index.ts"), Some("text"));
+        assert_eq!(loader_name(&registry, "index.htm"), Some("html"));
+        assert_eq!(loader_name(&registry, "paper.pdf"), Some("pdf"));
+        assert_eq!(loader_name(&registry, "data.json"), Some("json"));
+        assert_eq!(loader_name(&registry, "data.csv"), Some("csv"));
+        // Found through the `text/*` wildcard
+        assert_eq!(loader_name(&registry, "calendar.ics"), Some("text"));
+        assert_eq!(loader_name(&registry, "photo.jpg"), None);
+        assert_eq!(loader_name(&registry, "Makefile"), None);
+    }
+
+    #[test]
+    fn test_clean() {
+        assert_eq!(clean("  a  \n\n\n\nb\t\n\n"), "a\n\nb");
+    }
+}
@@ -0,0 +1,135 @@
+use std::path::Path;
+
+use serde_json::Value;
+
+use super::{Loader, LoaderError};
+
+/// Flattens JSON into one `path.to.field: value` line per value,
+/// so each line makes sense on its own once chunked
+pub struct JsonLoader;
+
+impl Loader for JsonLoader {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["json"]
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        &["application/json"]
+    }
+
+    fn load(&self, path: &Path) -> Result<String, LoaderError> {
+        let value = serde_json::from_slice::<Value>(&std::fs::read(path)?)?;
+        let mut lines = Vec::new();
+        flatten_json(&value, String::new(), &mut lines);
+        Ok(lines.join("\n"))
+    }
+}
+
+fn flatten_json(value: &Value, prefix: String, lines: &mut Vec<String>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten_json(value, path, lines);
+            }
+        }
+        Value::Array(array) => {
+            for (index, value) in array.iter().enumerate() {
+                flatten_json(value, format!("{}[{}]", prefix, index), lines);
+            }
+        }
+        Value::String(string) => lines.push(format!("{}: {}", prefix, string)),
+        value => lines.push(format!("{}: {}", prefix, value)),
+    }
+}
+
+/// Renders each row of a CSV file as a line of `header: value` pairs
+pub struct CsvLoader;
+
+impl Loader for CsvLoader {
+    fn name(&self) -> &'static str {
+        "csv"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["csv", "tsv"]
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        &["text/csv", "text/tab-separated-values"]
+    }
+
+    fn load(&self, path: &Path) -> Result<String, LoaderError> {
+        let delimiter = match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("tsv") => b'\t',
+            _ => b',',
+        };
+        let reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .flexible(true)
+            .from_path(path)?;
+        csv_to_text(reader)
+    }
+}
+
+fn csv_to_text<R: std::io::Read>(mut reader: csv::Reader<R>) -> Result<String, LoaderError> {
+    let headers = reader.headers()?.clone();
+    let mut lines = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let fields = record
+            .iter()
+            .enumerate()
+            .filter(|(_, value)| !value.trim().is_empty())
+            .map(|(index, value)| match headers.get(index) {
+                Some(header) => format!("{}: {}", header, value),
+                None => value.to_string(),
+            })
+            .collect::<Vec<_>>();
+        lines.push(fields.join(", "));
+    }
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_flatten_json() {
+        let value = serde_json::json!({
+            "name": "blossom",
+            "petals": [{ "color": "pink" }, { "color": "white" }],
+            "count": 5,
+        });
+        let mut lines = Vec::new();
+        flatten_json(&value, String::new(), &mut lines);
+        assert_eq!(
+            lines,
+            vec![
+                "count: 5",
+                "name: blossom",
+                "petals[0].color: pink",
+                "petals[1].color: white"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_csv_to_text() {
+        let data = "name,color\nblossom,pink\nleaf,\n";
+        let reader = csv::Reader::from_reader(data.as_bytes());
+        assert_eq!(
+            csv_to_text(reader).unwrap(),
+            "name: blossom, color: pink\nname: leaf"
+        );
+    }
+}
@@ -0,0 +1,53 @@
+use std::path::Path;
+
+use super::{Loader, LoaderError};
+use crate::chunking::CODE_EXTENSIONS;
+
+/// Prose, configuration and anything else which isn't chunked as source code
+const OTHER_EXTENSIONS: &[&str] = &[
+    "txt", "md", "markdown", "rst", "org", "adoc", "tex", "log", "sql", "css", "scss", "toml",
+    "yaml", "yml", "ini", "cfg", "conf", "env",
+];
+
+/// Source code takes its extensions from the chunker, so the two can't drift apart
+const EXTENSIONS: [&str; OTHER_EXTENSIONS.len() + CODE_EXTENSIONS.len()] =
+    concat(OTHER_EXTENSIONS, CODE_EXTENSIONS);
+
+const fn concat<const N: usize>(a: &[&'static str], b: &[&'static str]) -> [&'static str; N] {
+    let mut joined = [""; N];
+    let mut i = 0;
+    while i < a.len() {
+        joined[i] = a[i];
+        i += 1;
+    }
+    while i < N {
+        joined[i] = b[i - a.len()];
+        i += 1;
+    }
+    joined
+}
+
+/// Reads files which are already plain text, such as notes, Markdown and source code
+pub struct TextLoader;
+
+impl Loader for TextLoader {
+    fn name(&self) -> &'static str {
+        "text"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &EXTENSIONS
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        &["text/*"]
+    }
+
+    fn load(&self, path: &Path) -> Result<String, LoaderError> {
+        Ok(std::fs::read_to_string(path)?)
+    }
+
+    fn verbatim(&self) -> bool {
+        true
+    }
+}
@@ -3,12 +3,16 @@ use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{EnvFilter, Layer};
 
-use blossom::agent::ChatCommand;
-use blossom::{ChatModel, Config, State};
+use blossom::agent::{
+    is_image, AttachedImages, ChatCommand, Conversation, ConversationMemory, ConverseTool, Event,
+    GenerationOptions, ImageTool, LlmEngine, Message, Outcome, Prompt, Retrieved,
+    SearchDocumentsTool, SharedConversation, Supervisor, Template, ToolRegistry, MAX_DEPTH,
+};
+use blossom::{ChatModel, ChatSettingsModel, Config, MessageModel, MessageRole, State};
 
 mod cli;
 
-use cli::{Cli, Command};
+use cli::{AttachmentsCommand, Cli, Command, ExportFormat, SettingsArgs, SortOrder};
 
 #[tokio::main]
 async fn main() {
@@ -50,8 +54,16 @@ pub enum AppError {
     Sqlx(#[from] sqlx::error::Error),
     #[error("engine error: {0}")]
     Engine(#[from] blossom::agent::LlmEngineError),
-    #[error("chroma error: {0}")]
-    Chroma(#[from] anyhow::Error),
+    #[error("vector store error: {0}")]
+    VectorStore(#[from] blossom::vector_store::VectorStoreError),
+    #[error("ingest error: {0}")]
+    Ingest(#[from] blossom::ingest::IngestError),
+    #[error("transcript error: {0}")]
+    Transcript(#[from] TranscriptError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("template error: {0}")]
+    Template(#[from] blossom::agent::TemplateError),
 }
 
 /* App scripting */
@@ -59,55 +71,498 @@ pub enum AppError {
 use std::io::{self, Write};
 use std::path::Path;
 
-use chromadb::v1::collection::CollectionEntries;
-use chromadb::v1::{ChromaClient, ChromaCollection};
 use names::Generator;
-use tokio::io::{stdout, AsyncWriteExt};
-use tokio_stream::StreamExt;
+use uuid::Uuid;
 
-use blossom::agent::LlmEngine;
+use blossom::ingest::{Indexed, Indexer, Progress};
+use blossom::loaders::{self, LoaderRegistry};
+use blossom::retrieval::Retriever;
+use blossom::transcript::{AttachmentExport, ChatExport, TranscriptError};
+use blossom::vector_store::Passage;
+use blossom::AttachmentModel;
+use indicatif::{ProgressBar, ProgressStyle};
 
 async fn handle_command(state: State, command: Command) -> Result<(), AppError> {
     match command {
-        Command::New { maybe_name } => {
+        Command::New {
+            maybe_name,
+            settings,
+        } => {
+            let settings = chat_settings(settings)?;
             let name = maybe_name.unwrap_or_else(|| Generator::default().next().unwrap());
             let mut conn = state.sqlite_database().begin().await?;
-            let id = ChatModel::create(&name, &mut conn).await?;
+            let id = match ChatModel::create(&name, &mut conn).await {
+                Err(e) if is_unique_violation(&e) => {
+                    pretty_warn(&format!("A chat named '{}' already exists", name));
+                    return Ok(());
+                }
+                result => result?,
+            };
+            settings.save(id, &mut conn).await?;
             conn.commit().await?;
             pretty_message(&format!("Created new chat named '{}' with ID {}", name, id));
         }
-        Command::Ls => {
+        Command::Ls {
+            contains,
+            since,
+            sort,
+        } => {
             let mut conn = state.sqlite_database().acquire().await?;
-            let chats = ChatModel::read_all(&mut conn).await?;
+            let mut chats = ChatModel::read_all(&mut conn)
+                .await?
+                .into_iter()
+                .filter(|chat| match &contains {
+                    Some(contains) => chat.name().contains(contains.as_str()),
+                    None => true,
+                })
+                .filter(|chat| match since {
+                    Some(since) => chat.created_at().date() >= since,
+                    None => true,
+                })
+                .collect::<Vec<_>>();
+            match sort {
+                SortOrder::Oldest => {}
+                SortOrder::Newest => chats.reverse(),
+                SortOrder::Name => chats.sort_by(|a, b| a.name().cmp(b.name())),
+            }
             for chat in chats {
-                pretty_message(&format!("ID: {} | Name: {}", chat.id(), chat.name()));
+                pretty_message(&format!(
+                    "ID: {} | Name: {} | Created: {}",
+                    chat.id(),
+                    chat.name(),
+                    chat.created_at().date()
+                ));
+            }
+        }
+        Command::Rename { name, new_name } => {
+            let mut conn = state.sqlite_database().begin().await?;
+            let Some(chat) = find_chat(&name, &mut conn).await? else {
+                return Ok(());
+            };
+            match ChatModel::rename(chat.id(), &new_name, &mut conn).await {
+                Err(e) if is_unique_violation(&e) => {
+                    pretty_warn(&format!("A chat named '{}' already exists", new_name));
+                    return Ok(());
+                }
+                result => result?,
+            }
+            conn.commit().await?;
+            // Collections may be named after the chat, so follow it to its new name.
+            // The SQLite store shares the database, so this can't happen inside the
+            // transaction; the chat gets its old name back instead if it fails.
+            let naming = state.collection_naming();
+            if let Err(e) = indexer_for(&state, state.llm_engine())
+                .rename_collection(
+                    &naming.name(chat.id(), &name),
+                    &naming.name(chat.id(), &new_name),
+                )
+                .await
+            {
+                let mut conn = state.sqlite_database().acquire().await?;
+                ChatModel::rename(chat.id(), &name, &mut conn).await?;
+                return Err(e.into());
+            }
+            pretty_message(&format!("Renamed chat '{}' to '{}'", name, new_name));
+        }
+        Command::Rm { name } => {
+            let mut conn = state.sqlite_database().begin().await?;
+            let Some(chat) = find_chat(&name, &mut conn).await? else {
+                return Ok(());
+            };
+            ChatModel::delete(chat.id(), &mut conn).await?;
+            conn.commit().await?;
+            let collection_name = state.collection_naming().name(chat.id(), chat.name());
+            if let Err(e) = indexer_for(&state, state.llm_engine())
+                .delete_collection(&collection_name)
+                .await
+            {
+                tracing::error!("Failed to delete collection {}: {}", collection_name, e);
+                pretty_warn(&format!(
+                    "Deleted chat '{}' but its collection '{}' was left behind: {}",
+                    name, collection_name, e
+                ));
+                return Ok(());
             }
+            pretty_message(&format!("Deleted chat '{}'", name));
+        }
+        Command::Show { name } => {
+            let mut conn = state.sqlite_database().acquire().await?;
+            let Some(chat) = find_chat(&name, &mut conn).await? else {
+                return Ok(());
+            };
+            pretty_message(&format!(
+                "Chat '{}' with ID {}, created {}",
+                chat.name(),
+                chat.id(),
+                chat.created_at().date()
+            ));
+            let settings = ChatSettingsModel::read(chat.id(), &mut conn).await?;
+            if settings != ChatSettingsModel::default() {
+                print_settings(
+                    &chat_engine(state.llm_engine(), &chat, &settings),
+                    &settings,
+                );
+            }
+            print_transcript(&MessageModel::read_branch(chat.id(), &mut conn).await?);
         }
         Command::Cont { name } => {
             let mut conn = state.sqlite_database().acquire().await?;
-            let maybe_chat = ChatModel::read_by_name(&name, &mut conn).await;
-            let chat = match maybe_chat {
-                Ok(chat) => chat,
-                Err(_) => {
-                    pretty_warn(&format!("Chat '{}' not found", name));
+            let Some(chat) = find_chat(&name, &mut conn).await? else {
+                return Ok(());
+            };
+            run(&chat, &state).await?;
+        }
+        Command::Export {
+            name,
+            format,
+            output,
+        } => {
+            let mut conn = state.sqlite_database().acquire().await?;
+            let Some(chat) = find_chat(&name, &mut conn).await? else {
+                return Ok(());
+            };
+            let export = ChatExport::read(state.sqlite_database(), chat.id()).await?;
+            let contents = match format {
+                ExportFormat::Md => export.to_markdown(),
+                ExportFormat::Json => export.to_json()?,
+                ExportFormat::Jsonl => export.to_jsonl()?,
+            };
+            match output {
+                Some(output) => {
+                    std::fs::write(&output, contents)?;
+                    pretty_message(&format!("Exported '{}' to {}", name, output.display()));
+                }
+                None => print!("{}", contents),
+            }
+        }
+        Command::Import {
+            path,
+            name,
+            reembed,
+        } => {
+            let export = ChatExport::parse(&std::fs::read_to_string(&path)?)?;
+            let name = name.unwrap_or_else(|| export.name.clone());
+            let chat_id = match export.create(&name, state.sqlite_database()).await {
+                Err(TranscriptError::Sqlx(e)) if is_unique_violation(&e) => {
+                    pretty_warn(&format!(
+                        "A chat named '{}' already exists, pick another with --name",
+                        name
+                    ));
                     return Ok(());
                 }
+                result => result?,
             };
-            run(&chat, &state).await?;
+            pretty_message(&format!(
+                "Imported '{}' with {} messages",
+                name,
+                export.messages.len()
+            ));
+            if export.attachments.is_empty() {
+                return Ok(());
+            }
+            if !reembed {
+                pretty_message(&format!(
+                    "Pass --reembed to embed the {} attached files again",
+                    export.attachments.len()
+                ));
+                return Ok(());
+            }
+            embed_attachments(
+                &state,
+                state.llm_engine(),
+                chat_id,
+                &name,
+                &export.attachments,
+            )
+            .await;
+        }
+        Command::Attachments { name, command } => {
+            let mut conn = state.sqlite_database().acquire().await?;
+            let Some(chat) = find_chat(&name, &mut conn).await? else {
+                return Ok(());
+            };
+            let collection_name = state.collection_naming().name(chat.id(), chat.name());
+            let settings = ChatSettingsModel::read(chat.id(), &mut conn).await?;
+            let indexer = indexer_for(&state, &chat_engine(state.llm_engine(), &chat, &settings));
+            match command.unwrap_or(AttachmentsCommand::Ls) {
+                AttachmentsCommand::Ls => list_attachments(&indexer, chat.id()).await?,
+                AttachmentsCommand::Detach { path } => {
+                    detach(&indexer, chat.id(), &path, &collection_name).await?
+                }
+                AttachmentsCommand::Inspect { path } => {
+                    inspect(&indexer, chat.id(), &path, &collection_name).await?
+                }
+            }
         }
     }
     Ok(())
 }
 
+/// Look up a chat by name, warning the user if there isn't one
+async fn find_chat(
+    name: &str,
+    conn: &mut sqlx::SqliteConnection,
+) -> Result<Option<ChatModel>, AppError> {
+    match ChatModel::read_by_name(name, conn).await {
+        Ok(chat) => Ok(Some(chat)),
+        Err(sqlx::Error::RowNotFound) => {
+            pretty_warn(&format!("Chat '{}' not found", name));
+            Ok(None)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn is_unique_violation(error: &sqlx::Error) -> bool {
+    matches!(error, sqlx::Error::Database(e) if e.is_unique_violation())
+}
+
+fn print_transcript(messages: &[MessageModel]) {
+    for message in messages {
+        match (message.role(), message.tool_name()) {
+            (MessageRole::User, _) => println!(">>> {}", message.content()),
+            (MessageRole::Tool, Some(tool_name)) => {
+                println!("[{}] {}", tool_name, message.content())
+            }
+            _ => println!("{}", message.content()),
+        }
+    }
+}
+
+fn indexer_for(state: &State, engine: &LlmEngine) -> Indexer {
+    Indexer::new(
+        state.sqlite_database().clone(),
+        engine.clone(),
+        state.vector_store().clone(),
+        LoaderRegistry::default(),
+        *state.chunker(),
+    )
+}
+
+/// The settings given to `blossom new`, reading the system prompt from its file
+fn chat_settings(args: SettingsArgs) -> Result<ChatSettingsModel, AppError> {
+    let system_prompt = match &args.system_file {
+        Some(path) => Some(std::fs::read_to_string(path)?),
+        None => None,
+    };
+    if let Some(system_prompt) = &system_prompt {
+        Prompt::Conversational.parse(system_prompt)?;
+    }
+    Ok(ChatSettingsModel {
+        system_prompt,
+        supervisor_model: args.supervisor_model,
+        conversational_model: args.model,
+        image_model: args.image_model,
+        embedding_model: args.embedding_model,
+        temperature: args.temperature.map(f64::from),
+        top_p: args.top_p.map(f64::from),
+        num_ctx: args.num_ctx.map(i64::from),
+        seed: args.seed.map(i64::from),
+    })
+}
+
+/// The configured engine with the overrides of a chat applied
+fn chat_engine(base: &LlmEngine, chat: &ChatModel, settings: &ChatSettingsModel) -> LlmEngine {
+    let mut engine = base.clone().with_chat_name(chat.name().to_string());
+    if let Some(model) = &settings.supervisor_model {
+        engine = engine.with_supervisor_model(model.clone());
+    }
+    if let Some(model) = &settings.conversational_model {
+        engine = engine.with_conversational_model(model.clone());
+    }
+    if let Some(model) = &settings.image_model {
+        engine = engine.with_image_model(model.clone());
+    }
+    if let Some(model) = &settings.embedding_model {
+        engine = engine.with_embedding_model(model.clone());
+    }
+    if let Some(system_prompt) = &settings.system_prompt {
+        // Prompts saved before they were templates may not parse, so take those as they are
+        let template = Prompt::Conversational
+            .parse(system_prompt)
+            .unwrap_or_else(|_| Template::literal(system_prompt));
+        engine = engine.with_system_prompt(template);
+    }
+    engine.with_generation_options(GenerationOptions {
+        temperature: settings.temperature.map(|temperature| temperature as f32),
+        top_p: settings.top_p.map(|top_p| top_p as f32),
+        num_ctx: settings
+            .num_ctx
+            .and_then(|num_ctx| u32::try_from(num_ctx).ok()),
+        seed: settings.seed.and_then(|seed| i32::try_from(seed).ok()),
+    })
+}
+
+/// Apply `/set SETTING VALUE` to the settings of a chat.
+/// An empty value or `default` goes back to the configured setting.
+fn change_setting(
+    settings: &mut ChatSettingsModel,
+    setting: &str,
+    value: &str,
+) -> Result<(), String> {
+    let value = Some(value).filter(|value| !value.is_empty() && *value != "default");
+    fn parse<T: std::str::FromStr>(value: Option<&str>) -> Result<Option<T>, String> {
+        value
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| format!("Not a number: {}", value))
+            })
+            .transpose()
+    }
+    fn system_prompt(text: String) -> Result<String, String> {
+        match Prompt::Conversational.parse(&text) {
+            Ok(_) => Ok(text),
+            Err(e) => Err(format!("Invalid system prompt: {}", e)),
+        }
+    }
+    match setting {
+        "system" => {
+            settings.system_prompt = value
+                .map(|text| system_prompt(text.to_string()))
+                .transpose()?
+        }
+        "system-file" => {
+            settings.system_prompt = value
+                .map(|path| {
+                    std::fs::read_to_string(path)
+                        .map_err(|e| format!("Failed to read {}: {}", path, e))
+                        .and_then(system_prompt)
+                })
+                .transpose()?
+        }
+        "model" => settings.conversational_model = value.map(str::to_string),
+        "supervisor-model" => settings.supervisor_model = value.map(str::to_string),
+        "image-model" => settings.image_model = value.map(str::to_string),
+        "embedding-model" => settings.embedding_model = value.map(str::to_string),
+        "temperature" => settings.temperature = parse::<f32>(value)?.map(f64::from),
+        "top-p" | "top_p" => settings.top_p = parse::<f32>(value)?.map(f64::from),
+        "num-ctx" | "num_ctx" => settings.num_ctx = parse::<u32>(value)?.map(i64::from),
+        "seed" => settings.seed = parse::<i32>(value)?.map(i64::from),
+        _ => {
+            return Err(format!(
+                "Unknown setting '{}', try one of: system, system-file, model, supervisor-model, \
+                 image-model, embedding-model, temperature, top-p, num-ctx, seed",
+                setting
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Show the models and options a chat runs with, marking what it overrides
+fn print_settings(engine: &LlmEngine, settings: &ChatSettingsModel) {
+    let source = |overridden: bool| if overridden { "chat" } else { "default" };
+    let option = |value: Option<String>| value.unwrap_or_else(|| "model default".to_string());
+    let options = engine.generation_options();
+    let system_prompt = match &settings.system_prompt {
+        Some(system_prompt) => preview(system_prompt),
+        None => "built in".to_string(),
+    };
+    for (name, value, overridden) in [
+        ("system", system_prompt, settings.system_prompt.is_some()),
+        (
+            "model",
+            engine.conversational_model().to_string(),
+            settings.conversational_model.is_some(),
+        ),
+        (
+            "supervisor-model",
+            engine.supervisor_model().to_string(),
+            settings.supervisor_model.is_some(),
+        ),
+        (
+            "image-model",
+            engine.image_model().to_string(),
+            settings.image_model.is_some(),
+        ),
+        (
+            "embedding-model",
+            engine.embedding_model().to_string(),
+            settings.embedding_model.is_some(),
+        ),
+        (
+            "temperature",
+            option(options.temperature.map(|value| value.to_string())),
+            options.temperature.is_some(),
+        ),
+        (
+            "top-p",
+            option(options.top_p.map(|value| value.to_string())),
+            options.top_p.is_some(),
+        ),
+        (
+            "num-ctx",
+            engine.token_budget().context_length().to_string(),
+            options.num_ctx.is_some(),
+        ),
+        (
+            "seed",
+            option(options.seed.map(|value| value.to_string())),
+            options.seed.is_some(),
+        ),
+    ] {
+        println!("{:<16} {} ({})", name, value, source(overridden));
+    }
+}
+
+/// Attachments embedded with another model can't be compared against queries embedded
+/// with this one, so point out any that need attaching again
+async fn warn_stale_embeddings(
+    indexer: &Indexer,
+    chat_id: Uuid,
+    embedding_model: &str,
+) -> Result<(), AppError> {
+    let stale = indexer
+        .attachments(chat_id)
+        .await?
+        .into_iter()
+        .filter(|attachment| {
+            attachment
+                .embedding_model()
+                .is_some_and(|model| model != embedding_model)
+        })
+        .collect::<Vec<_>>();
+    if !stale.is_empty() {
+        pretty_warn(&format!(
+            "{} attached files were embedded with another model, detach them and attach them again to embed them with {}",
+            stale.len(),
+            embedding_model
+        ));
+    }
+    Ok(())
+}
+
 async fn run(chat: &ChatModel, state: &State) -> Result<(), AppError> {
-    // let _chat_id = chat.id();
+    let chat_id = chat.id();
     let chat_name = chat.name();
-    let engine = state.llm_engine();
-    let chroma_database = state.chroma_database();
-    // let _sqlite_database = state.sqlite_database();
-    let mut context = None;
+    let sqlite_database = state.sqlite_database();
+    let collection_name = state.collection_naming().name(chat_id, chat_name);
+    let mut conn = sqlite_database.acquire().await?;
+    let mut settings = ChatSettingsModel::read(chat_id, &mut conn).await?;
+    let mut engine = chat_engine(state.llm_engine(), chat, &settings);
+    let mut indexer = indexer_for(state, &engine);
     pretty_message(&format!("Running chat '{}'", chat_name));
 
+    let retrieved = Retrieved::default();
+    let images = AttachedImages::default();
+    images.set(indexer.images(chat_id).await?);
+    // The passages cited by the last answer, for `/source`
+    let mut sources = Vec::new();
+
+    // Replay the history of the chat so the user can pick up where they left off
+    let history = MessageModel::read_branch(chat_id, &mut conn).await?;
+    print_transcript(&history);
+    let conversation = SharedConversation::default();
+    let mut tools = tools_for(
+        state,
+        &engine,
+        &collection_name,
+        &retrieved,
+        &images,
+        &conversation,
+    );
+
     loop {
         print!(">>> ");
         io::stdout().flush().unwrap();
@@ -119,54 +574,197 @@ async fn run(chat: &ChatModel, state: &State) -> Result<(), AppError> {
 
         match chat_command {
             ChatCommand::Attach { paths } => {
-                let collection = get_collection(chat_name, chroma_database)?;
+                let (image_paths, paths): (Vec<_>, Vec<_>) =
+                    paths.into_iter().partition(|path| is_image(path));
+                for path in image_paths {
+                    if !path.is_file() {
+                        pretty_warn(&format!("Image not found: {}", path.display()));
+                        continue;
+                    }
+                    indexer.attach_image(chat_id, &path).await?;
+                    pretty_message(&format!("Attached the image: {}", path.display()));
+                }
+                images.set(indexer.images(chat_id).await?);
                 for path in paths {
-                    embed_path(&path, &collection, engine).await;
+                    let files = if path.is_dir() {
+                        match loaders::walk(&path) {
+                            Ok(files) => files,
+                            Err(e) => {
+                                pretty_warn(&format!("Failed to read the directory: {}", e));
+                                continue;
+                            }
+                        }
+                    } else {
+                        vec![path.clone()]
+                    };
+                    // Quietly skip what we can't read when attaching a whole directory
+                    let (files, unsupported): (Vec<_>, Vec<_>) = files
+                        .into_iter()
+                        .partition(|file| indexer.loaders().for_path(file).is_some());
+                    if path.is_dir() && !unsupported.is_empty() {
+                        pretty_message(&format!(
+                            "Skipped {} files in {} without a loader",
+                            unsupported.len(),
+                            path.display()
+                        ));
+                    } else if let Some(file) = unsupported.first() {
+                        pretty_warn(&format!("Don't know how to read: {}", file.display()));
+                    }
+                    for file in files {
+                        index_file(&indexer, chat_id, &file, &collection_name).await;
+                    }
                 }
             }
+            ChatCommand::Attachments => list_attachments(&indexer, chat_id).await?,
+            ChatCommand::Detach { path } => {
+                detach(&indexer, chat_id, &path, &collection_name).await?;
+                images.set(indexer.images(chat_id).await?);
+            }
+            ChatCommand::Inspect { path } => {
+                inspect(&indexer, chat_id, &path, &collection_name).await?
+            }
             ChatCommand::Chat { message } => {
-                pretty_message("Thinking about your message...");
-                let maybe_tool_call = engine.handle(&message).await;
-                let tool_call = match maybe_tool_call {
-                    Ok(tool_call) => tool_call,
-                    Err(e) => {
-                        pretty_warn(&format!("Failed to handle message: {}", e));
+                MessageModel::create(chat_id, MessageRole::User, &message, None, None, &mut conn)
+                    .await?;
+                prepare_conversation(&engine, &conversation, chat_id, true, &mut conn).await?;
+                sources = respond(
+                    &engine, &tools, &retrieved, &images, chat_id, &message, &mut conn,
+                )
+                .await?;
+            }
+            ChatCommand::Retry { turn } => {
+                let Some(turn) = user_turn(chat_id, turn, &mut conn).await? else {
+                    continue;
+                };
+                // Answer the turn again, leaving the old answer on its own branch
+                ChatModel::set_head(chat_id, Some(turn.id()), &mut conn).await?;
+                prepare_conversation(&engine, &conversation, chat_id, false, &mut conn).await?;
+                sources = respond(
+                    &engine,
+                    &tools,
+                    &retrieved,
+                    &images,
+                    chat_id,
+                    turn.content(),
+                    &mut conn,
+                )
+                .await?;
+            }
+            ChatCommand::Edit { turn, message } => {
+                if message.is_empty() {
+                    pretty_warn("Usage: /edit [N] MESSAGE");
+                    continue;
+                }
+                let Some(turn) = user_turn(chat_id, turn, &mut conn).await? else {
+                    continue;
+                };
+                ChatModel::set_head(chat_id, turn.parent_id(), &mut conn).await?;
+                MessageModel::create(chat_id, MessageRole::User, &message, None, None, &mut conn)
+                    .await?;
+                prepare_conversation(&engine, &conversation, chat_id, false, &mut conn).await?;
+                sources = respond(
+                    &engine, &tools, &retrieved, &images, chat_id, &message, &mut conn,
+                )
+                .await?;
+            }
+            ChatCommand::Fork { name } => {
+                let name = name.unwrap_or_else(|| format!("{}-fork", chat_name));
+                let export = ChatExport::read(sqlite_database, chat_id).await?;
+                let fork_id = match export.create(&name, sqlite_database).await {
+                    Err(TranscriptError::Sqlx(e)) if is_unique_violation(&e) => {
+                        pretty_warn(&format!("A chat named '{}' already exists", name));
                         continue;
                     }
+                    result => result?,
                 };
-                match tool_call.name() {
-                    "converse" => {
-                        pretty_message("Crafting a response...");
-                        // Validate the tool call
-                        let args = tool_call.args();
-                        if args.len() != 1 {
-                            pretty_warn(&format!(
-                                "Accidentally called `converse` with more than one argument: {:?}",
-                                tool_call
-                            ));
-                            continue;
-                        }
-                        let input = args.first().unwrap();
-                        assert_eq!(input.name(), "input");
-                        assert_eq!(input.r#type(), "String");
-
-                        // Complete on the response to std out
-                        let input = input.value();
-                        let mut stdout = stdout();
-                        let mut stream = engine.converse(input, context.clone()).await?;
-                        while let Some(Ok(res)) = stream.next().await {
-                            for ele in res {
-                                stdout.write_all(ele.response.as_bytes()).await.unwrap();
-                                stdout.flush().await.unwrap();
-
-                                if let Some(final_data) = ele.final_data {
-                                    context = Some(final_data.context);
-                                }
-                            }
+                settings.save(fork_id, &mut conn).await?;
+                // The fork gets its own collection, so embed its attachments again
+                embed_attachments(state, &engine, fork_id, &name, &export.attachments).await;
+                pretty_message(&format!(
+                    "Forked into '{}', continue it with `blossom cont -n {}`",
+                    name, name
+                ));
+            }
+            ChatCommand::Branches => {
+                let leaves = MessageModel::read_leaves(chat_id, &mut conn).await?;
+                let head = MessageModel::read_branch(chat_id, &mut conn)
+                    .await?
+                    .last()
+                    .map(|message| message.id());
+                for (i, leaf) in leaves.iter().enumerate() {
+                    let marker = if Some(leaf.id()) == head { "*" } else { " " };
+                    println!("{} [{}] {}", marker, i + 1, preview(leaf.content()));
+                }
+            }
+            ChatCommand::Switch { branch } => {
+                let leaves = MessageModel::read_leaves(chat_id, &mut conn).await?;
+                let Some(leaf) = branch
+                    .and_then(|branch| branch.checked_sub(1))
+                    .and_then(|i| leaves.get(i))
+                else {
+                    pretty_warn(&format!(
+                        "Usage: /switch N, where N is between 1 and {}",
+                        leaves.len()
+                    ));
+                    continue;
+                };
+                ChatModel::set_head(chat_id, Some(leaf.id()), &mut conn).await?;
+                // Nothing carries over from the branch being left
+                conversation.set(Conversation::default());
+                sources.clear();
+                print_transcript(&MessageModel::read_branch(chat_id, &mut conn).await?);
+            }
+            ChatCommand::Set { setting, value } => {
+                let Some(setting) = setting else {
+                    print_settings(&engine, &settings);
+                    continue;
+                };
+                let mut changed = settings.clone();
+                if let Err(e) = change_setting(&mut changed, &setting, &value) {
+                    pretty_warn(&e);
+                    continue;
+                }
+                changed.save(chat_id, &mut conn).await?;
+                settings = changed;
+                engine = chat_engine(state.llm_engine(), chat, &settings);
+                indexer = indexer_for(state, &engine);
+                tools = tools_for(
+                    state,
+                    &engine,
+                    &collection_name,
+                    &retrieved,
+                    &images,
+                    &conversation,
+                );
+                if value.is_empty() || value == "default" {
+                    pretty_message(&format!("Set {} back to its default", setting));
+                } else {
+                    pretty_message(&format!("Set {} for this chat", setting));
+                }
+                warn_stale_embeddings(&indexer, chat_id, engine.embedding_model()).await?;
+            }
+            ChatCommand::Context => {
+                let (loaded, _) = load_conversation(chat_id, &mut conn).await?;
+                print_context_usage(&engine, &loaded);
+            }
+            ChatCommand::Source { index } => {
+                match index
+                    .and_then(|index| index.checked_sub(1))
+                    .and_then(|i| sources.get(i))
+                {
+                    Some(passage) => {
+                        if let Some(source) = &passage.source {
+                            pretty_message(&source.to_string());
                         }
-                        println!();
+                        println!("{}", passage.text);
+                    }
+                    None if sources.is_empty() => {
+                        pretty_warn("The last answer didn't cite any sources")
                     }
-                    _ => pretty_warn(&format!("Unknown tool call: {:?}", tool_call)),
+                    None => pretty_warn(&format!(
+                        "Usage: /source N, where N is between 1 and {}",
+                        sources.len()
+                    )),
                 }
             }
             ChatCommand::Exit => {
@@ -178,71 +776,393 @@ async fn run(chat: &ChatModel, state: &State) -> Result<(), AppError> {
     Ok(())
 }
 
-fn get_collection(
+fn tools_for(
+    state: &State,
+    engine: &LlmEngine,
+    collection_name: &str,
+    retrieved: &Retrieved,
+    images: &AttachedImages,
+    conversation: &SharedConversation,
+) -> ToolRegistry {
+    let retriever = Retriever::new(
+        state.sqlite_database().clone(),
+        engine.clone(),
+        state.vector_store().clone(),
+    );
+    let mut tools = ToolRegistry::new();
+    tools.register(SearchDocumentsTool::new(
+        retriever,
+        collection_name.to_string(),
+        retrieved.clone(),
+    ));
+    tools.register(ImageTool::new(engine.clone(), images.clone()));
+    tools.register(
+        ConverseTool::new(engine.clone(), retrieved.clone())
+            .with_conversation(conversation.clone()),
+    );
+    tools
+}
+
+/// The user messages and answers on the branch being followed, as the earlier turns
+/// of a conversation. Picks up after the chat's summary if it covers this branch.
+/// Returns the IDs of the messages alongside.
+async fn load_conversation(
+    chat_id: Uuid,
+    conn: &mut sqlx::SqliteConnection,
+) -> Result<(Conversation, Vec<Uuid>), AppError> {
+    let chat = ChatModel::read(chat_id, &mut *conn).await?;
+    let branch = MessageModel::read_branch(chat_id, &mut *conn).await?;
+    let summarized = chat
+        .summary_through()
+        .and_then(|through| branch.iter().position(|message| message.id() == through));
+    let (summary, rest) = match summarized {
+        Some(i) => (chat.summary(), &branch[i + 1..]),
+        None => (None, &branch[..]),
+    };
+    let mut turns = rest
+        .iter()
+        .filter(|message| matches!(message.role(), MessageRole::User | MessageRole::Assistant))
+        .collect::<Vec<_>>();
+    // A user message without an answer yet is about to be answered, which adds it again
+    while turns.last().map(|message| message.role()) == Some(MessageRole::User) {
+        turns.pop();
+    }
+    let messages = turns
+        .iter()
+        .map(|message| match message.role() {
+            MessageRole::User => Message::user(message.content()),
+            _ => Message::assistant(message.content()),
+        })
+        .collect();
+    let ids = turns.iter().map(|message| message.id()).collect();
+    let conversation = Conversation::new(messages).with_summary(summary.map(str::to_string));
+    Ok((conversation, ids))
+}
+
+/// Bring the conversation up to date with the branch being followed before answering,
+/// summarizing older turns that no longer fit in the conversational model's context
+async fn prepare_conversation(
+    engine: &LlmEngine,
+    shared: &SharedConversation,
+    chat_id: Uuid,
+    keep_context: bool,
+    conn: &mut sqlx::SqliteConnection,
+) -> Result<(), AppError> {
+    let (mut conversation, ids) = load_conversation(chat_id, &mut *conn).await?;
+    if keep_context {
+        conversation = conversation.with_context(shared.get().context().cloned());
+    }
+    match engine.fit(&mut conversation).await {
+        Ok(0) => {}
+        Ok(folded) => {
+            let summary = conversation.summary().unwrap_or_default();
+            ChatModel::set_summary(chat_id, summary, ids[folded - 1], &mut *conn).await?;
+            pretty_message(&format!(
+                "Summarized {} older messages to make room",
+                folded
+            ));
+        }
+        Err(e) => pretty_warn(&format!("Failed to summarize older messages: {}", e)),
+    }
+    shared.set(conversation);
+    Ok(())
+}
+
+fn print_context_usage(engine: &LlmEngine, conversation: &Conversation) {
+    let usage = engine.context_usage(conversation);
+    pretty_message(&format!(
+        "Using {} of {} tokens, {} free",
+        usage.used(),
+        usage.context_length,
+        usage.free()
+    ));
+    println!("  System prompt: {} tokens", usage.system);
+    if usage.summary > 0 {
+        println!("    of which summary: {} tokens", usage.summary);
+    }
+    println!(
+        "  History: {} tokens in {} messages",
+        usage.history, usage.messages
+    );
+    println!("  Documents: {} tokens set aside", usage.documents);
+    println!("  Response: {} tokens set aside", usage.response);
+    if engine.conversation_memory() == ConversationMemory::Context {
+        println!("  The server keeps track of the history using its generation context");
+    }
+}
+
+/// Run the supervisor on a user message already saved to the chat, saving what
+/// came of it and returning the passages cited by the answer
+async fn respond(
+    engine: &LlmEngine,
+    tools: &ToolRegistry,
+    retrieved: &Retrieved,
+    images: &AttachedImages,
+    chat_id: Uuid,
+    message: &str,
+    conn: &mut sqlx::SqliteConnection,
+) -> Result<Vec<Passage>, AppError> {
+    pretty_message("Thinking about your message...");
+    // Drop anything retrieved during a turn that never reached `converse`
+    retrieved.clear();
+    let mut supervisor = Supervisor::new(message, &images.paths());
+    let result = supervisor
+        .run(engine, tools, |event| match event {
+            Event::Calling { depth, tool_call } => pretty_message(&format!(
+                "[{}/{}] Calling `{}`",
+                depth,
+                MAX_DEPTH,
+                tool_call.name()
+            )),
+            Event::Repairing { attempt, error } => pretty_warn(&format!(
+                "Couldn't read the tool call ({}), asking again [{}/{}]",
+                error,
+                attempt,
+                engine.tool_call_repairs()
+            )),
+            Event::Finished(Outcome::Failed { error, .. }) => pretty_warn(error),
+            Event::Finished(Outcome::Answer {
+                tool_call: None,
+                answer,
+            }) => println!("{}", answer),
+            Event::Finished(_) => {}
+        })
+        .await;
+    for outcome in supervisor.outcomes() {
+        let (role, content, tool_call) = match outcome {
+            Outcome::Response {
+                tool_call,
+                response,
+            } => (MessageRole::Tool, response, Some(tool_call)),
+            Outcome::Answer { tool_call, answer } => {
+                (MessageRole::Assistant, answer, tool_call.as_ref())
+            }
+            Outcome::Failed { .. } => continue,
+        };
+        let tool_name = tool_call.map(|tool_call| tool_call.name());
+        let tool_arguments = tool_call.map(|tool_call| tool_call.arguments_json());
+        MessageModel::create(
+            chat_id,
+            role,
+            content,
+            tool_name,
+            tool_arguments.as_deref(),
+            &mut *conn,
+        )
+        .await?;
+    }
+    if let Err(e) = result {
+        pretty_warn(&format!("Failed to handle message: {}", e));
+    }
+    let sources = retrieved.cited();
+    print_sources(&sources);
+    Ok(sources)
+}
+
+/// Look up a user turn on the current branch, numbered from 1 and defaulting to the last
+async fn user_turn(
+    chat_id: Uuid,
+    turn: Option<usize>,
+    conn: &mut sqlx::SqliteConnection,
+) -> Result<Option<MessageModel>, AppError> {
+    let mut turns = MessageModel::read_branch(chat_id, conn)
+        .await?
+        .into_iter()
+        .filter(|message| message.role() == MessageRole::User)
+        .collect::<Vec<_>>();
+    let count = turns.len();
+    let found = match turn {
+        Some(turn) if turn >= 1 && turn <= count => Some(turns.swap_remove(turn - 1)),
+        Some(_) => None,
+        None => turns.pop(),
+    };
+    match (found, count) {
+        (Some(found), _) => Ok(Some(found)),
+        (None, 0) => {
+            pretty_warn("There are no messages to go back to yet");
+            Ok(None)
+        }
+        (None, count) => {
+            pretty_warn(&format!("Pick a turn between 1 and {}", count));
+            Ok(None)
+        }
+    }
+}
+
+/// Embed the files attached to an exported chat into a chat created from it
+async fn embed_attachments(
+    state: &State,
+    engine: &LlmEngine,
+    chat_id: Uuid,
     name: &str,
-    chroma_database: &ChromaClient,
-) -> Result<ChromaCollection, AppError> {
-    let collection = chroma_database.create_collection(name, None, true)?;
-    Ok(collection)
+    attachments: &[AttachmentExport],
+) {
+    let collection_name = state.collection_naming().name(chat_id, name);
+    let indexer = indexer_for(state, engine);
+    for attachment in attachments {
+        let path = Path::new(&attachment.path);
+        if !path.is_file() {
+            pretty_warn(&format!("Attached file not found: {}", path.display()));
+            continue;
+        }
+        if is_image(path) {
+            if let Err(e) = indexer.attach_image(chat_id, path).await {
+                pretty_warn(&format!("Failed to attach {}: {}", path.display(), e));
+            }
+            continue;
+        }
+        index_file(&indexer, chat_id, path, &collection_name).await;
+    }
 }
 
-async fn embed_path(path: &Path, collection: &ChromaCollection, engine: &LlmEngine) {
-    // If the path is a directory, panic
-    if path.is_dir() {
-        pretty_warn(&format!(
-            "This is a directory, not a file: {}",
-            path.display()
+async fn list_attachments(indexer: &Indexer, chat_id: Uuid) -> Result<(), AppError> {
+    let attachments = indexer.attachments(chat_id).await?;
+    if attachments.is_empty() {
+        pretty_message("Nothing is attached to this chat");
+    }
+    for attachment in attachments.iter() {
+        // Images aren't indexed, only read by the image tool
+        if is_image(Path::new(attachment.path())) {
+            pretty_message(&format!("{} | image", attachment.path()));
+            continue;
+        }
+        pretty_message(&format!(
+            "{} | {} | {} chunks | {}",
+            attachment.path(),
+            format_size(attachment.size()),
+            attachment.chunk_count(),
+            indexed_at(attachment)
         ));
-        return;
     }
+    Ok(())
+}
 
-    // Check if the extension is not a text file
-    if path.extension().unwrap_or_default() != "txt" {
-        pretty_warn(&format!("This is not a text file: {}", path.display()));
-        return;
+async fn detach(
+    indexer: &Indexer,
+    chat_id: Uuid,
+    path: &Path,
+    collection: &str,
+) -> Result<(), AppError> {
+    match indexer.detach(chat_id, path, collection).await? {
+        Some(removed) => pretty_message(&format!(
+            "Detached {} and removed {} chunks",
+            path.display(),
+            removed
+        )),
+        None => pretty_warn(&format!("Not attached: {}", path.display())),
     }
+    Ok(())
+}
+
+async fn inspect(
+    indexer: &Indexer,
+    chat_id: Uuid,
+    path: &Path,
+    collection: &str,
+) -> Result<(), AppError> {
+    let Some(attachment) = indexer.attachment(chat_id, path).await? else {
+        pretty_warn(&format!("Not attached: {}", path.display()));
+        return Ok(());
+    };
+    pretty_message(attachment.path());
+    println!("  Size: {}", format_size(attachment.size()));
+    println!("  Chunks: {}", attachment.chunk_count());
+    println!(
+        "  Embedding model: {}",
+        attachment.embedding_model().unwrap_or("none")
+    );
+    println!("  Indexed: {}", indexed_at(&attachment));
+    println!("  Content hash: {}", attachment.content_hash());
+    for (i, passage) in indexer
+        .chunks(&attachment, collection)
+        .await?
+        .iter()
+        .enumerate()
+    {
+        let range = match &passage.source {
+            Some(source) => format!("bytes {}-{}", source.start, source.end),
+            None => passage.id.clone(),
+        };
+        println!("  [{}] {}: {}", i + 1, range, preview(&passage.text));
+    }
+    Ok(())
+}
 
-    // TODO: this is janky bad chunking
-    // Read the data and chunk the file among paragraphs
-    let maybe_data = std::fs::read_to_string(path);
-    if let Err(e) = maybe_data {
-        pretty_warn(&format!("Failed to read the file: {}", e));
+fn indexed_at(attachment: &AttachmentModel) -> String {
+    match attachment.indexed_at() {
+        Some(at) => format!("indexed {} {:02}:{:02}", at.date(), at.hour(), at.minute()),
+        None => "not indexed".to_string(),
+    }
+}
+
+fn format_size(bytes: i64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// The start of a chunk on a single line
+fn preview(text: &str) -> String {
+    const PREVIEW_CHARS: usize = 60;
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(PREVIEW_CHARS) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text,
+    }
+}
+
+fn print_sources(sources: &[Passage]) {
+    if sources.is_empty() {
         return;
     }
-    let data = maybe_data.unwrap();
-    let paragraphs = data.split("\n\n").collect::<Vec<&str>>();
-
-    // Embed the paragraphs in batches
-    let mut batch_index = 0;
-    let batch_size = 5;
-    let mut batch_ids = Vec::new();
-    let mut batch_documents = Vec::new();
-    let mut batch_embeddings = Vec::new();
-    for (id_index, paragraph) in paragraphs.into_iter().enumerate() {
-        let response = engine.embed(paragraph).await.unwrap();
-        // Map the response to f32
-        let response = response.iter().map(|x| *x as f32).collect::<Vec<f32>>();
-        let i = path;
-        let id = format!("{}-{}", i.to_str().unwrap(), id_index);
-        let id_str = id;
-        batch_ids.push(id_str);
-        batch_documents.push(paragraph);
-        batch_embeddings.push(response);
-        if batch_index == batch_size {
-            let collection_entries = CollectionEntries {
-                ids: batch_ids.iter().map(|x| x.as_str()).collect::<Vec<&str>>(),
-                embeddings: Some(batch_embeddings.clone()),
-                metadatas: None,
-                documents: Some(batch_documents.clone()),
-            };
-            collection.upsert(collection_entries, None).unwrap();
-            batch_ids.clear();
-            batch_documents.clear();
-            batch_embeddings.clear();
-            batch_index = 0;
+    println!("\nSources:");
+    for (i, passage) in sources.iter().enumerate() {
+        match &passage.source {
+            Some(source) => println!("  [{}] {}", i + 1, source),
+            None => println!("  [{}] {}", i + 1, passage.id),
         }
-        batch_index += 1;
     }
+}
+
+async fn index_file(indexer: &Indexer, chat_id: Uuid, path: &Path, collection: &str) {
+    let progress_bar = ProgressBar::new(0).with_style(
+        ProgressStyle::with_template("🌸 {msg} [{bar:30}] {pos}/{len} chunks")
+            .expect("valid progress template")
+            .progress_chars("=> "),
+    );
+    progress_bar.set_message(path.display().to_string());
+    let result = indexer
+        .index_file(chat_id, path, collection, |progress| match progress {
+            Progress::Chunked { changed, .. } => progress_bar.set_length(changed as u64),
+            Progress::Embedded { done, .. } => progress_bar.set_position(done as u64),
+        })
+        .await;
+    progress_bar.finish_and_clear();
 
-    pretty_message(&format!("Embedded the file: {}", path.display()));
+    match result {
+        Ok(Indexed::Unchanged) => {
+            pretty_message(&format!("Already up to date: {}", path.display()))
+        }
+        Ok(Indexed::Updated {
+            chunks,
+            embedded,
+            removed,
+        }) => pretty_message(&format!(
+            "Embedded {} of {} chunks from {} ({} removed)",
+            embedded,
+            chunks,
+            path.display(),
+            removed
+        )),
+        Err(e) => pretty_warn(&format!("Failed to embed {}: {}", path.display(), e)),
+    }
 }
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::agent::{LlmEngine, LlmEngineError};
+use crate::database::models::ChunkText;
+use crate::database::Database;
+use crate::vector_store::{Passage, Source, VectorStore, VectorStoreError};
+
+/// Dampens how much the very top ranks dominate when fusing rankings,
+/// the value suggested by the original reciprocal rank fusion paper
+pub const RRF_K: f32 = 60.0;
+/// How many candidates each search contributes for every passage asked for
+const CANDIDATES_PER_RESULT: usize = 3;
+
+/// Finds the passages attached to a chat that are most relevant to a query by
+/// combining a BM25 keyword search with a vector search. Keyword search catches
+/// exact identifiers, error codes and names that embeddings tend to blur.
+#[derive(Clone)]
+pub struct Retriever {
+    database: Database,
+    engine: LlmEngine,
+    vector_store: Arc<dyn VectorStore>,
+}
+
+impl Retriever {
+    pub fn new(database: Database, engine: LlmEngine, vector_store: Arc<dyn VectorStore>) -> Self {
+        Self {
+            database,
+            engine,
+            vector_store,
+        }
+    }
+
+    /// The best `limit` passages in a collection, fused from both searches
+    pub async fn retrieve(
+        &self,
+        collection: &str,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<Passage>, RetrievalError> {
+        let candidates = limit * CANDIDATES_PER_RESULT;
+        let keyword = self.keyword_search(collection, query, candidates).await?;
+        let vector = self.vector_search(collection, query, candidates).await?;
+        Ok(reciprocal_rank_fusion(vec![keyword, vector], limit))
+    }
+
+    /// Passages ranked by BM25 against the words in the query
+    pub async fn keyword_search(
+        &self,
+        collection: &str,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<Passage>, RetrievalError> {
+        let Some(query) = fts_query(query) else {
+            return Ok(Vec::new());
+        };
+        let mut conn = self.database.acquire().await?;
+        let chunks = ChunkText::search(collection, &query, limit as i64, &mut conn).await?;
+        chunks.iter().map(passage).collect()
+    }
+
+    /// Passages ranked by how close their embeddings are to the query's
+    pub async fn vector_search(
+        &self,
+        collection: &str,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<Passage>, RetrievalError> {
+        let embedding = self.engine.embed(query).await?;
+        let embedding = embedding.iter().map(|x| *x as f32).collect::<Vec<f32>>();
+        let matches = self
+            .vector_store
+            .query(collection, &embedding, limit)
+            .await?;
+        Ok(matches.into_iter().map(Passage::from).collect())
+    }
+}
+
+/// Merge several rankings of passages into one, scoring each passage by the sum
+/// of `1 / (RRF_K + rank)` over every ranking it appears in
+pub fn reciprocal_rank_fusion(rankings: Vec<Vec<Passage>>, limit: usize) -> Vec<Passage> {
+    let mut scores: HashMap<String, f32> = HashMap::new();
+    // Keep passages in the order first seen so ties break predictably
+    let mut passages = Vec::new();
+    for ranking in rankings {
+        for (rank, passage) in ranking.into_iter().enumerate() {
+            let score = scores.entry(passage.id.clone()).or_insert_with(|| {
+                passages.push(passage);
+                0.0
+            });
+            *score += 1.0 / (RRF_K + rank as f32 + 1.0);
+        }
+    }
+    passages.sort_by(|a, b| scores[&b.id].total_cmp(&scores[&a.id]));
+    passages.truncate(limit);
+    passages
+}
+
+/// Turn free text into an FTS5 query matching any of its words, quoting each
+/// so punctuation and FTS5 operators in the input are taken literally
+fn fts_query(query: &str) -> Option<String> {
+    let terms = query
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect::<Vec<_>>();
+    if terms.is_empty() {
+        return None;
+    }
+    Some(terms.join(" OR "))
+}
+
+/// A chunk indexed for keyword search as a passage, along with where it came from
+pub(crate) fn passage(chunk: &ChunkText) -> Result<Passage, RetrievalError> {
+    let source = chunk.metadata().map(serde_json::from_str).transpose()?;
+    Ok(Passage {
+        id: chunk.id().to_string(),
+        text: chunk.document().to_string(),
+        source,
+    })
+}
+
+/// Index a chunk's text for keyword search, alongside where it came from
+pub(crate) async fn index_text(
+    collection: &str,
+    id: &str,
+    document: &str,
+    source: Option<&Source>,
+    conn: &mut crate::database::DatabaseConnection,
+) -> Result<(), RetrievalError> {
+    let metadata = source.map(serde_json::to_string).transpose()?;
+    ChunkText::upsert(collection, id, document, metadata.as_deref(), conn).await?;
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RetrievalError {
+    #[error("engine error: {0}")]
+    Engine(#[from] LlmEngineError),
+    #[error("vector store error: {0}")]
+    VectorStore(#[from] VectorStoreError),
+    #[error("sqlx error: {0}")]
+    Sqlx(#[from] sqlx::Error),
+    #[error("invalid metadata: {0}")]
+    Metadata(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tests::prelude::*;
+    use crate::vector_store::{SqliteVectorStore, VectorDocument};
+
+    use super::*;
+
+    fn passage(id: &str) -> Passage {
+        Passage {
+            id: id.to_string(),
+            text: id.to_string(),
+            source: None,
+        }
+    }
+
+    #[test]
+    fn test_reciprocal_rank_fusion() {
+        let fused = reciprocal_rank_fusion(
+            vec![
+                vec![passage("a"), passage("b"), passage("c")],
+                vec![passage("c"), passage("d"), passage("a")],
+            ],
+            3,
+        );
+        let ids = fused.iter().map(|p| p.id.as_str()).collect::<Vec<_>>();
+        // `a` and `c` are in both rankings, `a` ranking higher overall than `c`
+        assert_eq!(ids, vec!["a", "c", "b"]);
+    }
+
+    #[test]
+    fn test_fts_query() {
+        assert_eq!(fts_query("  "), None);
+        assert_eq!(
+            fts_query("E1234 \"quoted\" NOT"),
+            Some("\"E1234\" OR \"\"\"quoted\"\"\" OR \"NOT\"".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_finds_exact_identifiers() {
+        let database = test_database().await;
+        let vector_store = Arc::new(SqliteVectorStore::new(database.clone()));
+        let texts = [
+            "cherry blossoms are pink",
+            "cherry blossoms bloom",
+            "failed with error ERR_4402",
+        ];
+        let mut conn = database.acquire().await.unwrap();
+        let mut documents = Vec::new();
+        for (i, text) in texts.iter().enumerate() {
+            index_text("chat", &i.to_string(), text, None, &mut conn)
+                .await
+                .unwrap();
+            documents.push(VectorDocument {
+                id: i.to_string(),
+                document: text.to_string(),
+                embedding: mock_embedding(text).into_iter().map(|x| x as f32).collect(),
+                source: None,
+            });
+        }
+        vector_store.upsert("chat", documents).await.unwrap();
+
+        let retriever = Retriever::new(
+            database,
+            MockLanguageModel::new().engine(),
+            vector_store.clone(),
+        );
+        let passages = retriever
+            .retrieve("chat", "what does ERR_4402 mean?", 1)
+            .await
+            .unwrap();
+        assert_eq!(passages[0].text, "failed with error ERR_4402");
+
+        assert!(retriever
+            .keyword_search("chat", "tulips", 3)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
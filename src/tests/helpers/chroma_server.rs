@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Value};
+use tokio::io::BufReader;
+use tokio::net::{TcpListener, TcpStream};
+use url::Url;
+
+use super::http::{read_request, respond, FakeRequest};
+use crate::vector_store::cosine_distance;
+
+#[derive(Debug, Default)]
+struct FakeCollection {
+    id: String,
+    name: String,
+    metadata: Value,
+    // Keyed by document id: (document, embedding, metadata)
+    documents: HashMap<String, (String, Vec<f32>, Value)>,
+}
+
+#[derive(Debug, Default)]
+struct FakeChromaState {
+    collections: Vec<FakeCollection>,
+    requests: Vec<FakeRequest>,
+}
+
+/// An in-process stand-in for a Chroma server.
+/// Speaks just enough of the v1 REST API to exercise our Chroma vector store,
+/// searching its in-memory collections by brute force.
+#[derive(Debug, Clone)]
+pub(crate) struct FakeChroma {
+    url: Url,
+    state: Arc<Mutex<FakeChromaState>>,
+}
+
+impl FakeChroma {
+    /// Bind to a random local port and start serving requests in the background
+    pub(crate) async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind fake chroma");
+        let address = listener.local_addr().expect("local address");
+        let url = Url::parse(&format!("http://{}", address)).expect("fake chroma url");
+        let state = Arc::new(Mutex::new(FakeChromaState::default()));
+
+        let server_state = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = server_state.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve(stream, state).await {
+                        tracing::warn!("fake chroma failed to serve a request: {}", e);
+                    }
+                });
+            }
+        });
+
+        Self { url, state }
+    }
+
+    pub(crate) fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// The names of every collection that currently exists
+    pub(crate) fn collections(&self) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        state.collections.iter().map(|c| c.name.clone()).collect()
+    }
+
+    /// Every request received so far, in order
+    pub(crate) fn requests(&self) -> Vec<FakeRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+async fn serve(stream: TcpStream, state: Arc<Mutex<FakeChromaState>>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let request = read_request(&mut reader).await?;
+
+    let (status, body) = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(request.clone());
+        handle(&mut state, &request)
+    };
+    respond(reader, status, &body.to_string()).await
+}
+
+/// Chroma's rules for collection names, short of the IP address check
+fn valid_name(name: &str) -> bool {
+    (3..=63).contains(&name.len())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name.ends_with(|c: char| c.is_ascii_alphanumeric())
+        && !name.contains("..")
+}
+
+fn invalid_name(name: &str) -> (&'static str, Value) {
+    (
+        "400 Bad Request",
+        json!({ "error": format!("Expected collection name {} to be 3-63 characters long", name) }),
+    )
+}
+
+fn describe(collection: &FakeCollection) -> Value {
+    json!({
+        "id": collection.id,
+        "name": collection.name,
+        "metadata": collection.metadata,
+    })
+}
+
+fn handle(state: &mut FakeChromaState, request: &FakeRequest) -> (&'static str, Value) {
+    let not_found = |name: &str| {
+        (
+            "404 Not Found",
+            json!({ "error": format!("Collection {} does not exist.", name) }),
+        )
+    };
+    let segments = request
+        .path
+        .trim_start_matches("/api/v1/")
+        .split('/')
+        .collect::<Vec<_>>();
+
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["heartbeat"]) => ("200 OK", json!({ "nanosecond heartbeat": 1 })),
+        ("POST", ["collections"]) => {
+            let name = request.body["name"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string();
+            if !valid_name(&name) {
+                return invalid_name(&name);
+            }
+            if !state.collections.iter().any(|c| c.name == name) {
+                state.collections.push(FakeCollection {
+                    id: format!("collection-{}", state.collections.len()),
+                    name: name.clone(),
+                    metadata: request.body["metadata"].clone(),
+                    documents: HashMap::new(),
+                });
+            }
+            let collection = state.collections.iter().find(|c| c.name == name).unwrap();
+            ("200 OK", describe(collection))
+        }
+        ("GET", ["collections", name]) => {
+            match state.collections.iter().find(|c| c.name == *name) {
+                Some(collection) => ("200 OK", describe(collection)),
+                None => not_found(name),
+            }
+        }
+        ("PUT", ["collections", id]) => {
+            let Some(collection) = state.collections.iter_mut().find(|c| c.id == *id) else {
+                return not_found(id);
+            };
+            if let Some(name) = request.body["new_name"].as_str() {
+                if !valid_name(name) {
+                    return invalid_name(name);
+                }
+                collection.name = name.to_string();
+            }
+            if let Some(metadata) = request.body.get("new_metadata") {
+                collection.metadata = metadata.clone();
+            }
+            ("200 OK", Value::Null)
+        }
+        ("GET", ["collections", id, "count"]) => {
+            match state.collections.iter().find(|c| c.id == *id) {
+                Some(collection) => ("200 OK", json!(collection.documents.len())),
+                None => not_found(id),
+            }
+        }
+        ("DELETE", ["collections", name]) => {
+            let count = state.collections.len();
+            state.collections.retain(|c| c.name != *name);
+            if state.collections.len() == count {
+                return not_found(name);
+            }
+            ("200 OK", Value::Null)
+        }
+        ("POST", ["collections", id, "upsert"]) => {
+            let Some(collection) = state.collections.iter_mut().find(|c| c.id == *id) else {
+                return not_found(id);
+            };
+            let body = &request.body;
+            for (i, document_id) in body["ids"].as_array().into_iter().flatten().enumerate() {
+                let document = body["documents"][i].as_str().unwrap_or_default();
+                let embedding =
+                    serde_json::from_value(body["embeddings"][i].clone()).unwrap_or_default();
+                let metadata = body["metadatas"][i].clone();
+                collection.documents.insert(
+                    document_id.as_str().unwrap_or_default().to_string(),
+                    (document.to_string(), embedding, metadata),
+                );
+            }
+            ("200 OK", json!(true))
+        }
+        ("POST", ["collections", id, "update"]) => {
+            let Some(collection) = state.collections.iter_mut().find(|c| c.id == *id) else {
+                return not_found(id);
+            };
+            let body = &request.body;
+            for (i, document_id) in body["ids"].as_array().into_iter().flatten().enumerate() {
+                let document_id = document_id.as_str().unwrap_or_default();
+                if let Some(stored) = collection.documents.get_mut(document_id) {
+                    stored.2 = body["metadatas"][i].clone();
+                }
+            }
+            ("200 OK", json!(true))
+        }
+        ("POST", ["collections", id, "delete"]) => {
+            let Some(collection) = state.collections.iter_mut().find(|c| c.id == *id) else {
+                return not_found(id);
+            };
+            for document_id in request.body["ids"].as_array().into_iter().flatten() {
+                collection
+                    .documents
+                    .remove(document_id.as_str().unwrap_or_default());
+            }
+            ("200 OK", json!([]))
+        }
+        ("POST", ["collections", id, "query"]) => {
+            let Some(collection) = state.collections.iter().find(|c| c.id == *id) else {
+                return not_found(id);
+            };
+            let query: Vec<f32> =
+                serde_json::from_value(request.body["query_embeddings"][0].clone())
+                    .unwrap_or_default();
+            let limit = request.body["n_results"].as_u64().unwrap_or(10) as usize;
+            let mut matches = collection
+                .documents
+                .iter()
+                .map(|(id, (document, embedding, metadata))| {
+                    (id, document, cosine_distance(embedding, &query), metadata)
+                })
+                .collect::<Vec<_>>();
+            matches.sort_by(|a, b| a.2.total_cmp(&b.2));
+            matches.truncate(limit);
+            (
+                "200 OK",
+                json!({
+                    "ids": [matches.iter().map(|m| m.0).collect::<Vec<_>>()],
+                    "documents": [matches.iter().map(|m| m.1).collect::<Vec<_>>()],
+                    "distances": [matches.iter().map(|m| m.2).collect::<Vec<_>>()],
+                    "metadatas": [matches.iter().map(|m| m.3).collect::<Vec<_>>()],
+                    "embeddings": null,
+                }),
+            )
+        }
+        _ => ("404 Not Found", Value::Null),
+    }
+}
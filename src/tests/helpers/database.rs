@@ -6,3 +6,16 @@ pub(crate) async fn test_database() -> Database {
         .await
         .unwrap()
 }
+
+/// A database in a temporary file, which unlike one in memory runs in WAL mode
+/// and so catches transactions outliving writes made on other connections.
+/// The files are removed along with the returned directory.
+pub(crate) async fn test_file_database() -> (Database, std::path::PathBuf) {
+    let directory =
+        std::env::temp_dir().join(format!("blossom-database-{}", rand::random::<u64>()));
+    std::fs::create_dir(&directory).unwrap();
+    let path = directory.join("blossom.db");
+    let url = Url::parse(&format!("sqlite://{}", path.display())).unwrap();
+    let database = Database::connect(&url).await.unwrap();
+    (database, directory)
+}
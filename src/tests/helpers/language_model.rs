@@ -0,0 +1,224 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures::stream;
+
+use crate::agent::{
+    Completion, CompletionStream, Context, GenerationOptions, LanguageModel, LanguageModelError,
+    LlmEngine, Message,
+};
+
+/// The number of dimensions of the embeddings produced by the mock
+pub(crate) const MOCK_EMBEDDING_DIMENSIONS: usize = 16;
+
+/// A request the mock language model received
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum MockRequest {
+    Chat {
+        model: String,
+        messages: Vec<Message>,
+    },
+    ChatStream {
+        model: String,
+        messages: Vec<Message>,
+    },
+    Complete {
+        model: String,
+        prompt: String,
+        context: Option<Context>,
+    },
+    Embed {
+        model: String,
+        input: String,
+    },
+    Vision {
+        model: String,
+        prompt: String,
+        images: usize,
+    },
+}
+
+#[derive(Debug, Default)]
+struct MockScript {
+    chats: VecDeque<String>,
+    completions: VecDeque<String>,
+    requests: Vec<MockRequest>,
+    options: Vec<GenerationOptions>,
+}
+
+/// A scriptable, deterministic language model.
+/// Chat and completion responses are handed out in the order they were
+/// scripted, embeddings are derived from the input bytes, and every request
+/// is recorded so tests can assert on what reached the model.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MockLanguageModel(Arc<Mutex<MockScript>>);
+
+impl MockLanguageModel {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Script the next response to a chat request
+    pub(crate) fn chat_response(self, response: &str) -> Self {
+        self.0.lock().unwrap().chats.push_back(response.to_string());
+        self
+    }
+
+    /// Script the next response to a streamed chat, completion or vision request
+    pub(crate) fn completion_response(self, response: &str) -> Self {
+        self.0
+            .lock()
+            .unwrap()
+            .completions
+            .push_back(response.to_string());
+        self
+    }
+
+    /// Every request received so far, in order
+    pub(crate) fn requests(&self) -> Vec<MockRequest> {
+        self.0.lock().unwrap().requests.clone()
+    }
+
+    /// The generation options of every chat, streamed chat and completion request, in order
+    pub(crate) fn options(&self) -> Vec<GenerationOptions> {
+        self.0.lock().unwrap().options.clone()
+    }
+
+    /// Build an engine backed by this mock, using the model names `supervisor`,
+    /// `conversational`, `image` and `embedding`
+    pub(crate) fn engine(&self) -> LlmEngine {
+        LlmEngine::new(
+            Arc::new(self.clone()),
+            "supervisor".to_string(),
+            "conversational".to_string(),
+            "image".to_string(),
+            "embedding".to_string(),
+        )
+    }
+
+    fn record(&self, request: MockRequest) {
+        self.0.lock().unwrap().requests.push(request);
+    }
+
+    fn record_options(&self, options: &GenerationOptions) {
+        self.0.lock().unwrap().options.push(*options);
+    }
+
+    /// Stream the next scripted completion a word at a time, ending with a context
+    /// that counts how many completions have been streamed so far.
+    /// Streamed chats carry their history in their messages, so end without one.
+    fn next_completion(&self) -> CompletionStream {
+        let mut script = self.0.lock().unwrap();
+        let response = script.completions.pop_front().unwrap_or_default();
+        let turn = script
+            .requests
+            .iter()
+            .filter(|request| {
+                matches!(
+                    request,
+                    MockRequest::Complete { .. } | MockRequest::Vision { .. }
+                )
+            })
+            .count();
+
+        let mut pieces = response
+            .split_inclusive(' ')
+            .map(|text| {
+                Ok(Completion {
+                    text: text.to_string(),
+                    context: None,
+                })
+            })
+            .collect::<Vec<_>>();
+        let streamed_chat = matches!(script.requests.last(), Some(MockRequest::ChatStream { .. }));
+        pieces.push(Ok(Completion {
+            text: String::new(),
+            context: (!streamed_chat).then(|| Context::from(vec![turn as i32])),
+        }));
+        Box::pin(stream::iter(pieces))
+    }
+}
+
+/// A deterministic embedding which places inputs sharing bytes near each other
+pub(crate) fn mock_embedding(input: &str) -> Vec<f64> {
+    let mut embedding = vec![0.0; MOCK_EMBEDDING_DIMENSIONS];
+    for byte in input.to_lowercase().bytes() {
+        embedding[byte as usize % MOCK_EMBEDDING_DIMENSIONS] += 1.0;
+    }
+    embedding
+}
+
+#[async_trait]
+impl LanguageModel for MockLanguageModel {
+    async fn chat(
+        &self,
+        model: &str,
+        messages: &[Message],
+        options: &GenerationOptions,
+    ) -> Result<String, LanguageModelError> {
+        self.record_options(options);
+        self.record(MockRequest::Chat {
+            model: model.to_string(),
+            messages: messages.to_vec(),
+        });
+        self.0
+            .lock()
+            .unwrap()
+            .chats
+            .pop_front()
+            .ok_or(LanguageModelError::NoMessage)
+    }
+
+    async fn chat_stream(
+        &self,
+        model: &str,
+        messages: &[Message],
+        options: &GenerationOptions,
+    ) -> Result<CompletionStream, LanguageModelError> {
+        self.record_options(options);
+        self.record(MockRequest::ChatStream {
+            model: model.to_string(),
+            messages: messages.to_vec(),
+        });
+        Ok(self.next_completion())
+    }
+
+    async fn complete(
+        &self,
+        model: &str,
+        prompt: &str,
+        context: Option<Context>,
+        options: &GenerationOptions,
+    ) -> Result<CompletionStream, LanguageModelError> {
+        self.record_options(options);
+        self.record(MockRequest::Complete {
+            model: model.to_string(),
+            prompt: prompt.to_string(),
+            context,
+        });
+        Ok(self.next_completion())
+    }
+
+    async fn embed(&self, model: &str, input: &str) -> Result<Vec<f64>, LanguageModelError> {
+        self.record(MockRequest::Embed {
+            model: model.to_string(),
+            input: input.to_string(),
+        });
+        Ok(mock_embedding(input))
+    }
+
+    async fn vision(
+        &self,
+        model: &str,
+        prompt: &str,
+        images: &[String],
+    ) -> Result<CompletionStream, LanguageModelError> {
+        self.record(MockRequest::Vision {
+            model: model.to_string(),
+            prompt: prompt.to_string(),
+            images: images.len(),
+        });
+        Ok(self.next_completion())
+    }
+}
@@ -1,3 +1,12 @@
+mod chroma_server;
 mod database;
+mod http;
+mod language_model;
+mod ollama_server;
 
-pub(crate) use database::test_database;
+pub(crate) use chroma_server::FakeChroma;
+pub(crate) use database::{test_database, test_file_database};
+pub(crate) use language_model::{
+    mock_embedding, MockLanguageModel, MockRequest, MOCK_EMBEDDING_DIMENSIONS,
+};
+pub(crate) use ollama_server::FakeOllama;
@@ -0,0 +1,154 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Value};
+use tokio::io::BufReader;
+use tokio::net::{TcpListener, TcpStream};
+use url::Url;
+
+use super::http::{read_request, respond};
+use super::language_model::mock_embedding;
+
+#[derive(Debug, Default)]
+struct FakeOllamaScript {
+    chats: VecDeque<String>,
+    generations: VecDeque<String>,
+    requests: Vec<(String, Value)>,
+}
+
+/// An in-process stand-in for an Ollama server.
+/// Speaks just enough of `/api/chat`, `/api/generate` and `/api/embeddings`
+/// to exercise our Ollama backend without a live server.
+#[derive(Debug, Clone)]
+pub(crate) struct FakeOllama {
+    url: Url,
+    script: Arc<Mutex<FakeOllamaScript>>,
+}
+
+impl FakeOllama {
+    /// Bind to a random local port and start serving requests in the background
+    pub(crate) async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind fake ollama");
+        let address = listener.local_addr().expect("local address");
+        let url = Url::parse(&format!("http://{}", address)).expect("fake ollama url");
+        let script = Arc::new(Mutex::new(FakeOllamaScript::default()));
+
+        let server_script = script.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let script = server_script.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve(stream, script).await {
+                        tracing::warn!("fake ollama failed to serve a request: {}", e);
+                    }
+                });
+            }
+        });
+
+        Self { url, script }
+    }
+
+    pub(crate) fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Script the content of the next `/api/chat` response
+    pub(crate) fn chat_response(&self, response: &str) -> &Self {
+        self.script
+            .lock()
+            .unwrap()
+            .chats
+            .push_back(response.to_string());
+        self
+    }
+
+    /// Script the text streamed by the next `/api/generate` response
+    pub(crate) fn generate_response(&self, response: &str) -> &Self {
+        self.script
+            .lock()
+            .unwrap()
+            .generations
+            .push_back(response.to_string());
+        self
+    }
+
+    /// The path and JSON body of every request received so far, in order
+    pub(crate) fn requests(&self) -> Vec<(String, Value)> {
+        self.script.lock().unwrap().requests.clone()
+    }
+}
+
+async fn serve(stream: TcpStream, script: Arc<Mutex<FakeOllamaScript>>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let request = read_request(&mut reader).await?;
+    let (path, body) = (request.path, request.body);
+
+    let response = {
+        let mut script = script.lock().unwrap();
+        script.requests.push((path.clone(), body.clone()));
+        let model = body["model"].clone();
+        match path.as_str() {
+            // Streamed chats are answered in a single, final piece
+            "/api/chat" => {
+                let content = script.chats.pop_front().unwrap_or_default();
+                Some(
+                    json!({
+                        "model": model,
+                        "created_at": "2024-01-01T00:00:00Z",
+                        "message": { "role": "assistant", "content": content },
+                        "done": true,
+                    })
+                    .to_string(),
+                )
+            }
+            "/api/generate" => {
+                let generation = script.generations.pop_front().unwrap_or_default();
+                let context = script
+                    .requests
+                    .iter()
+                    .filter(|(path, _)| path == "/api/generate")
+                    .count();
+                let mut lines = generation
+                    .split_inclusive(' ')
+                    .map(|piece| {
+                        json!({
+                            "model": model,
+                            "created_at": "2024-01-01T00:00:00Z",
+                            "response": piece,
+                            "done": false,
+                        })
+                        .to_string()
+                    })
+                    .collect::<Vec<_>>();
+                lines.push(
+                    json!({
+                        "model": model,
+                        "created_at": "2024-01-01T00:00:00Z",
+                        "response": "",
+                        "done": true,
+                        "context": [context],
+                        "total_duration": 0,
+                        "prompt_eval_count": 0,
+                        "prompt_eval_duration": 0,
+                        "eval_count": 0,
+                        "eval_duration": 0,
+                    })
+                    .to_string(),
+                );
+                Some(lines.join("\n"))
+            }
+            "/api/embeddings" => {
+                let prompt = body["prompt"].as_str().unwrap_or_default();
+                Some(json!({ "embedding": mock_embedding(prompt) }).to_string())
+            }
+            _ => None,
+        }
+    };
+
+    match response {
+        Some(body) => respond(reader, "200 OK", &body).await,
+        None => respond(reader, "404 Not Found", "").await,
+    }
+}
@@ -0,0 +1,383 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::database::models::{Attachment, Chat, Message, MessageRole, UnknownMessageRole};
+use crate::database::Database;
+
+/// The version of the export format, bumped whenever it changes incompatibly
+pub const EXPORT_VERSION: u32 = 1;
+
+/// Everything needed to share a chat or recreate it elsewhere: the messages on its current branch,
+/// the tools called along the way and what was attached to it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatExport {
+    pub version: u32,
+    pub name: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    pub messages: Vec<MessageExport>,
+    pub attachments: Vec<AttachmentExport>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageExport {
+    pub role: String,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_arguments: Option<Value>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+/// What was known about an attached file when the chat was exported.
+/// The file itself isn't included, so importing only re-embeds it if it can be found.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttachmentExport {
+    pub path: String,
+    pub size: i64,
+    pub chunk_count: i64,
+    pub content_hash: String,
+    #[serde(default)]
+    pub embedding_model: Option<String>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub indexed_at: Option<OffsetDateTime>,
+}
+
+/// A single line of a JSON Lines export: the chat comes first, followed by
+/// its messages and attachments
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Line {
+    Chat {
+        version: u32,
+        name: String,
+        #[serde(with = "time::serde::rfc3339")]
+        created_at: OffsetDateTime,
+    },
+    Message(MessageExport),
+    Attachment(AttachmentExport),
+}
+
+impl ChatExport {
+    pub async fn read(database: &Database, chat_id: Uuid) -> Result<Self, TranscriptError> {
+        let mut conn = database.acquire().await?;
+        let chat = Chat::read(chat_id, &mut conn).await?;
+        let messages = Message::read_branch(chat_id, &mut conn)
+            .await?
+            .into_iter()
+            .map(|message| {
+                let tool_arguments = message
+                    .tool_arguments()
+                    .map(serde_json::from_str)
+                    .transpose()?;
+                Ok(MessageExport {
+                    role: message.role().to_string(),
+                    content: message.content().to_string(),
+                    tool_name: message.tool_name().map(str::to_string),
+                    tool_arguments,
+                    created_at: message.created_at(),
+                })
+            })
+            .collect::<Result<Vec<_>, TranscriptError>>()?;
+        let attachments = Attachment::read_by_chat(chat_id, &mut conn)
+            .await?
+            .into_iter()
+            .map(|attachment| AttachmentExport {
+                path: attachment.path().to_string(),
+                size: attachment.size(),
+                chunk_count: attachment.chunk_count(),
+                content_hash: attachment.content_hash().to_string(),
+                embedding_model: attachment.embedding_model().map(str::to_string),
+                indexed_at: attachment.indexed_at(),
+            })
+            .collect();
+        Ok(Self {
+            version: EXPORT_VERSION,
+            name: chat.name().to_string(),
+            created_at: chat.created_at(),
+            messages,
+            attachments,
+        })
+    }
+
+    /// Recreate the chat and its messages under `name`, returning the new chat's id.
+    /// Attachments are left for the caller to re-embed, as they need indexing afresh.
+    pub async fn create(&self, name: &str, database: &Database) -> Result<Uuid, TranscriptError> {
+        let mut conn = database.begin().await?;
+        let chat_id = Chat::create(name, &mut conn).await?;
+        for message in self.messages.iter() {
+            let role = message.role.parse::<MessageRole>()?;
+            let tool_arguments = message.tool_arguments.as_ref().map(Value::to_string);
+            Message::create_at(
+                chat_id,
+                role,
+                &message.content,
+                message.tool_name.as_deref(),
+                tool_arguments.as_deref(),
+                message.created_at,
+                &mut conn,
+            )
+            .await?;
+        }
+        conn.commit().await?;
+        Ok(chat_id)
+    }
+
+    /// Read an export written as either JSON or JSON Lines
+    pub fn parse(text: &str) -> Result<Self, TranscriptError> {
+        if let Ok(export) = serde_json::from_str::<ChatExport>(text) {
+            return Self::check_version(export);
+        }
+
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let first = lines.next().ok_or(TranscriptError::Empty)?;
+        let Line::Chat {
+            version,
+            name,
+            created_at,
+        } = serde_json::from_str(first)?
+        else {
+            return Err(TranscriptError::MissingChat);
+        };
+        let mut export = ChatExport {
+            version,
+            name,
+            created_at,
+            messages: Vec::new(),
+            attachments: Vec::new(),
+        };
+        for line in lines {
+            match serde_json::from_str(line)? {
+                Line::Chat { .. } => return Err(TranscriptError::MultipleChats),
+                Line::Message(message) => export.messages.push(message),
+                Line::Attachment(attachment) => export.attachments.push(attachment),
+            }
+        }
+        Self::check_version(export)
+    }
+
+    fn check_version(export: Self) -> Result<Self, TranscriptError> {
+        if export.version > EXPORT_VERSION {
+            return Err(TranscriptError::UnsupportedVersion(export.version));
+        }
+        Ok(export)
+    }
+
+    pub fn to_json(&self) -> Result<String, TranscriptError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_jsonl(&self) -> Result<String, TranscriptError> {
+        let mut lines = vec![serde_json::to_string(&Line::Chat {
+            version: self.version,
+            name: self.name.clone(),
+            created_at: self.created_at,
+        })?];
+        for message in self.messages.iter() {
+            lines.push(serde_json::to_string(&Line::Message(message.clone()))?);
+        }
+        for attachment in self.attachments.iter() {
+            lines.push(serde_json::to_string(&Line::Attachment(
+                attachment.clone(),
+            ))?);
+        }
+        Ok(lines.join("\n") + "\n")
+    }
+
+    /// A readable transcript for sharing, which can't be imported again
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = format!("# {}\n\n", self.name);
+        markdown.push_str(&format!("Created {}\n", date(self.created_at)));
+
+        if !self.attachments.is_empty() {
+            markdown.push_str("\n## Attachments\n\n");
+            for attachment in self.attachments.iter() {
+                markdown.push_str(&format!(
+                    "- `{}`: {} bytes, {} chunks",
+                    attachment.path, attachment.size, attachment.chunk_count
+                ));
+                if let Some(embedding_model) = &attachment.embedding_model {
+                    markdown.push_str(&format!(", embedded with `{}`", embedding_model));
+                }
+                markdown.push('\n');
+            }
+        }
+
+        markdown.push_str("\n## Transcript\n");
+        for message in self.messages.iter() {
+            let heading = match (message.role.as_str(), &message.tool_name) {
+                ("user", _) => "User".to_string(),
+                ("tool", Some(tool_name)) => format!("Tool `{}`", tool_name),
+                ("assistant", Some(tool_name)) => format!("Assistant via `{}`", tool_name),
+                ("assistant", None) => "Assistant".to_string(),
+                (role, _) => role.to_string(),
+            };
+            markdown.push_str(&format!("\n### {}\n\n", heading));
+            if let Some(Value::Object(arguments)) = &message.tool_arguments {
+                for (name, value) in arguments {
+                    let value = value
+                        .as_str()
+                        .map(str::to_string)
+                        .unwrap_or(value.to_string());
+                    markdown.push_str(&format!("- `{}`: {}\n", name, value));
+                }
+                markdown.push('\n');
+            }
+            markdown.push_str(message.content.trim());
+            markdown.push('\n');
+        }
+        markdown
+    }
+}
+
+fn date(at: OffsetDateTime) -> String {
+    format!("{} {:02}:{:02}", at.date(), at.hour(), at.minute())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TranscriptError {
+    #[error("sqlx error: {0}")]
+    Sqlx(#[from] sqlx::Error),
+    #[error("invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    UnknownRole(#[from] UnknownMessageRole),
+    #[error("the export is empty")]
+    Empty,
+    #[error("the first line of the export must describe the chat")]
+    MissingChat,
+    #[error("the export describes more than one chat")]
+    MultipleChats,
+    #[error("the export was written by a newer version (format {0})")]
+    UnsupportedVersion(u32),
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tests::prelude::*;
+
+    use super::*;
+
+    async fn chat(database: &Database) -> Uuid {
+        let mut conn = database.acquire().await.unwrap();
+        let chat_id = Chat::create("blossoms", &mut conn).await.unwrap();
+        Message::create(
+            chat_id,
+            MessageRole::User,
+            "what color?",
+            None,
+            None,
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        Message::create(
+            chat_id,
+            MessageRole::Tool,
+            "Found 1 relevant passages",
+            Some("search_documents"),
+            Some(r#"{"query":"color"}"#),
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        Message::create(
+            chat_id,
+            MessageRole::Assistant,
+            "Pink!",
+            Some("converse"),
+            Some(r#"{"input":"what color?"}"#),
+            &mut conn,
+        )
+        .await
+        .unwrap();
+        let attachment = Attachment::get_or_create(chat_id, "/notes.txt", &mut conn)
+            .await
+            .unwrap();
+        Attachment::mark_indexed(attachment.id(), "abc", 42, 3, "embedding", &mut conn)
+            .await
+            .unwrap();
+        chat_id
+    }
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let database = test_database().await;
+        let chat_id = chat(&database).await;
+        let export = ChatExport::read(&database, chat_id).await.unwrap();
+        assert_eq!(export.messages.len(), 3);
+        assert_eq!(
+            export.messages[1].tool_arguments,
+            Some(serde_json::json!({ "query": "color" }))
+        );
+        assert_eq!(export.attachments[0].chunk_count, 3);
+
+        let json = export.to_json().unwrap();
+        assert_eq!(ChatExport::parse(&json).unwrap(), export);
+        let jsonl = export.to_jsonl().unwrap();
+        assert_eq!(jsonl.lines().count(), 5);
+        assert_eq!(ChatExport::parse(&jsonl).unwrap(), export);
+
+        // Messages keep when they were written, however long ago that was
+        let mut export = export;
+        for (i, message) in export.messages.iter_mut().enumerate() {
+            message.created_at = OffsetDateTime::from_unix_timestamp(1_714_564_800).unwrap()
+                + time::Duration::milliseconds(500 + 60_000 * i as i64);
+        }
+        let imported_id = export.create("copy", &database).await.unwrap();
+        let imported = ChatExport::read(&database, imported_id).await.unwrap();
+        assert_eq!(imported.name, "copy");
+        let contents = |export: &ChatExport| {
+            export
+                .messages
+                .iter()
+                .map(|m| (m.role.clone(), m.content.clone(), m.tool_arguments.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(contents(&imported), contents(&export));
+        let created_at = |export: &ChatExport| {
+            export
+                .messages
+                .iter()
+                .map(|m| m.created_at)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(created_at(&imported), created_at(&export));
+        assert!(imported.attachments.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_markdown() {
+        let database = test_database().await;
+        let chat_id = chat(&database).await;
+        let markdown = ChatExport::read(&database, chat_id)
+            .await
+            .unwrap()
+            .to_markdown();
+        assert!(markdown.starts_with("# blossoms\n"));
+        assert!(markdown.contains("- `/notes.txt`: 42 bytes, 3 chunks, embedded with `embedding`"));
+        assert!(markdown.contains("### User\n\nwhat color?\n"));
+        assert!(markdown.contains("### Tool `search_documents`\n\n- `query`: color\n"));
+        assert!(markdown.contains("### Assistant via `converse`"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(ChatExport::parse(""), Err(TranscriptError::Empty)));
+        let message = r#"{"type":"message","role":"user","content":"hi","created_at":"2024-05-01T00:00:00Z"}"#;
+        assert!(matches!(
+            ChatExport::parse(message),
+            Err(TranscriptError::MissingChat)
+        ));
+        let newer =
+            r#"{"type":"chat","version":99,"name":"a","created_at":"2024-05-01T00:00:00Z"}"#;
+        assert!(matches!(
+            ChatExport::parse(newer),
+            Err(TranscriptError::UnsupportedVersion(99))
+        ));
+    }
+}
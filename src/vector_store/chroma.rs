@@ -0,0 +1,590 @@
+use async_trait::async_trait;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use super::{Source, VectorDocument, VectorMatch, VectorStore, VectorStoreError};
+
+/// The tenant Chroma puts collections in unless told otherwise
+pub const DEFAULT_CHROMA_TENANT: &str = "default_tenant";
+/// The database Chroma puts collections in unless told otherwise
+pub const DEFAULT_CHROMA_DATABASE: &str = "default_database";
+
+/// Everything but the characters a URL path segment may carry as they are
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// A vector store backed by a Chroma server, spoken to over its v1 REST API
+#[derive(Debug, Clone)]
+pub struct ChromaVectorStore {
+    client: Client,
+    base_url: Url,
+    auth_token: Option<String>,
+    tenant: String,
+    database: String,
+}
+
+impl ChromaVectorStore {
+    pub fn new(url: &Url, auth_token: Option<String>, tenant: String, database: String) -> Self {
+        // Make sure joined paths land under the base path rather than replacing it
+        let mut base_url = url.clone();
+        let path = base_url.path().trim_end_matches('/').to_string();
+        base_url.set_path(&format!("{}/api/v1/", path));
+        Self {
+            client: Client::new(),
+            base_url,
+            auth_token,
+            tenant,
+            database,
+        }
+    }
+
+    /// Check the server is up, returning its clock in nanoseconds
+    pub async fn heartbeat(&self) -> Result<u64, VectorStoreError> {
+        let response = self
+            .send(self.request(Method::GET, "heartbeat")?)
+            .await?
+            .json::<Heartbeat>()
+            .await?;
+        Ok(response.nanosecond_heartbeat)
+    }
+
+    fn request(&self, method: Method, path: &str) -> Result<RequestBuilder, VectorStoreError> {
+        let url = self.base_url.join(path)?;
+        let request = self.client.request(method, url);
+        Ok(match &self.auth_token {
+            Some(auth_token) => request.bearer_auth(auth_token),
+            None => request,
+        })
+    }
+
+    /// A request against a collection by name, scoped to our tenant and database
+    fn collection_request(
+        &self,
+        method: Method,
+        path: &str,
+    ) -> Result<RequestBuilder, VectorStoreError> {
+        Ok(self.request(method, path)?.query(&[
+            ("tenant", self.tenant.as_str()),
+            ("database", self.database.as_str()),
+        ]))
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response, VectorStoreError> {
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(VectorStoreError::Status(status.as_u16(), message));
+        }
+        Ok(response)
+    }
+
+    async fn get_or_create_collection(&self, name: &str) -> Result<Collection, VectorStoreError> {
+        let body = json!({
+            "name": collection_name(name),
+            "metadata": null,
+            "get_or_create": true,
+        });
+        let request = self
+            .collection_request(Method::POST, "collections")?
+            .json(&body);
+        Ok(self.send(request).await?.json::<Collection>().await?)
+    }
+
+    /// Look up a collection by name, returning `None` if it doesn't exist
+    async fn get_collection(&self, name: &str) -> Result<Option<Collection>, VectorStoreError> {
+        let path = collection_path(&collection_name(name));
+        let request = self.collection_request(Method::GET, &path)?;
+        match self.send(request).await {
+            Ok(response) => Ok(Some(response.json::<Collection>().await?)),
+            Err(e) if is_missing_collection(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn count(&self, collection: &Collection) -> Result<usize, VectorStoreError> {
+        let path = format!("collections/{}/count", collection.id);
+        Ok(self
+            .send(self.request(Method::GET, &path)?)
+            .await?
+            .json::<usize>()
+            .await?)
+    }
+
+    /// Fail unless the collection holds embeddings of the given dimension or none at all
+    async fn check_dimension(
+        &self,
+        collection: &Collection,
+        name: &str,
+        actual: usize,
+    ) -> Result<(), VectorStoreError> {
+        match collection.dimension() {
+            Some(expected) if expected != actual && self.count(collection).await? > 0 => {
+                Err(VectorStoreError::DimensionMismatch {
+                    collection: name.to_string(),
+                    expected,
+                    actual,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The name a collection goes by in Chroma, which only takes names of 3 to 63 characters
+/// out of `[a-zA-Z0-9._-]` that start and end with a letter or digit, hold no `..`
+/// and aren't IP addresses. Names breaking those rules have everything else replaced
+/// and a hash of the original added, so that different names stay apart.
+fn collection_name(name: &str) -> String {
+    if is_valid_collection_name(name) {
+        return name.to_string();
+    }
+    let mut sanitized = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            sanitized.push(c);
+        } else if !sanitized.ends_with('-') {
+            sanitized.push('-');
+        }
+    }
+    let sanitized = sanitized
+        .trim_matches(|c: char| !c.is_ascii_alphanumeric())
+        .chars()
+        .take(54)
+        .collect::<String>();
+    let hash = format!("{:x}", Sha256::digest(name.as_bytes()));
+    match sanitized.trim_end_matches(|c: char| !c.is_ascii_alphanumeric()) {
+        "" => hash[..8].to_string(),
+        sanitized => format!("{}-{}", sanitized, &hash[..8]),
+    }
+}
+
+fn is_valid_collection_name(name: &str) -> bool {
+    let starts_and_ends_alphanumeric = name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name.ends_with(|c: char| c.is_ascii_alphanumeric());
+    (3..=63).contains(&name.len())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        && starts_and_ends_alphanumeric
+        && !name.contains("..")
+        && name.parse::<std::net::Ipv4Addr>().is_err()
+}
+
+/// The path of a collection looked up by name, which is escaped since chat names may hold anything
+fn collection_path(name: &str) -> String {
+    format!("collections/{}", utf8_percent_encode(name, PATH_SEGMENT))
+}
+
+/// Chroma reports missing collections as a 404 or, in older versions, a 500 naming the problem
+fn is_missing_collection(error: &VectorStoreError) -> bool {
+    match error {
+        VectorStoreError::Status(status, message) => {
+            *status == StatusCode::NOT_FOUND.as_u16() || message.contains("does not exist")
+        }
+        _ => false,
+    }
+}
+
+#[async_trait]
+impl VectorStore for ChromaVectorStore {
+    async fn upsert(
+        &self,
+        collection: &str,
+        documents: Vec<VectorDocument>,
+    ) -> Result<(), VectorStoreError> {
+        if documents.is_empty() {
+            return Ok(());
+        }
+        let name = collection;
+        let collection = self.get_or_create_collection(name).await?;
+        let dimension = documents[0].embedding.len();
+        if let Some(document) = documents.iter().find(|d| d.embedding.len() != dimension) {
+            return Err(VectorStoreError::DimensionMismatch {
+                collection: name.to_string(),
+                expected: dimension,
+                actual: document.embedding.len(),
+            });
+        }
+        self.check_dimension(&collection, name, dimension).await?;
+        if collection.dimension() != Some(dimension) {
+            let path = format!("collections/{}", collection.id);
+            let body = json!({ "new_metadata": { "dimension": dimension } });
+            self.send(self.request(Method::PUT, &path)?.json(&body))
+                .await?;
+        }
+
+        let body = json!({
+            "ids": documents.iter().map(|d| d.id.as_str()).collect::<Vec<_>>(),
+            "embeddings": documents.iter().map(|d| &d.embedding).collect::<Vec<_>>(),
+            "documents": documents.iter().map(|d| d.document.as_str()).collect::<Vec<_>>(),
+            "metadatas": documents.iter().map(|d| &d.source).collect::<Vec<_>>(),
+        });
+        let path = format!("collections/{}/upsert", collection.id);
+        self.send(self.request(Method::POST, &path)?.json(&body))
+            .await?;
+        Ok(())
+    }
+
+    async fn query(
+        &self,
+        collection: &str,
+        embedding: &[f32],
+        limit: usize,
+    ) -> Result<Vec<VectorMatch>, VectorStoreError> {
+        // Nothing has been stored if the collection doesn't exist yet
+        let name = collection;
+        let Some(collection) = self.get_collection(name).await? else {
+            return Ok(Vec::new());
+        };
+        self.check_dimension(&collection, name, embedding.len())
+            .await?;
+
+        let body = json!({
+            "query_embeddings": [embedding],
+            "n_results": limit,
+            "include": ["documents", "metadatas", "distances"],
+        });
+        let path = format!("collections/{}/query", collection.id);
+        let result = self
+            .send(self.request(Method::POST, &path)?.json(&body))
+            .await?
+            .json::<QueryResult>()
+            .await?;
+
+        let ids = result.ids.into_iter().next().unwrap_or_default();
+        let documents = result
+            .documents
+            .and_then(|documents| documents.into_iter().next())
+            .unwrap_or_default();
+        let distances = result
+            .distances
+            .and_then(|distances| distances.into_iter().next())
+            .unwrap_or_default();
+        let mut metadatas = result
+            .metadatas
+            .and_then(|metadatas| metadatas.into_iter().next())
+            .unwrap_or_default()
+            .into_iter();
+        let matches = ids
+            .into_iter()
+            .zip(documents)
+            .zip(distances)
+            .filter_map(|((id, document), distance)| {
+                // Documents stored without a source have null metadata
+                let source = metadatas.next().flatten();
+                document.map(|document| VectorMatch {
+                    id,
+                    document,
+                    distance,
+                    source,
+                })
+            })
+            .collect();
+        Ok(matches)
+    }
+
+    async fn update_sources(
+        &self,
+        collection: &str,
+        sources: Vec<(String, Source)>,
+    ) -> Result<(), VectorStoreError> {
+        if sources.is_empty() {
+            return Ok(());
+        }
+        let Some(collection) = self.get_collection(collection).await? else {
+            return Ok(());
+        };
+        let (ids, metadatas): (Vec<_>, Vec<_>) = sources.into_iter().unzip();
+        let body = json!({ "ids": ids, "metadatas": metadatas });
+        let path = format!("collections/{}/update", collection.id);
+        self.send(self.request(Method::POST, &path)?.json(&body))
+            .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, collection: &str, ids: &[String]) -> Result<(), VectorStoreError> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let Some(collection) = self.get_collection(collection).await? else {
+            return Ok(());
+        };
+        let path = format!("collections/{}/delete", collection.id);
+        let body = json!({ "ids": ids });
+        self.send(self.request(Method::POST, &path)?.json(&body))
+            .await?;
+        Ok(())
+    }
+
+    async fn rename_collection(&self, from: &str, to: &str) -> Result<(), VectorStoreError> {
+        let Some(collection) = self.get_collection(from).await? else {
+            return Ok(());
+        };
+        let path = format!("collections/{}", collection.id);
+        let body = json!({ "new_name": collection_name(to) });
+        self.send(self.request(Method::PUT, &path)?.json(&body))
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_collection(&self, collection: &str) -> Result<(), VectorStoreError> {
+        let path = collection_path(&collection_name(collection));
+        match self
+            .send(self.collection_request(Method::DELETE, &path)?)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) if is_missing_collection(&e) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Heartbeat {
+    #[serde(rename = "nanosecond heartbeat")]
+    nanosecond_heartbeat: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Collection {
+    id: String,
+    metadata: Option<serde_json::Value>,
+}
+
+impl Collection {
+    /// The dimension of the embeddings stored, kept in the metadata since Chroma doesn't report it
+    fn dimension(&self) -> Option<usize> {
+        let dimension = self.metadata.as_ref()?.get("dimension")?.as_u64()?;
+        Some(dimension as usize)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryResult {
+    ids: Vec<Vec<String>>,
+    documents: Option<Vec<Vec<Option<String>>>>,
+    distances: Option<Vec<Vec<f32>>>,
+    metadatas: Option<Vec<Vec<Option<Source>>>>,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tests::prelude::*;
+
+    use super::*;
+
+    fn document(id: &str, text: &str) -> VectorDocument {
+        VectorDocument {
+            id: id.to_string(),
+            document: text.to_string(),
+            embedding: mock_embedding(text).into_iter().map(|x| x as f32).collect(),
+            source: Some(Source {
+                path: "notes.txt".to_string(),
+                start: 0,
+                end: text.len(),
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let chroma = FakeChroma::start().await;
+        let store = ChromaVectorStore::new(
+            chroma.url(),
+            Some("secret".to_string()),
+            "tenant".to_string(),
+            "database".to_string(),
+        );
+        assert_eq!(store.heartbeat().await.unwrap(), 1);
+
+        let query = document("q", "cherry blossoms").embedding;
+        assert!(store.query("chat", &query, 2).await.unwrap().is_empty());
+
+        store
+            .upsert(
+                "chat",
+                vec![
+                    document("0", "cherry blossoms are pink"),
+                    document("1", "zzzz"),
+                    document("2", "cherry blossoms"),
+                ],
+            )
+            .await
+            .unwrap();
+        let matches = store.query("chat", &query, 2).await.unwrap();
+        let ids = matches.iter().map(|m| m.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, vec!["2", "0"]);
+        assert_eq!(matches[1].document, "cherry blossoms are pink");
+        assert_eq!(matches[1].source.as_ref().unwrap().end, 24);
+
+        let moved = Source {
+            path: "notes.txt".to_string(),
+            start: 10,
+            end: 34,
+        };
+        store
+            .update_sources("chat", vec![("0".to_string(), moved.clone())])
+            .await
+            .unwrap();
+        let matches = store.query("chat", &query, 2).await.unwrap();
+        assert_eq!(matches[1].source, Some(moved));
+
+        store.delete("chat", &["2".to_string()]).await.unwrap();
+        let matches = store.query("chat", &query, 2).await.unwrap();
+        assert_eq!(matches[0].id, "0");
+
+        store.rename_collection("chat", "renamed").await.unwrap();
+        assert_eq!(chroma.collections(), vec!["renamed".to_string()]);
+        store
+            .rename_collection("missing", "elsewhere")
+            .await
+            .unwrap();
+        store.delete_collection("renamed").await.unwrap();
+        store.delete_collection("renamed").await.unwrap();
+        assert!(chroma.collections().is_empty());
+
+        for request in chroma.requests() {
+            assert_eq!(
+                request.headers.get("authorization").map(String::as_str),
+                Some("Bearer secret")
+            );
+            if request.path == "/api/v1/collections" || request.path.ends_with("/renamed") {
+                assert_eq!(request.query["tenant"], "tenant");
+                assert_eq!(request.query["database"], "database");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_unavailable() {
+        // Nothing should be listening on the discard port
+        let url = Url::parse("http://127.0.0.1:9").unwrap();
+        let store = ChromaVectorStore::new(
+            &url,
+            None,
+            DEFAULT_CHROMA_TENANT.to_string(),
+            DEFAULT_CHROMA_DATABASE.to_string(),
+        );
+        assert!(matches!(
+            store.heartbeat().await,
+            Err(VectorStoreError::Http(_))
+        ));
+    }
+
+    #[test]
+    fn test_base_url() {
+        let url = Url::parse("http://localhost:8000/").unwrap();
+        let store = ChromaVectorStore::new(&url, None, String::new(), String::new());
+        assert_eq!(
+            store.base_url.join("heartbeat").unwrap().as_str(),
+            "http://localhost:8000/api/v1/heartbeat"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_collection_names() {
+        let chroma = FakeChroma::start().await;
+        let store = ChromaVectorStore::new(
+            chroma.url(),
+            None,
+            DEFAULT_CHROMA_TENANT.to_string(),
+            DEFAULT_CHROMA_DATABASE.to_string(),
+        );
+        let query = document("q", "cherry blossoms").embedding;
+        for name in ["a", "my notes!"] {
+            store
+                .upsert(name, vec![document("0", "cherry blossoms")])
+                .await
+                .unwrap();
+            assert_eq!(store.query(name, &query, 1).await.unwrap().len(), 1);
+        }
+        store.rename_collection("a", "b").await.unwrap();
+        assert_eq!(store.query("b", &query, 1).await.unwrap().len(), 1);
+        store.delete_collection("b").await.unwrap();
+        store.delete_collection("my notes!").await.unwrap();
+        assert!(chroma.collections().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dimension_mismatch() {
+        let chroma = FakeChroma::start().await;
+        let store = ChromaVectorStore::new(
+            chroma.url(),
+            None,
+            DEFAULT_CHROMA_TENANT.to_string(),
+            DEFAULT_CHROMA_DATABASE.to_string(),
+        );
+        let query = document("q", "cherry blossoms").embedding;
+        store
+            .upsert("chat", vec![document("0", "cherry blossoms")])
+            .await
+            .unwrap();
+
+        let mismatched = VectorDocument {
+            embedding: vec![1.0, 0.0],
+            ..document("1", "cherry")
+        };
+        let error = store
+            .upsert("chat", vec![mismatched.clone()])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            VectorStoreError::DimensionMismatch { expected, actual: 2, .. } if expected == query.len()
+        ));
+        assert!(matches!(
+            store.query("chat", &[1.0, 0.0], 1).await,
+            Err(VectorStoreError::DimensionMismatch { .. })
+        ));
+        assert_eq!(store.query("chat", &query, 2).await.unwrap().len(), 1);
+
+        // Once emptied, the collection takes whatever comes next
+        store.delete("chat", &["0".to_string()]).await.unwrap();
+        store.upsert("chat", vec![mismatched]).await.unwrap();
+        let matches = store.query("chat", &[1.0, 0.0], 1).await.unwrap();
+        assert_eq!(matches[0].id, "1");
+    }
+
+    #[test]
+    fn test_collection_name() {
+        assert_eq!(collection_name("pink-cat"), "pink-cat");
+        assert_eq!(collection_name("v1.2_notes"), "v1.2_notes");
+        let renamed = [
+            "a",
+            "my notes!",
+            "my notes?",
+            "..hidden..",
+            "10.0.0.1",
+            "猫",
+            &"long".repeat(20),
+        ]
+        .map(collection_name);
+        for name in renamed.iter() {
+            assert!(is_valid_collection_name(name), "{}", name);
+        }
+        assert!(renamed[1].starts_with("my-notes-"));
+        assert_ne!(renamed[1], renamed[2]);
+        assert_eq!(collection_name("my notes!"), renamed[1]);
+    }
+
+    #[test]
+    fn test_collection_path() {
+        let url = Url::parse("http://localhost:8000/").unwrap();
+        let store = ChromaVectorStore::new(&url, None, String::new(), String::new());
+        let path = collection_path("pink cat/../?#é");
+        assert_eq!(path, "collections/pink%20cat%2F..%2F%3F%23%C3%A9");
+        assert_eq!(
+            store.base_url.join(&path).unwrap().as_str(),
+            "http://localhost:8000/api/v1/collections/pink%20cat%2F..%2F%3F%23%C3%A9"
+        );
+    }
+}
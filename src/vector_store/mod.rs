@@ -0,0 +1,190 @@
+use std::fmt::{self, Display, Formatter};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+mod chroma;
+mod sqlite;
+
+pub use chroma::{ChromaVectorStore, DEFAULT_CHROMA_DATABASE, DEFAULT_CHROMA_TENANT};
+pub use sqlite::SqliteVectorStore;
+
+/// Where a stored document came from: a file and the byte range of the text extracted from it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Source {
+    pub path: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (bytes {}-{})", self.path, self.start, self.end)
+    }
+}
+
+/// A document and its embedding, ready to be stored in a collection
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorDocument {
+    pub id: String,
+    pub document: String,
+    pub embedding: Vec<f32>,
+    pub source: Option<Source>,
+}
+
+/// A document matching a query, along with its cosine distance from the query
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorMatch {
+    pub id: String,
+    pub document: String,
+    pub distance: f32,
+    pub source: Option<Source>,
+}
+
+/// A retrieved piece of a document, keeping track of where it came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Passage {
+    pub id: String,
+    pub text: String,
+    pub source: Option<Source>,
+}
+
+impl From<VectorMatch> for Passage {
+    fn from(value: VectorMatch) -> Self {
+        Self {
+            id: value.id,
+            text: value.document,
+            source: value.source,
+        }
+    }
+}
+
+/// Somewhere to store document embeddings and search them by similarity.
+/// Documents are grouped into named collections, one per chat.
+#[async_trait]
+pub trait VectorStore: Send + Sync {
+    /// Insert documents into a collection, replacing any with the same id
+    async fn upsert(
+        &self,
+        collection: &str,
+        documents: Vec<VectorDocument>,
+    ) -> Result<(), VectorStoreError>;
+
+    /// Find the documents in a collection closest to the given embedding, nearest first.
+    /// A collection that doesn't exist yet holds no documents.
+    async fn query(
+        &self,
+        collection: &str,
+        embedding: &[f32],
+        limit: usize,
+    ) -> Result<Vec<VectorMatch>, VectorStoreError>;
+
+    /// Point documents at new sources without re-embedding them, such as when
+    /// an unchanged chunk has moved within its file
+    async fn update_sources(
+        &self,
+        collection: &str,
+        sources: Vec<(String, Source)>,
+    ) -> Result<(), VectorStoreError>;
+
+    /// Remove documents from a collection by id, ignoring any that don't exist
+    async fn delete(&self, collection: &str, ids: &[String]) -> Result<(), VectorStoreError>;
+
+    /// Give a collection a new name, doing nothing if it doesn't exist
+    async fn rename_collection(&self, from: &str, to: &str) -> Result<(), VectorStoreError>;
+
+    /// Remove a collection and everything in it
+    async fn delete_collection(&self, collection: &str) -> Result<(), VectorStoreError>;
+}
+
+/// How the collection holding a chat's documents is named.
+/// The template may refer to `{chat_id}` and `{chat_name}`; a template without
+/// either is used as a prefix for the chat's id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollectionNaming(String);
+
+impl CollectionNaming {
+    pub fn new(template: &str) -> Self {
+        Self(template.to_string())
+    }
+
+    pub fn name(&self, chat_id: Uuid, chat_name: &str) -> String {
+        if !self.0.contains("{chat_id}") && !self.0.contains("{chat_name}") {
+            return format!("{}-{}", self.0, chat_id);
+        }
+        self.0
+            .replace("{chat_id}", &chat_id.to_string())
+            .replace("{chat_name}", chat_name)
+    }
+}
+
+impl Default for CollectionNaming {
+    fn default() -> Self {
+        Self::new("{chat_name}")
+    }
+}
+
+/// The cosine distance between two vectors, from 0 (same direction) to 2 (opposite)
+pub fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
+    let (mut dot, mut norm_a, mut norm_b) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b.iter()) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 1.0;
+    }
+    1.0 - dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum VectorStoreError {
+    #[error("http error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("unexpected response {0}: {1}")]
+    Status(u16, String),
+    #[error("invalid metadata: {0}")]
+    Metadata(#[from] serde_json::Error),
+    #[error("invalid url: {0}")]
+    InvalidUrl(#[from] url::ParseError),
+    #[error("sqlx error: {0}")]
+    Sqlx(#[from] sqlx::Error),
+    #[error("collection {collection} holds embeddings of {expected} dimensions, not {actual}, so they were likely made by another embedding model. Detach its files and attach them again to embed them with the current one")]
+    DimensionMismatch {
+        collection: String,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_collection_naming() {
+        let chat_id = Uuid::nil();
+        assert_eq!(
+            CollectionNaming::default().name(chat_id, "pink-cat"),
+            "pink-cat"
+        );
+        assert_eq!(
+            CollectionNaming::new("blossom-{chat_id}").name(chat_id, "pink-cat"),
+            "blossom-00000000-0000-0000-0000-000000000000"
+        );
+        assert_eq!(
+            CollectionNaming::new("blossom-embeddings").name(chat_id, "pink-cat"),
+            "blossom-embeddings-00000000-0000-0000-0000-000000000000"
+        );
+    }
+
+    #[test]
+    fn test_cosine_distance() {
+        assert_eq!(cosine_distance(&[1.0, 0.0], &[2.0, 0.0]), 0.0);
+        assert_eq!(cosine_distance(&[1.0, 0.0], &[0.0, 3.0]), 1.0);
+        assert_eq!(cosine_distance(&[1.0, 0.0], &[-1.0, 0.0]), 2.0);
+        assert_eq!(cosine_distance(&[0.0, 0.0], &[1.0, 0.0]), 1.0);
+    }
+}
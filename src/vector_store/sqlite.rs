@@ -0,0 +1,252 @@
+use async_trait::async_trait;
+
+use super::{cosine_distance, Source, VectorDocument, VectorMatch, VectorStore, VectorStoreError};
+use crate::database::models::Embedding;
+use crate::database::Database;
+
+/// A vector store kept in the app's own SQLite database.
+/// Embeddings are stored as blobs and searched by brute force, which is plenty
+/// for the handful of documents attached to a single chat.
+#[derive(Clone)]
+pub struct SqliteVectorStore {
+    database: Database,
+}
+
+impl SqliteVectorStore {
+    pub fn new(database: Database) -> Self {
+        Self { database }
+    }
+}
+
+#[async_trait]
+impl VectorStore for SqliteVectorStore {
+    async fn upsert(
+        &self,
+        collection: &str,
+        documents: Vec<VectorDocument>,
+    ) -> Result<(), VectorStoreError> {
+        let mut conn = self.database.begin().await?;
+        let mut dimension = Embedding::dimension(collection, &mut conn).await?;
+        for document in documents {
+            let actual = document.embedding.len();
+            match dimension {
+                Some(expected) if expected != actual => {
+                    return Err(VectorStoreError::DimensionMismatch {
+                        collection: collection.to_string(),
+                        expected,
+                        actual,
+                    });
+                }
+                Some(_) => {}
+                None => {
+                    Embedding::set_dimension(collection, actual, &mut conn).await?;
+                    dimension = Some(actual);
+                }
+            }
+            let metadata = document
+                .source
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()?;
+            Embedding::upsert(
+                collection,
+                &document.id,
+                &document.document,
+                &document.embedding.into(),
+                metadata.as_deref(),
+                &mut conn,
+            )
+            .await?;
+        }
+        conn.commit().await?;
+        Ok(())
+    }
+
+    async fn query(
+        &self,
+        collection: &str,
+        embedding: &[f32],
+        limit: usize,
+    ) -> Result<Vec<VectorMatch>, VectorStoreError> {
+        let mut conn = self.database.acquire().await?;
+        match Embedding::dimension(collection, &mut conn).await? {
+            Some(expected) if expected != embedding.len() => {
+                return Err(VectorStoreError::DimensionMismatch {
+                    collection: collection.to_string(),
+                    expected,
+                    actual: embedding.len(),
+                });
+            }
+            _ => {}
+        }
+        let embeddings = Embedding::read_by_collection(collection, &mut conn).await?;
+
+        let mut matches = Vec::with_capacity(embeddings.len());
+        let mut mismatched = 0;
+        for stored in embeddings {
+            // Only embeddings stored before dimensions were kept track of can differ
+            if stored.embedding().len() != embedding.len() {
+                mismatched += 1;
+                continue;
+            }
+            let source = stored.metadata().map(serde_json::from_str).transpose()?;
+            matches.push(VectorMatch {
+                distance: cosine_distance(stored.embedding(), embedding),
+                id: stored.id().to_string(),
+                document: stored.document().to_string(),
+                source,
+            });
+        }
+        if mismatched > 0 {
+            tracing::warn!(
+                "Skipped {} embeddings in {} without {} dimensions, re-attach the documents to embed them again",
+                mismatched,
+                collection,
+                embedding.len()
+            );
+        }
+        matches.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        matches.truncate(limit);
+        Ok(matches)
+    }
+
+    async fn update_sources(
+        &self,
+        collection: &str,
+        sources: Vec<(String, Source)>,
+    ) -> Result<(), VectorStoreError> {
+        let mut conn = self.database.begin().await?;
+        for (id, source) in sources {
+            let metadata = serde_json::to_string(&source)?;
+            Embedding::update_metadata(collection, &id, Some(&metadata), &mut conn).await?;
+        }
+        conn.commit().await?;
+        Ok(())
+    }
+
+    async fn delete(&self, collection: &str, ids: &[String]) -> Result<(), VectorStoreError> {
+        let mut conn = self.database.begin().await?;
+        for id in ids {
+            Embedding::delete(collection, id, &mut conn).await?;
+        }
+        conn.commit().await?;
+        Ok(())
+    }
+
+    async fn rename_collection(&self, from: &str, to: &str) -> Result<(), VectorStoreError> {
+        let mut conn = self.database.acquire().await?;
+        Embedding::rename_collection(from, to, &mut conn).await?;
+        Ok(())
+    }
+
+    async fn delete_collection(&self, collection: &str) -> Result<(), VectorStoreError> {
+        let mut conn = self.database.acquire().await?;
+        Embedding::delete_by_collection(collection, &mut conn).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tests::prelude::*;
+
+    use super::*;
+
+    fn document(id: &str, text: &str) -> VectorDocument {
+        VectorDocument {
+            id: id.to_string(),
+            document: text.to_string(),
+            embedding: mock_embedding(text).into_iter().map(|x| x as f32).collect(),
+            source: Some(Source {
+                path: "notes.txt".to_string(),
+                start: 0,
+                end: text.len(),
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upsert_query() {
+        let store = SqliteVectorStore::new(test_database().await);
+        store
+            .upsert(
+                "chat",
+                vec![
+                    document("0", "cherry blossoms are pink"),
+                    document("1", "zzzz"),
+                    document("2", "cherry blossoms"),
+                ],
+            )
+            .await
+            .unwrap();
+
+        let query = document("q", "cherry blossoms").embedding;
+        let matches = store.query("chat", &query, 2).await.unwrap();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].id, "2");
+        assert!(matches[0].distance.abs() < 1e-6);
+        assert_eq!(matches[1].id, "0");
+        assert_eq!(matches[1].source.as_ref().unwrap().end, 24);
+
+        let moved = Source {
+            path: "notes.txt".to_string(),
+            start: 10,
+            end: 34,
+        };
+        store
+            .update_sources("chat", vec![("0".to_string(), moved.clone())])
+            .await
+            .unwrap();
+        let matches = store.query("chat", &query, 2).await.unwrap();
+        assert_eq!(matches[1].source, Some(moved));
+
+        assert!(store.query("other", &query, 2).await.unwrap().is_empty());
+        // Embeddings of another dimension are rejected rather than compared
+        let mismatched = VectorDocument {
+            embedding: vec![1.0],
+            ..document("3", "cherry")
+        };
+        let error = store
+            .upsert("chat", vec![mismatched.clone()])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            VectorStoreError::DimensionMismatch { expected, actual: 1, .. } if expected == query.len()
+        ));
+        assert_eq!(store.query("chat", &query, 5).await.unwrap().len(), 3);
+        assert!(matches!(
+            store.query("chat", &[1.0], 5).await,
+            Err(VectorStoreError::DimensionMismatch { .. })
+        ));
+
+        store.delete("chat", &["2".to_string()]).await.unwrap();
+        let matches = store.query("chat", &query, 2).await.unwrap();
+        assert_eq!(matches[0].id, "0");
+
+        store.rename_collection("chat", "renamed").await.unwrap();
+        assert!(store.query("chat", &query, 2).await.unwrap().is_empty());
+        assert_eq!(store.query("renamed", &query, 2).await.unwrap().len(), 2);
+        store.delete_collection("renamed").await.unwrap();
+        assert!(store.query("chat", &query, 2).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_emptied_collection_takes_new_dimension() {
+        let store = SqliteVectorStore::new(test_database().await);
+        store
+            .upsert("chat", vec![document("0", "cherry blossoms")])
+            .await
+            .unwrap();
+        store.delete("chat", &["0".to_string()]).await.unwrap();
+
+        let small = VectorDocument {
+            embedding: vec![1.0, 0.0],
+            ..document("1", "cherry")
+        };
+        store.upsert("chat", vec![small]).await.unwrap();
+        let matches = store.query("chat", &[1.0, 0.0], 2).await.unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].id, "1");
+    }
+}